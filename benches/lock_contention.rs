@@ -0,0 +1,84 @@
+//! Measures snapshot reads of `PipeWireObjects` while a writer keeps
+//! updating it, like the backend thread does on a busy graph.
+//!
+//! Compare the two locks with `cargo bench --bench lock_contention`
+//! and `cargo bench --bench lock_contention --features parking_lot`.
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use easy_pw::node::Node;
+use easy_pw::objects::PipeWireObjects;
+use easy_pw::sync::RwLock;
+use pipewire::permissions::PermissionFlags;
+use pipewire::properties::properties;
+use pipewire::registry::GlobalObject;
+use pipewire::types::ObjectType;
+
+const NODES: u32 = 200;
+const READERS: usize = 8;
+const SNAPSHOTS: u32 = 2000;
+
+fn node(id: u32) -> Node {
+    let props = properties! {
+        "node.name" => format!("node{id}"),
+        "object.serial" => id.to_string(),
+    };
+    Node::new(&GlobalObject {
+        id,
+        permissions: PermissionFlags::all(),
+        type_: ObjectType::Node,
+        version: 3,
+        props: Some(props.dict()),
+    })
+    .unwrap()
+}
+
+fn main() {
+    let mut objects = PipeWireObjects::default();
+    objects.extend((0..NODES).map(node));
+    let objects = Arc::new(RwLock::new(objects));
+    let done = Arc::new(AtomicBool::new(false));
+
+    let writer = {
+        let objects = objects.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut writes = 0u64;
+            while !done.load(Ordering::Relaxed) {
+                objects.write().unwrap().update_nodes();
+                writes += 1;
+            }
+            writes
+        })
+    };
+
+    let start = Instant::now();
+    let readers: Vec<_> = (0..READERS)
+        .map(|_| {
+            let objects = objects.clone();
+            thread::spawn(move || {
+                for _ in 0..SNAPSHOTS {
+                    black_box(
+                        objects.read().unwrap().take_snapshot(),
+                    );
+                }
+            })
+        })
+        .collect();
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    let elapsed = start.elapsed();
+    done.store(true, Ordering::Relaxed);
+    let writes = writer.join().unwrap();
+
+    let per_snapshot: Duration =
+        elapsed / (SNAPSHOTS * READERS as u32);
+    println!(
+        "{READERS} readers: {per_snapshot:?} per snapshot, \
+         {writes} writes in {elapsed:?}"
+    );
+}
@@ -0,0 +1,87 @@
+//! Compares finding nodes by scanning them with the indexed and the
+//! sorted lookups of `PipeWireObjects` on a 1000 node graph, for ids
+//! that exist and ids that do not, and what adding the nodes costs
+//! with either.
+//!
+//! Run with `cargo bench --bench node_lookup`.
+use std::hint::black_box;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use easy_pw::node::Node;
+use easy_pw::objects::PipeWireObjects;
+use pipewire::permissions::PermissionFlags;
+use pipewire::properties::properties;
+use pipewire::registry::GlobalObject;
+use pipewire::types::ObjectType;
+
+const NODES: u32 = 1000;
+const ROUNDS: u32 = 100;
+
+fn node(id: u32) -> Node {
+    let props = properties! {
+        "node.name" => format!("node{id}"),
+        "object.serial" => id.to_string(),
+    };
+    Node::new(&GlobalObject {
+        id,
+        permissions: PermissionFlags::all(),
+        type_: ObjectType::Node,
+        version: 3,
+        props: Some(props.dict()),
+    })
+    .unwrap()
+}
+
+fn measure(
+    name: &str,
+    ids: Range<u32>,
+    lookup: impl Fn(u32) -> Option<u32>,
+) {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for id in ids.clone() {
+            black_box(lookup(black_box(id)));
+        }
+    }
+    let elapsed: Duration = start.elapsed() / (ROUNDS * NODES);
+    println!("{name}: {elapsed:?} per lookup");
+}
+
+/// Add the nodes in an order unrelated to their ids, as they are
+/// announced.
+fn fill(sorted: bool) -> PipeWireObjects {
+    let mut objects = PipeWireObjects::default();
+    objects.keep_nodes_sorted(sorted);
+    let start = Instant::now();
+    for i in 0..NODES {
+        objects.insert_node(node((i * 7919) % NODES));
+    }
+    let elapsed = start.elapsed() / NODES;
+    let name = if sorted { "sorted" } else { "indexed" };
+    println!("{name} insertion: {elapsed:?} per node");
+    objects
+}
+
+fn main() {
+    let objects = fill(false);
+    let sorted = fill(true);
+
+    // Ids past the last node are never found
+    for (kind, ids) in [("hit", 0..NODES), ("miss", NODES..NODES * 2)]
+    {
+        measure(&format!("linear {kind}"), ids.clone(), |id| {
+            objects
+                .nodes
+                .iter()
+                .find(|node| node.id == id)
+                .map(|n| n.id)
+        });
+        measure(&format!("indexed {kind}"), ids.clone(), |id| {
+            objects.find_node_by_id(id).map(|n| n.id)
+        });
+        measure(&format!("sorted {kind}"), ids, |id| {
+            sorted.find_node_by_id(id).map(|n| n.id)
+        });
+    }
+}
@@ -0,0 +1,114 @@
+//! Callbacks run on the events of the manager, see
+//! [`crate::manager::PipeWireManager::on_event_filtered`].
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use crate::event::{ConnectorEvent, Event};
+use crate::logging::{self, Logger};
+
+type EventFilter = dyn Fn(&ConnectorEvent) -> bool + Send + Sync;
+type EventCallback = dyn Fn(&Event) + Send + Sync;
+type Subscribers = Arc<Mutex<Vec<Arc<Subscriber>>>>;
+
+thread_local! {
+    static DISPATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether this is the thread running the callbacks, which must not
+/// wait for events since it is the one delivering them.
+pub(crate) fn is_dispatching() -> bool {
+    DISPATCHING.get()
+}
+
+/// A callback along with the events it wants.
+struct Subscriber {
+    filter: Box<EventFilter>,
+    callback: Box<EventCallback>,
+}
+
+/// Runs the registered callbacks on the events sent to the manager.
+pub(crate) struct EventCallbacks {
+    subscribers: Subscribers,
+}
+
+impl EventCallbacks {
+    /// Put a forwarding thread in front of the manager, returning
+    /// the sender the backend should use instead.
+    ///
+    /// Events reach the manager first, and are only then handed to
+    /// the thread running the callbacks, started along with the
+    /// first callback, so a slow or panicking callback never holds
+    /// back the responses the manager waits for.
+    pub(crate) fn forward(
+        manager: mpsc::Sender<Event>,
+        logger: Option<Logger>,
+    ) -> (mpsc::Sender<Event>, Self) {
+        let (sender, receiver) = mpsc::channel::<Event>();
+        let subscribers = Subscribers::default();
+        let dispatched = subscribers.clone();
+        thread::spawn(move || {
+            let mut dispatcher = None;
+            for event in receiver {
+                let subscribed =
+                    !dispatched.lock().unwrap().is_empty();
+                let copy = subscribed.then(|| event.clone());
+                let _result = manager.send(event);
+                if let Some(event) = copy {
+                    let _result = dispatcher
+                        .get_or_insert_with(|| {
+                            Self::dispatch(
+                                dispatched.clone(),
+                                logger.clone(),
+                            )
+                        })
+                        .send(event);
+                }
+            }
+        });
+        (sender, Self { subscribers })
+    }
+
+    /// Start the thread running the callbacks on the events sent
+    /// through the returned sender.
+    fn dispatch(
+        subscribers: Subscribers,
+        logger: Option<Logger>,
+    ) -> mpsc::Sender<Event> {
+        let (sender, receiver) = mpsc::channel::<Event>();
+        thread::spawn(move || {
+            let _logger = logging::scope(logger);
+            DISPATCHING.set(true);
+            for event in receiver {
+                // Not holding the lock lets callbacks register others
+                let subscribers = subscribers.lock().unwrap().clone();
+                for subscriber in subscribers {
+                    let result =
+                        panic::catch_unwind(AssertUnwindSafe(|| {
+                            if (subscriber.filter)(&event.kind) {
+                                (subscriber.callback)(&event);
+                            }
+                        }));
+                    if result.is_err() {
+                        log_error!(
+                            "An event callback panicked on {event:?}"
+                        );
+                    }
+                }
+            }
+        });
+        sender
+    }
+
+    pub(crate) fn subscribe(
+        &self,
+        filter: impl Fn(&ConnectorEvent) -> bool + Send + Sync + 'static,
+        callback: impl Fn(&Event) + Send + Sync + 'static,
+    ) {
+        self.subscribers.lock().unwrap().push(Arc::new(Subscriber {
+            filter: Box::new(filter),
+            callback: Box::new(callback),
+        }));
+    }
+}
@@ -0,0 +1,146 @@
+//! Incremental updates of the graph, for consumers that keep their
+//! own copy of it in sync without taking full snapshots.
+use std::collections::VecDeque;
+
+use thiserror::Error;
+
+/// How many changes are kept by default.
+pub const DEFAULT_CHANGE_LOG_CAPACITY: usize = 256;
+
+/// A node or link that appeared or disappeared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GraphChange {
+    NodeAdded(u32),
+    NodeRemoved(u32),
+    LinkAdded(u32),
+    LinkRemoved(u32),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum DiffError {
+    /// Some of the changes were already dropped, or the graph was
+    /// cleared since then. A full snapshot is needed to sync again.
+    #[error("The changes since that version are not kept anymore")]
+    TooOld,
+    #[error("Version {0} is ahead of the graph")]
+    UnknownVersion(u64),
+}
+
+/// The latest changes of the graph, along with its version: the
+/// amount of changes since the objects were created.
+#[derive(Debug)]
+pub(crate) struct ChangeLog {
+    version: u64,
+    capacity: usize,
+    changes: VecDeque<GraphChange>,
+}
+
+impl Default for ChangeLog {
+    fn default() -> Self {
+        Self {
+            version: 0,
+            capacity: DEFAULT_CHANGE_LOG_CAPACITY,
+            changes: VecDeque::new(),
+        }
+    }
+}
+
+impl ChangeLog {
+    pub(crate) fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Keep at most `capacity` changes, dropping the oldest ones.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.truncate();
+    }
+
+    pub(crate) fn record(&mut self, change: GraphChange) {
+        self.version += 1;
+        self.changes.push_back(change);
+        self.truncate();
+    }
+
+    /// Forget every change, making every previous version too old,
+    /// as when the graph is cleared all at once.
+    pub(crate) fn reset(&mut self) {
+        self.version += 1;
+        self.changes.clear();
+    }
+
+    /// The changes made after `version`, oldest first.
+    pub(crate) fn since(
+        &self,
+        version: u64,
+    ) -> Result<Vec<GraphChange>, DiffError> {
+        let missed = self
+            .version
+            .checked_sub(version)
+            .ok_or(DiffError::UnknownVersion(version))?;
+        let kept = self.changes.len() as u64;
+        if missed > kept {
+            return Err(DiffError::TooOld);
+        }
+        Ok(self
+            .changes
+            .iter()
+            .skip((kept - missed) as usize)
+            .copied()
+            .collect())
+    }
+
+    fn truncate(&mut self) {
+        while self.changes.len() > self.capacity {
+            self.changes.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChangeLog, DiffError, GraphChange};
+
+    #[test]
+    fn changes_since_a_version_are_incremental() {
+        let mut log = ChangeLog::default();
+        log.record(GraphChange::NodeAdded(1));
+        let synced = log.version();
+        log.record(GraphChange::NodeAdded(2));
+        log.record(GraphChange::LinkAdded(30));
+
+        assert_eq!(
+            log.since(synced),
+            Ok(vec![
+                GraphChange::NodeAdded(2),
+                GraphChange::LinkAdded(30)
+            ])
+        );
+        assert_eq!(log.since(log.version()), Ok(vec![]));
+        assert_eq!(
+            log.since(log.version() + 1),
+            Err(DiffError::UnknownVersion(4))
+        );
+    }
+
+    #[test]
+    fn dropped_changes_need_a_full_resync() {
+        let mut log = ChangeLog::default();
+        log.set_capacity(2);
+        for id in 1..=3 {
+            log.record(GraphChange::NodeAdded(id));
+        }
+        assert_eq!(log.since(0), Err(DiffError::TooOld));
+        assert_eq!(
+            log.since(1),
+            Ok(vec![
+                GraphChange::NodeAdded(2),
+                GraphChange::NodeAdded(3)
+            ])
+        );
+
+        let synced = log.version();
+        log.reset();
+        assert_eq!(log.since(synced), Err(DiffError::TooOld));
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::event::PipeWireEvent;
+use crate::manager::PipeWireManager;
+use crate::node::{Node, NodeKind};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ControlError {
+    #[error("Node {0} was not found")]
+    NodeNotFound(u32),
+    #[error("No node has the serial {0}")]
+    SerialNotFound(u64),
+    #[error("Failed to set metadata {1} on node {0}")]
+    MetadataFailed(u32, String),
+    #[error("Failed to set the params of node {0}")]
+    ParamsFailed(u32),
+    #[error("{0} is not a valid volume")]
+    InvalidVolume(f32),
+    #[error("Failed to monitor the levels of node {0}")]
+    MonitorFailed(u32),
+    #[error(
+        "{0} is not a valid quantum, it must be a power of two between {MIN_QUANTUM} and {MAX_QUANTUM}"
+    )]
+    InvalidQuantum(u32),
+    #[error(
+        "Blocking calls cannot be made from event callbacks or the backend thread"
+    )]
+    ReentrantCall,
+}
+
+/// Highest linear volume accepted, as PipeWire does not amplify
+/// further.
+pub const MAX_VOLUME: f32 = 10.0;
+
+/// Reject volumes that are not numbers or negative, and clamp the
+/// others to [`MAX_VOLUME`].
+pub(crate) fn validate_volume(
+    volume: f32,
+) -> Result<f32, ControlError> {
+    if !volume.is_finite() || volume < 0.0 {
+        return Err(ControlError::InvalidVolume(volume));
+    }
+    Ok(volume.min(MAX_VOLUME))
+}
+
+/// Smallest quantum of the graph that can be forced, in samples.
+pub const MIN_QUANTUM: u32 = 16;
+/// Largest quantum of the graph that can be forced, in samples.
+pub const MAX_QUANTUM: u32 = 8192;
+
+/// Reject quantums PipeWire would not run the graph with: they must
+/// be powers of two within [`MIN_QUANTUM`] and [`MAX_QUANTUM`].
+pub(crate) fn validate_quantum(
+    quantum: u32,
+) -> Result<(), ControlError> {
+    if !quantum.is_power_of_two()
+        || !(MIN_QUANTUM..=MAX_QUANTUM).contains(&quantum)
+    {
+        return Err(ControlError::InvalidQuantum(quantum));
+    }
+    Ok(())
+}
+
+/// A node checked to exist once, to control it repeatedly (e.g.
+/// while dragging a fader) without looking it up every time.
+///
+/// The backend keeps every node bound for as long as it exists, so
+/// controls go straight to its proxy and dropping the handle costs
+/// nothing. Controlling a node removed in the meantime fails with
+/// [`ControlError::ParamsFailed`].
+pub struct NodeHandle<'a> {
+    pub(crate) manager: &'a PipeWireManager,
+    pub(crate) node_id: u32,
+}
+
+impl NodeHandle<'_> {
+    pub fn node_id(&self) -> u32 {
+        self.node_id
+    }
+
+    /// Set the linear volume of every channel, 1.0 being 100%.
+    pub fn set_volume(
+        &self,
+        volume: f32,
+    ) -> Result<(), ControlError> {
+        self.manager.send_node_control(
+            PipeWireEvent::SetVolumeCommand(
+                self.node_id,
+                validate_volume(volume)?,
+            ),
+        )
+    }
+
+    pub fn set_mute(&self, mute: bool) -> Result<(), ControlError> {
+        self.manager
+            .send_node_control(PipeWireEvent::SetMuteCommand(
+                self.node_id,
+                mute,
+            ))
+    }
+
+    /// Override the `node.description` shown by mixers.
+    pub fn set_description(
+        &self,
+        description: &str,
+    ) -> Result<(), ControlError> {
+        self.manager.send_node_control(
+            PipeWireEvent::SetDescriptionCommand(
+                self.node_id,
+                description.to_owned(),
+            ),
+        )
+    }
+}
+
+/// The mute states of the outputs saved while every output is muted.
+#[derive(Debug, Default)]
+pub(crate) struct GlobalMute {
+    saved: Option<HashMap<u32, bool>>,
+}
+
+impl GlobalMute {
+    pub(crate) fn is_active(&self) -> bool {
+        self.saved.is_some()
+    }
+
+    /// Save the mute state of every sink, returning the sinks to mute.
+    /// Muting again keeps the states saved by the first call, so
+    /// that restoring still returns to the states from before.
+    pub(crate) fn mute(&mut self, nodes: &[Node]) -> Vec<u32> {
+        let saved = self.saved.get_or_insert_with(HashMap::new);
+        nodes
+            .iter()
+            .filter(|node| node.kind() == NodeKind::Sink)
+            .map(|node| {
+                saved
+                    .entry(node.id)
+                    .or_insert(node.mute.unwrap_or(false));
+                node.id
+            })
+            .collect()
+    }
+
+    /// End the global mute, returning the saved state of every sink
+    /// that still exists.
+    pub(crate) fn restore(
+        &mut self,
+        nodes: &[Node],
+    ) -> Vec<(u32, bool)> {
+        let Some(saved) = self.saved.take() else {
+            return vec![];
+        };
+        let mut states: Vec<(u32, bool)> = saved
+            .into_iter()
+            .filter(|(id, _)| nodes.iter().any(|node| node.id == *id))
+            .collect();
+        states.sort_unstable();
+        states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        validate_quantum, validate_volume, ControlError, GlobalMute,
+        MAX_VOLUME,
+    };
+    use crate::node::Node;
+    use crate::test_utils::stereo_node;
+
+    fn sink(id: u32, mute: Option<bool>) -> Node {
+        let mut sink = stereo_node(id, "sink", id * 10);
+        sink.media_class = Some("Audio/Sink".to_owned());
+        sink.mute = mute;
+        sink
+    }
+
+    #[test]
+    fn restore_returns_to_individual_states() {
+        let mut nodes = vec![
+            sink(1, Some(true)),
+            sink(2, Some(false)),
+            sink(3, None),
+            stereo_node(4, "stream", 40),
+        ];
+        let mut global_mute = GlobalMute::default();
+
+        assert_eq!(global_mute.mute(&nodes), vec![1, 2, 3]);
+        assert!(global_mute.is_active());
+        for node in nodes.iter_mut().take(3) {
+            node.mute = Some(true);
+        }
+        // Muting twice must not save the muted states
+        assert_eq!(global_mute.mute(&nodes), vec![1, 2, 3]);
+
+        nodes.remove(2);
+        assert_eq!(
+            global_mute.restore(&nodes),
+            vec![(1, true), (2, false)]
+        );
+        assert!(!global_mute.is_active());
+        assert!(global_mute.restore(&nodes).is_empty());
+    }
+
+    #[test]
+    fn volumes_are_validated_and_clamped() {
+        assert_eq!(validate_volume(0.5), Ok(0.5));
+        assert_eq!(validate_volume(0.0), Ok(0.0));
+        assert_eq!(validate_volume(25.0), Ok(MAX_VOLUME));
+        assert_eq!(
+            validate_volume(-0.1),
+            Err(ControlError::InvalidVolume(-0.1))
+        );
+        assert!(validate_volume(f32::NAN).is_err());
+    }
+
+    #[test]
+    fn quantums_are_powers_of_two_within_bounds() {
+        for quantum in [16, 128, 1024, 8192] {
+            assert_eq!(validate_quantum(quantum), Ok(()));
+        }
+        for quantum in [0, 8, 100, 16384] {
+            assert_eq!(
+                validate_quantum(quantum),
+                Err(ControlError::InvalidQuantum(quantum))
+            );
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::{self, Write};
+
+use crate::node::NodeKind;
+use crate::objects::PipeWireObjects;
+use crate::port::PortDirection;
+
+impl PipeWireObjects {
+    /// Describe the graph in the Graphviz DOT language.
+    /// Every node is drawn as a cluster holding its ports, colored by
+    /// its [`NodeKind`], and every link as an edge between two ports.
+    ///
+    /// The output can be rendered with `dot -Tpng`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = vec![];
+        // Writing into a Vec cannot fail
+        let _ = self.write_dot(&mut dot);
+        String::from_utf8_lossy(&dot).into_owned()
+    }
+
+    /// Same as [`Self::to_dot`], writing the graph to `dot` as it
+    /// goes instead of building it in memory first.
+    pub fn write_dot<W: Write>(&self, dot: &mut W) -> io::Result<()> {
+        writeln!(dot, "digraph pipewire {{")?;
+        writeln!(dot, "    rankdir=LR;")?;
+        writeln!(dot, "    node [style=filled, fillcolor=white];")?;
+
+        for node in self.nodes.iter() {
+            writeln!(
+                dot,
+                "    subgraph cluster_node_{} {{",
+                node.id
+            )?;
+            writeln!(
+                dot,
+                "        label=\"{} ({})\";",
+                escape(&node.name),
+                node.id
+            )?;
+            writeln!(
+                dot,
+                "        style=filled; fillcolor={};",
+                kind_color(node.kind())
+            )?;
+            if node.ports().is_empty() {
+                // Graphviz does not draw empty clusters
+                writeln!(
+                    dot,
+                    "        node_{} [label=\"\", shape=point, style=invis];",
+                    node.id
+                )?;
+            }
+            for port in node.ports().iter() {
+                let shape = match port.direction {
+                    PortDirection::In => "ellipse",
+                    PortDirection::Out => "box",
+                };
+                writeln!(
+                    dot,
+                    "        port_{} [label=\"{}\", shape={shape}];",
+                    port.id,
+                    escape(&port.name)
+                )?;
+            }
+            writeln!(dot, "    }}")?;
+        }
+
+        for link in self.links.iter() {
+            writeln!(
+                dot,
+                "    port_{} -> port_{} [tooltip=\"link {}\"];",
+                link.output_port, link.input_port, link.id
+            )?;
+        }
+        writeln!(dot, "}}")
+    }
+}
+
+fn kind_color(kind: NodeKind) -> &'static str {
+    match kind {
+        NodeKind::Sink => "lightblue",
+        NodeKind::Source => "palegreen",
+        NodeKind::Duplex => "plum",
+        NodeKind::StreamOutput | NodeKind::StreamInput => {
+            "lightyellow"
+        }
+        NodeKind::Midi => "lightsalmon",
+        NodeKind::VideoSource | NodeKind::VideoSink => "lightpink",
+        NodeKind::Other => "lightgrey",
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::link::Link;
+    use crate::node::Node;
+    use crate::objects::PipeWireObjects;
+    use crate::test_utils::{stereo_node, with_nodes};
+
+    #[test]
+    fn dot_contains_every_node_and_link() {
+        let objects = PipeWireObjects {
+            links: vec![
+                Link::from_parts(30, (1, 10), (2, 21)),
+                Link::from_parts(31, (1, 12), (2, 23)),
+            ],
+            ..with_nodes([
+                stereo_node(1, "source", 10),
+                stereo_node(2, "sink \"main\"", 20),
+                Node::from_parts(3, "portless"),
+            ])
+        };
+        let dot = objects.to_dot();
+
+        assert!(dot.starts_with("digraph pipewire {"));
+        assert!(dot.contains("subgraph cluster_node_1 {"));
+        assert!(dot.contains("subgraph cluster_node_2 {"));
+        assert!(dot.contains("subgraph cluster_node_3 {"));
+        assert!(dot.contains("label=\"sink \\\"main\\\" (2)\";"));
+        assert!(dot
+            .contains("port_12 [label=\"monitor_FR\", shape=box];"));
+        assert!(dot.contains("port_10 -> port_21"));
+        assert!(dot.contains("port_12 -> port_23"));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+}
@@ -1,29 +1,209 @@
 use std::{
+    collections::HashMap,
     fmt::Display,
     rc::Rc,
-    sync::{mpsc, Arc, RwLock},
+    sync::{mpsc, Arc, Mutex},
+    thread,
 };
 
 use futures::executor::block_on;
-use pipewire::{core::Core, registry::Registry};
+use pipewire::{
+    core::Core, node::Node as NodeProxy, properties::Properties,
+    registry::Registry,
+};
 
+use super::link::{Link, LinkOptions, LinkState};
+use super::meter::{BoundMeter, LevelCallback};
+use super::node::{BoundNode, LinkStrategy, NodeState};
 use super::objects::PipeWireObjects;
+use super::port::{Port, PortError};
+use super::proxies::BoundProxies;
+use super::retry::RetryPolicy;
+use super::sync::RwLock;
 
 /// Events that is received by the main thread.
 #[derive(Debug, PartialEq, Clone)]
 pub enum ConnectorEvent {
     None,
+    /// The nodes were linked. Links created by the manager are
+    /// reported as soon as they are created, the others once the
+    /// server announces them.
     LinkUpdate(u32, u32),
     LinkFailed(u32, u32),
+    /// Answer to a [`PipeWireEvent::LinkPortsCommand`], by output
+    /// and input port id. Sent after the
+    /// [`ConnectorEvent::LinkUpdate`] of their nodes.
+    PortsLinked(u32, u32),
+    /// Sent after the [`ConnectorEvent::LinkFailed`] of the nodes.
+    PortsLinkFailed(u32, u32),
+    /// The server announced a link the manager created, by link id,
+    /// which is not pending anymore.
+    LinkAnnounced(u32),
     UnlinkUpdate(u32, u32),
     UnLinkFailed(u32, u32),
+    /// An unlink was requested but the nodes were not linked.
+    NothingToUnlink(u32, u32),
+    /// A property of the default metadata object changed.
+    /// Subject and key.
+    MetadataUpdate(u32, String),
+    MetadataFailed(u32, String),
+    /// Link id and its new state.
+    LinkStateChanged(u32, LinkState),
+    /// A link is gone, by id. Followed by the
+    /// [`ConnectorEvent::UnlinkUpdate`] of the nodes it linked.
+    LinkRemoved(u32),
+    /// The params of the node were sent to the server.
+    ControlUpdate(u32),
+    ControlFailed(u32),
+    /// The volume of a node changed, see [`crate::node::Node::volume`].
+    VolumeChanged {
+        node_id: u32,
+        volume: f32,
+    },
+    MuteChanged {
+        node_id: u32,
+        muted: bool,
+    },
+    /// A node started or stopped processing audio.
+    NodeStateChanged {
+        node_id: u32,
+        state: NodeState,
+    },
+    /// Answer to a [`PipeWireEvent::RemoveLinksCommand`].
+    LinksRemoved {
+        removed: usize,
+        failed: usize,
+    },
+    /// A level monitor was connected to its node, by monitor id.
+    MonitorStarted(u32),
+    MonitorFailed(u32),
+    /// A virtual node was created, by name. It is only known once
+    /// PipeWire announces it.
+    NodeCreated(String),
+    NodeCreationFailed(String),
+    /// Every port a node announced was discovered, by node id. Sent
+    /// once per node, when its last expected port is attached.
+    NodePortsComplete(u32),
+    /// Every object that existed when connecting was announced.
+    /// Sent once per connection, so again after reconnecting.
+    Ready,
+    /// Something failed on the backend thread, with the message it
+    /// logged. Commands still get their own failure response.
+    Error(String),
+    /// A bounded event stream fell behind and this many events were
+    /// dropped from it. Only sent to the bounded event streams.
+    #[cfg(feature = "async")]
+    Lagged(u64),
+}
+
+/// An event along with its position among every event sent by the
+/// backend, counting from 0, so that consumers can order events and
+/// notice the ones they missed. Callbacks, event streams and
+/// [`crate::manager::PipeWireManager::drain_events`] all see the same
+/// number for the same event.
+///
+/// A [`ConnectorEvent::Lagged`] event has the sequence number of the
+/// first event dropped, and the next event the one right after the
+/// last dropped.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Event {
+    pub seq: u64,
+    pub kind: ConnectorEvent,
+}
+
+/// Numbers the events sent through it, in the order they are sent.
+#[derive(Clone)]
+pub(crate) struct SequencedSender {
+    sender: mpsc::Sender<Event>,
+    next_seq: Arc<Mutex<u64>>,
+}
+
+impl SequencedSender {
+    pub(crate) fn new(sender: mpsc::Sender<Event>) -> Self {
+        Self {
+            sender,
+            next_seq: Arc::default(),
+        }
+    }
+
+    pub(crate) fn send(
+        &self,
+        kind: ConnectorEvent,
+    ) -> Result<(), mpsc::SendError<ConnectorEvent>> {
+        // Held while sending so that numbers follow the send order
+        let mut next_seq = self.next_seq.lock().unwrap();
+        let event = Event {
+            seq: *next_seq,
+            kind,
+        };
+        self.sender
+            .send(event)
+            .map_err(|e| mpsc::SendError(e.0.kind))?;
+        *next_seq += 1;
+        Ok(())
+    }
+}
+
+/// Number every event the backend sends before handing it to
+/// `manager`, returning the sender the backend should use. Events
+/// get their number here, once, whichever way they are delivered.
+pub(crate) fn sequence(
+    manager: mpsc::Sender<Event>,
+) -> mpsc::Sender<ConnectorEvent> {
+    let (sender, receiver) = mpsc::channel::<ConnectorEvent>();
+    let manager = SequencedSender::new(manager);
+    thread::spawn(move || {
+        for event in receiver {
+            if manager.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    sender
+}
+
+/// Log a failure of the backend thread and report it to the manager
+/// as a [`ConnectorEvent::Error`].
+pub(crate) fn report_error(
+    sender: &mpsc::Sender<ConnectorEvent>,
+    message: String,
+) {
+    log_error!("{message}");
+    let _result = sender.send(ConnectorEvent::Error(message));
 }
 
 /// Events that is received by the PipeWire Backend thread.
 #[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum PipeWireEvent {
-    LinkCommand(u32, u32),
+    LinkCommand(u32, u32, LinkStrategy),
     UnlinkCommand(u32, u32),
+    /// Output node, output port, input node, input port and how the
+    /// link behaves.
+    LinkPortsCommand(u32, u32, u32, u32, LinkOptions),
+    /// Subject, key, type and value.
+    SetMetadataCommand(u32, String, Option<String>, String),
+    /// Key and value of a property of the `settings` metadata, on
+    /// the core subject.
+    SetSettingCommand(String, String),
+    SetMuteCommand(u32, bool),
+    SetVolumeCommand(u32, f32),
+    /// Node id and its new `node.description`.
+    SetDescriptionCommand(u32, String),
+    /// Ids of the links to remove.
+    RemoveLinksCommand(Vec<u32>),
+    /// Monitor id, node id and the callback receiving the levels.
+    MonitorLevelsCommand(u32, u32, LevelCallback),
+    /// Monitor id.
+    StopMonitorLevelsCommand(u32),
+    /// Name and properties of the node to create.
+    CreateNodeCommand(String, HashMap<String, String>),
+    /// Stop the backend thread, see
+    /// [`crate::manager::PipeWireManager::reinit`].
+    ShutdownCommand,
+    /// Link and unlink commands handled one after the other, with no
+    /// registry event in between.
+    TransactionCommand(Vec<PipeWireEvent>),
 }
 
 impl Display for PipeWireEvent {
@@ -32,19 +212,113 @@ impl Display for PipeWireEvent {
         f: &mut std::fmt::Formatter<'_>,
     ) -> std::fmt::Result {
         match self {
-            PipeWireEvent::LinkCommand(source_id, target_id) => {
-                write!(f, "LinkCommand({source_id}, {target_id})")
+            PipeWireEvent::LinkCommand(
+                source_id,
+                target_id,
+                strategy,
+            ) => {
+                write!(
+                    f,
+                    "LinkCommand({source_id}, {target_id}, {strategy:?})"
+                )
             }
             PipeWireEvent::UnlinkCommand(source_id, target_id) => {
                 write!(f, "UnlinkCommand({source_id}, {target_id})")
             }
+            PipeWireEvent::LinkPortsCommand(
+                source_id,
+                source_port,
+                target_id,
+                target_port,
+                options,
+            ) => {
+                write!(f, "LinkPortsCommand({source_id}:{source_port}, {target_id}:{target_port}, {options:?})")
+            }
+            PipeWireEvent::SetMetadataCommand(
+                subject,
+                key,
+                _,
+                value,
+            ) => {
+                write!(
+                    f,
+                    "SetMetadataCommand({subject}, {key}, {value})"
+                )
+            }
+            PipeWireEvent::SetSettingCommand(key, value) => {
+                write!(f, "SetSettingCommand({key}, {value})")
+            }
+            PipeWireEvent::SetMuteCommand(node_id, mute) => {
+                write!(f, "SetMuteCommand({node_id}, {mute})")
+            }
+            PipeWireEvent::SetVolumeCommand(node_id, volume) => {
+                write!(f, "SetVolumeCommand({node_id}, {volume})")
+            }
+            PipeWireEvent::SetDescriptionCommand(
+                node_id,
+                description,
+            ) => {
+                write!(
+                    f,
+                    "SetDescriptionCommand({node_id}, {description})"
+                )
+            }
+            PipeWireEvent::RemoveLinksCommand(link_ids) => {
+                write!(f, "RemoveLinksCommand({link_ids:?})")
+            }
+            PipeWireEvent::MonitorLevelsCommand(
+                monitor_id,
+                node_id,
+                _,
+            ) => {
+                write!(
+                    f,
+                    "MonitorLevelsCommand({monitor_id}, {node_id})"
+                )
+            }
+            PipeWireEvent::StopMonitorLevelsCommand(monitor_id) => {
+                write!(f, "StopMonitorLevelsCommand({monitor_id})")
+            }
+            PipeWireEvent::CreateNodeCommand(name, _) => {
+                write!(f, "CreateNodeCommand({name})")
+            }
+            PipeWireEvent::ShutdownCommand => {
+                write!(f, "ShutdownCommand")
+            }
+            PipeWireEvent::TransactionCommand(events) => {
+                write!(f, "TransactionCommand(")?;
+                for (index, event) in events.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{event}")?;
+                }
+                write!(f, ")")
+            }
         }
     }
 }
 
+/// Create links through `core`, as the link commands do.
+pub(crate) fn link_through(
+    core: &Rc<RwLock<Core>>,
+    retry_policy: RetryPolicy,
+) -> impl Fn(&Port, &Port) -> Result<(), PortError> {
+    let core = core.clone();
+    move |port, other_port| {
+        port.link_port_with_retry(
+            core.clone(),
+            other_port,
+            &retry_policy,
+        )
+    }
+}
+
 impl PipeWireEvent {
     #[allow(unreachable_patterns)]
-    /// Handle the event and return a ConnectorEvent response if needed.
+    #[allow(clippy::too_many_arguments)]
+    /// Handle the event and return a ConnectorEvent response if needed,
+    /// as an error when the command failed.
     pub fn handle(
         &self,
         _event_locker: Arc<RwLock<()>>,
@@ -52,52 +326,332 @@ impl PipeWireEvent {
         core: Rc<RwLock<Core>>,
         sender: Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
         registry: Rc<RwLock<Registry>>,
-    ) -> Result<(), ConnectorEvent> {
+        proxies: Rc<RwLock<BoundProxies>>,
+        retry_policy: &RetryPolicy,
+    ) -> Result<Option<ConnectorEvent>, ConnectorEvent> {
         let event_locker = _event_locker.write().unwrap();
-        log::debug!("(Pipewire) Handling Event: {self:#?}");
+        log_debug!("(Pipewire) Handling Event: {self:#?}");
         match self {
-            PipeWireEvent::LinkCommand(source_id, target_id) => {
-                let result = &PipeWireEvent::_link_command(
-                    objects, core, *source_id, *target_id,
-                );
-                if let Err(e) = result {
-                    log::error!("Failed to link nodes: {e}");
-                    return Err(ConnectorEvent::LinkFailed(
-                        *source_id, *target_id,
-                    ));
-                }
+            PipeWireEvent::LinkCommand(
+                source_id,
+                target_id,
+                strategy,
+            ) => {
+                return PipeWireEvent::_link_nodes_command(
+                    objects,
+                    &sender,
+                    *source_id,
+                    *target_id,
+                    *strategy,
+                    link_through(&core, *retry_policy),
+                )
+                .map(Some);
             }
             PipeWireEvent::UnlinkCommand(source_id, target_id) => {
-                log::info!(
+                log_info!(
                     "Unlinking nodes {source_id} and {target_id}"
                 );
-                let result = &PipeWireEvent::_unlink_command(
+                return PipeWireEvent::_unlink_nodes_command(
                     objects,
-                    registry,
+                    Some(registry),
+                    &sender,
                     *source_id,
                     *target_id,
-                    sender.clone(),
                 );
+            }
+            PipeWireEvent::LinkPortsCommand(
+                source_id,
+                source_port,
+                target_id,
+                target_port,
+                options,
+            ) => {
+                let result = &PipeWireEvent::_link_ports_command(
+                    objects.clone(),
+                    core,
+                    (*source_port, *target_port),
+                    options,
+                    retry_policy,
+                );
+                let sender = sender.read().unwrap();
                 if let Err(e) = result {
-                    log::error!("Failed to link nodes: {e}");
-                    return Err(ConnectorEvent::UnLinkFailed(
+                    report_error(
+                        &sender,
+                        format!("Failed to link ports: {e}"),
+                    );
+                    let _result =
+                        sender.send(ConnectorEvent::LinkFailed(
+                            *source_id, *target_id,
+                        ));
+                    return Err(ConnectorEvent::PortsLinkFailed(
+                        *source_port,
+                        *target_port,
+                    ));
+                }
+                let mut objects = objects.write().unwrap();
+                objects.insert_pending_link(Link::pending(
+                    (*source_id, *source_port),
+                    (*target_id, *target_port),
+                ));
+                objects.record_port_route(*source_port, *target_port);
+                let _result =
+                    sender.send(ConnectorEvent::LinkUpdate(
                         *source_id, *target_id,
                     ));
+                return Ok(Some(ConnectorEvent::PortsLinked(
+                    *source_port,
+                    *target_port,
+                )));
+            }
+            PipeWireEvent::SetMetadataCommand(
+                subject,
+                key,
+                type_,
+                value,
+            ) => {
+                let proxies = proxies.read().unwrap();
+                match proxies.metadata.as_ref() {
+                    Some(bound) => bound.metadata.set_property(
+                        *subject,
+                        key,
+                        type_.as_deref(),
+                        Some(value),
+                    ),
+                    None => {
+                        report_error(
+                            &sender.read().unwrap(),
+                            format!("Failed to set metadata {key}: no default metadata object"),
+                        );
+                        return Err(ConnectorEvent::MetadataFailed(
+                            *subject,
+                            key.clone(),
+                        ));
+                    }
+                }
+            }
+            PipeWireEvent::SetSettingCommand(key, value) => {
+                let proxies = proxies.read().unwrap();
+                match proxies.settings.as_ref() {
+                    Some(bound) => bound.metadata.set_property(
+                        0,
+                        key,
+                        None,
+                        Some(value),
+                    ),
+                    None => {
+                        report_error(
+                            &sender.read().unwrap(),
+                            format!("Failed to set setting {key}: no settings metadata object"),
+                        );
+                        return Err(ConnectorEvent::MetadataFailed(
+                            0,
+                            key.clone(),
+                        ));
+                    }
+                }
+            }
+            PipeWireEvent::SetMuteCommand(node_id, mute) => {
+                return PipeWireEvent::_node_control(
+                    proxies,
+                    &sender,
+                    *node_id,
+                    |bound| bound.set_mute(*mute),
+                )
+                .map(Some);
+            }
+            PipeWireEvent::SetVolumeCommand(node_id, volume) => {
+                return PipeWireEvent::_node_control(
+                    proxies,
+                    &sender,
+                    *node_id,
+                    |bound| bound.set_volume(*volume),
+                )
+                .map(Some);
+            }
+            PipeWireEvent::SetDescriptionCommand(
+                node_id,
+                description,
+            ) => {
+                return PipeWireEvent::_node_control(
+                    proxies,
+                    &sender,
+                    *node_id,
+                    |bound| bound.set_description(description),
+                )
+                .map(Some);
+            }
+            PipeWireEvent::RemoveLinksCommand(link_ids) => {
+                let (removed, failed) =
+                    PipeWireEvent::_remove_links_command(
+                        objects,
+                        Some(registry),
+                        link_ids,
+                        sender,
+                    );
+                return Ok(Some(ConnectorEvent::LinksRemoved {
+                    removed,
+                    failed,
+                }));
+            }
+            PipeWireEvent::MonitorLevelsCommand(
+                monitor_id,
+                node_id,
+                callback,
+            ) => {
+                let result = match objects
+                    .read()
+                    .unwrap()
+                    .find_node_by_exact_id(*node_id)
+                {
+                    Some(node) => BoundMeter::connect(
+                        &core.read().unwrap(),
+                        node,
+                        callback.clone(),
+                    ),
+                    None => {
+                        Err(format!("Node {node_id} was not found"))
+                    }
+                };
+                return match result {
+                    Ok(meter) => {
+                        proxies
+                            .write()
+                            .unwrap()
+                            .meters
+                            .insert(*monitor_id, meter);
+                        Ok(Some(ConnectorEvent::MonitorStarted(
+                            *monitor_id,
+                        )))
+                    }
+                    Err(e) => {
+                        report_error(
+                            &sender.read().unwrap(),
+                            format!("Failed to monitor the levels of node {node_id}: {e}"),
+                        );
+                        Err(ConnectorEvent::MonitorFailed(
+                            *monitor_id,
+                        ))
+                    }
+                };
+            }
+            PipeWireEvent::StopMonitorLevelsCommand(monitor_id) => {
+                proxies.write().unwrap().meters.remove(monitor_id);
+            }
+            PipeWireEvent::CreateNodeCommand(
+                name,
+                node_properties,
+            ) => {
+                let mut properties = Properties::new();
+                for (key, value) in node_properties {
+                    properties.insert(key.as_str(), value.as_str());
                 }
+                let result =
+                    core.read().unwrap().create_object::<NodeProxy>(
+                        "adapter",
+                        &properties,
+                    );
+                return match result {
+                    Ok(node) => {
+                        proxies
+                            .write()
+                            .unwrap()
+                            .virtual_nodes
+                            .push(node);
+                        Ok(Some(ConnectorEvent::NodeCreated(
+                            name.clone(),
+                        )))
+                    }
+                    Err(e) => {
+                        report_error(
+                            &sender.read().unwrap(),
+                            format!(
+                                "Failed to create node {name}: {e}"
+                            ),
+                        );
+                        Err(ConnectorEvent::NodeCreationFailed(
+                            name.clone(),
+                        ))
+                    }
+                };
             }
+            // Handled by the backend thread, which owns the main loop
+            // and handles the commands of transactions one by one
+            PipeWireEvent::ShutdownCommand
+            | PipeWireEvent::TransactionCommand(_) => {}
             _ => {
-                log::warn!("Unhandled event: {self:?}");
+                log_warn!("Unhandled event: {self:?}");
             }
         }
         drop(event_locker);
-        Ok(())
+        Ok(None)
+    }
+
+    /// Apply a control to the bound node, returning the response.
+    fn _node_control(
+        proxies: Rc<RwLock<BoundProxies>>,
+        sender: &Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
+        node_id: u32,
+        control: impl FnOnce(&BoundNode) -> Result<(), String>,
+    ) -> Result<ConnectorEvent, ConnectorEvent> {
+        let proxies = proxies.read().unwrap();
+        let result = match proxies.nodes.get(&node_id) {
+            Some(bound) => control(bound),
+            None => Err(format!("Node {node_id} is not bound")),
+        };
+        match result {
+            Ok(()) => Ok(ConnectorEvent::ControlUpdate(node_id)),
+            Err(e) => {
+                report_error(
+                    &sender.read().unwrap(),
+                    format!("Failed to control node {node_id}: {e}"),
+                );
+                Err(ConnectorEvent::ControlFailed(node_id))
+            }
+        }
     }
 
-    fn _link_command(
+    /// Link the nodes through `link_ports`, recording the route, and
+    /// answer with [`ConnectorEvent::LinkUpdate`] or
+    /// [`ConnectorEvent::LinkFailed`].
+    pub(crate) fn _link_nodes_command(
         objects: Arc<RwLock<PipeWireObjects>>,
-        core: Rc<RwLock<Core>>,
+        sender: &Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
         source_id: u32,
         target_id: u32,
+        strategy: LinkStrategy,
+        link_ports: impl FnMut(&Port, &Port) -> Result<(), PortError>,
+    ) -> Result<ConnectorEvent, ConnectorEvent> {
+        let result = PipeWireEvent::_link_command(
+            objects.clone(),
+            source_id,
+            target_id,
+            strategy,
+            link_ports,
+        );
+        if let Err(e) = result {
+            report_error(
+                &sender.read().unwrap(),
+                format!("Failed to link nodes: {e}"),
+            );
+            return Err(ConnectorEvent::LinkFailed(
+                source_id, target_id,
+            ));
+        }
+        objects
+            .write()
+            .unwrap()
+            .record_route(source_id, target_id, strategy);
+        Ok(ConnectorEvent::LinkUpdate(source_id, target_id))
+    }
+
+    /// Link the nodes, creating each link through `link_ports`, and
+    /// record the created links as pending.
+    pub(crate) fn _link_command(
+        objects: Arc<RwLock<PipeWireObjects>>,
+        source_id: u32,
+        target_id: u32,
+        strategy: LinkStrategy,
+        link_ports: impl FnMut(&Port, &Port) -> Result<(), PortError>,
     ) -> Result<(), String> {
         let objects = objects.write();
 
@@ -106,75 +660,518 @@ impl PipeWireEvent {
         }
         let mut objects = objects.unwrap();
 
-        if source_id == target_id {
+        if let Err(e) = objects.can_link(source_id, target_id) {
+            return Err(format!("Cannot link nodes: {e}"));
+        }
+        let equalization = objects.channel_equalization;
+
+        let (Some(input_node), Some(target_node)) = (
+            objects.find_node_by_id(source_id),
+            objects.find_node_by_id(target_id),
+        ) else {
             return Err(format!(
-                "Source and target IDs are the same: {source_id}"
+                "One or both nodes not found for IDs: {source_id} and {target_id}"
+            ));
+        };
+        let report = input_node
+            .link_device_with(
+                target_node,
+                strategy,
+                equalization,
+                link_ports,
+            )
+            .map_err(|e| format!("Failed to link devices: {e}"))?;
+        // Known right away rather than once the server announces them
+        for (output, input) in report.pairs_linked {
+            objects.insert_pending_link(Link::pending(
+                (source_id, output.id),
+                (target_id, input.id),
             ));
         }
-
-        for link in objects.links.iter() {
-            if link.output_node == source_id
-                && link.input_node == target_id
-            {
-                return Err(format!(
-                    "Link already exist {source_id} <===> {target_id}"
-                ));
-            }
+        Ok(())
+    }
+    fn _link_ports_command(
+        objects: Arc<RwLock<PipeWireObjects>>,
+        core: Rc<RwLock<Core>>,
+        (source_port, target_port): (u32, u32),
+        options: &LinkOptions,
+        retry_policy: &RetryPolicy,
+    ) -> Result<(), String> {
+        let objects = objects.read();
+        if let Err(e) = objects {
+            return Err(format!("Failed to lock objects: {e}"));
         }
+        let objects = objects.unwrap();
 
-        let (input_node, target_node) =
-            objects.find_two_nodes_by_id_mut(source_id, target_id);
-
-        if input_node.is_none() || target_node.is_none() {
+        let source = objects.find_port_by_id(source_port);
+        let target = objects.find_port_by_id(target_port);
+        if source.is_none() || target.is_none() {
             return Err(format!(
-                "One or both nodes not found for IDs: {source_id} and {target_id}"
+                "One or both ports not found for IDs: {source_port} and {target_port}"
             ));
         }
 
-        let input_node = input_node.unwrap();
-        let target_node = target_node.unwrap();
-        if let Err(e) = input_node.link_device(core, target_node) {
-            return Err(format!("Failed to link devices: {e}"));
+        source
+            .unwrap()
+            .link_port_with_options(
+                core,
+                target.unwrap(),
+                retry_policy,
+                options,
+            )
+            .map_err(|e| format!("Failed to link ports: {e}"))
+    }
+
+    /// Remove the links, returning how many were removed and how
+    /// many could not be. Links already gone with one of their nodes
+    /// count as neither.
+    fn _remove_links_command(
+        objects: Arc<RwLock<PipeWireObjects>>,
+        registry: Option<Rc<RwLock<Registry>>>,
+        link_ids: &[u32],
+        sender: Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
+    ) -> (usize, usize) {
+        let mut objects = objects.write().unwrap();
+        let (mut removed, mut failed) = (0, 0);
+        for &id in link_ids {
+            match block_on(objects.remove_link(
+                id,
+                registry.clone(),
+                sender.clone(),
+            )) {
+                Ok(Some((output_node, input_node))) => {
+                    objects.forget_route(output_node, input_node);
+                    removed += 1;
+                }
+                // Gone with its node, not removed by us
+                Ok(None) => {}
+                Err(e) => {
+                    report_error(
+                        &sender.read().unwrap(),
+                        format!("Failed to remove link: {e}"),
+                    );
+                    failed += 1;
+                }
+            }
+        }
+        (removed, failed)
+    }
+
+    /// Unlink the nodes, forgetting their route, and answer with
+    /// [`ConnectorEvent::NothingToUnlink`] if they were not linked or
+    /// [`ConnectorEvent::UnLinkFailed`]. Removing a link already
+    /// sends [`ConnectorEvent::UnlinkUpdate`], which is only sent
+    /// here when every link was still pending.
+    pub(crate) fn _unlink_nodes_command(
+        objects: Arc<RwLock<PipeWireObjects>>,
+        registry: Option<Rc<RwLock<Registry>>>,
+        sender: &Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
+        source_id: u32,
+        target_id: u32,
+    ) -> Result<Option<ConnectorEvent>, ConnectorEvent> {
+        objects.write().unwrap().forget_route(source_id, target_id);
+        let result = PipeWireEvent::_unlink_command(
+            objects,
+            registry,
+            source_id,
+            target_id,
+            sender.clone(),
+        );
+        match result {
+            Ok((0, 0)) => {
+                log_debug!("Nodes {source_id} and {target_id} were not linked");
+                Ok(Some(ConnectorEvent::NothingToUnlink(
+                    source_id, target_id,
+                )))
+            }
+            Ok((0, _)) => Ok(Some(ConnectorEvent::UnlinkUpdate(
+                source_id, target_id,
+            ))),
+            Ok(_) => Ok(None),
+            Err(e) => {
+                report_error(
+                    &sender.read().unwrap(),
+                    format!("Failed to unlink nodes: {e}"),
+                );
+                Err(ConnectorEvent::UnLinkFailed(
+                    source_id, target_id,
+                ))
+            }
         }
-        Ok(())
     }
+
+    /// Remove every link between the two nodes, returning how many
+    /// links were removed and how many pending links were cancelled.
+    /// Pending links are destroyed once the server announces them.
     fn _unlink_command(
         objects: Arc<RwLock<PipeWireObjects>>,
-        registry: Rc<RwLock<Registry>>,
+        registry: Option<Rc<RwLock<Registry>>>,
         source_id: u32,
         target_id: u32,
         sender: Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
-    ) -> Result<(), String> {
+    ) -> Result<(usize, usize), String> {
         let objects = objects.write();
         if let Err(e) = objects {
             return Err(format!("Failed to lock objects: {e}"));
         }
         let mut objects = objects.unwrap();
 
-        let mut links_id = vec![];
+        let links_id =
+            objects.find_link_ids_between(source_id, target_id);
+        let mut links_removed = 0;
 
-        for link in objects.links.iter() {
-            if link.output_node == source_id
-                && link.input_node == target_id
+        for id in links_id {
+            log_debug!("Found link with ID: {id} while searching for source ID: {source_id} and target ID: {target_id}");
+            if block_on(objects.remove_link(
+                id,
+                registry.clone(),
+                sender.clone(),
+            ))?
+            .is_some()
             {
-                links_id.push(link.id);
+                links_removed += 1;
             }
         }
+        Ok((
+            links_removed,
+            objects.cancel_pending_links(source_id, target_id),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::sync::{mpsc, Arc};
+    use std::time::{Duration, Instant};
+
+    use libspa::utils::result::SpaResult;
+
+    use super::{sequence, ConnectorEvent, Event, PipeWireEvent};
+    use crate::link::{
+        Link, LinkOptions, LogicalLink, PENDING_LINK_TIMEOUT,
+    };
+    use crate::node::LinkStrategy;
+    use crate::objects::PipeWireObjects;
+    use crate::proxies::BoundProxies;
+    use crate::retry::RetryPolicy;
+    use crate::sync::RwLock;
+    use crate::test_utils::{stereo_node, with_nodes};
+
+    #[test]
+    fn events_are_numbered_in_the_order_they_are_sent() {
+        let (manager, received) = mpsc::channel();
+        let backend = sequence(manager);
+        let events = [
+            ConnectorEvent::LinkUpdate(1, 2),
+            ConnectorEvent::Ready,
+            ConnectorEvent::UnlinkUpdate(1, 2),
+        ];
+        for event in events.clone() {
+            backend.send(event).unwrap();
+        }
 
-        if links_id.is_empty() {
-            return Err(
-                "Tried to unlink a non existant linkage".to_owned()
+        for (seq, kind) in (0..).zip(events) {
+            assert_eq!(
+                received.recv_timeout(Duration::from_secs(1)),
+                Ok(Event { seq, kind })
             );
         }
+    }
 
-        for id in links_id {
-            log::debug!("Found link with ID: {id} while searching for source ID: {source_id} and target ID: {target_id}");
-            block_on(objects.remove_link(
-                id,
-                Some(registry.clone()),
-                sender.clone(),
-            ))?;
+    #[test]
+    fn link_command_retries_transient_failures() {
+        let objects = Arc::new(RwLock::new(with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ])));
+        let (sender, receiver) = mpsc::channel();
+        let sender = Arc::new(RwLock::new(sender));
+        let policy = RetryPolicy {
+            initial_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut attempts = 0;
+        let response = PipeWireEvent::_link_nodes_command(
+            objects.clone(),
+            &sender,
+            1,
+            2,
+            LinkStrategy::default(),
+            |port, other_port| {
+                port.link_port_through(
+                    other_port,
+                    &policy,
+                    &LinkOptions::default(),
+                    |_| {
+                        attempts += 1;
+                        if attempts == 1 {
+                            // The server is busy the first time
+                            let busy =
+                                SpaResult::from_c(-libc::EBUSY)
+                                    .into_result()
+                                    .unwrap_err();
+                            return Err(busy.into());
+                        }
+                        Ok(())
+                    },
+                )
+            },
+        );
+
+        assert_eq!(response, Ok(ConnectorEvent::LinkUpdate(1, 2)));
+        // Two channels, the first one created on the second attempt
+        assert_eq!(attempts, 3);
+        assert_eq!(
+            objects.read().unwrap().link_count_between(1, 2),
+            2
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn link_command_gives_up_on_permanent_failures() {
+        let objects = Arc::new(RwLock::new(with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ])));
+        let (sender, _receiver) = mpsc::channel();
+        let mut attempts = 0;
+        let response = PipeWireEvent::_link_nodes_command(
+            objects.clone(),
+            &Arc::new(RwLock::new(sender)),
+            1,
+            2,
+            LinkStrategy::default(),
+            |port, other_port| {
+                port.link_port_through(
+                    other_port,
+                    &RetryPolicy::default(),
+                    &LinkOptions::default(),
+                    |_| -> Result<(), _> {
+                        attempts += 1;
+                        Err(pipewire::Error::WrongProxyType)
+                    },
+                )
+            },
+        );
+
+        assert_eq!(response, Err(ConnectorEvent::LinkFailed(1, 2)));
+        assert_eq!(attempts, 1);
+        assert!(!objects.read().unwrap().is_linked(1, 2));
+    }
+
+    #[test]
+    fn remove_links_command_removes_every_link() {
+        let objects = Arc::new(RwLock::new(PipeWireObjects {
+            links: vec![
+                Link::from_parts(30, (1, 10), (2, 21)),
+                Link::from_parts(31, (1, 12), (2, 23)),
+                Link::from_parts(32, (2, 20), (1, 11)),
+                // From a node already gone
+                Link::from_parts(34, (3, 40), (2, 21)),
+            ],
+            ..with_nodes([
+                stereo_node(1, "source", 10),
+                stereo_node(2, "sink", 20),
+            ])
+        }));
+        let (sender, receiver) = mpsc::channel();
+
+        let result = PipeWireEvent::_remove_links_command(
+            objects.clone(),
+            None,
+            &[30, 31, 32, 33, 34],
+            Arc::new(RwLock::new(sender)),
+        );
+        assert_eq!(result, (3, 1));
+        assert!(objects.read().unwrap().links.is_empty());
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![
+                ConnectorEvent::LinkRemoved(30),
+                ConnectorEvent::UnlinkUpdate(1, 2),
+                ConnectorEvent::LinkRemoved(31),
+                ConnectorEvent::UnlinkUpdate(1, 2),
+                ConnectorEvent::LinkRemoved(32),
+                ConnectorEvent::UnlinkUpdate(2, 1),
+                ConnectorEvent::Error(
+                    "Failed to remove link: Failed to find link with id 33"
+                        .to_owned()
+                ),
+                ConnectorEvent::LinkRemoved(34),
+                ConnectorEvent::UnlinkUpdate(3, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn links_unlinked_while_pending_are_cancelled() {
+        let objects = Arc::new(RwLock::new(with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ])));
+        let mut created = vec![];
+        PipeWireEvent::_link_command(
+            objects.clone(),
+            1,
+            2,
+            LinkStrategy::default(),
+            |port, other_port| {
+                created.push((port.id, other_port.id));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(created, vec![(10, 21), (12, 23)]);
+        {
+            let objects = objects.read().unwrap();
+            assert_eq!(objects.link_count_between(1, 2), 2);
+            assert_eq!(objects.pending_link_count_between(1, 2), 2);
+            assert!(objects.find_link_ids_between(1, 2).is_empty());
         }
-        Ok(())
+
+        let (sender, _receiver) = mpsc::channel();
+        let removed = PipeWireEvent::_unlink_command(
+            objects.clone(),
+            None,
+            1,
+            2,
+            Arc::new(RwLock::new(sender)),
+        );
+        assert_eq!(removed, Ok((0, 2)));
+        let mut objects = objects.write().unwrap();
+        assert!(!objects.is_linked(1, 2));
+        // Destroyed once announced instead of being added
+        assert!(objects.take_cancelled_link(&Link::from_parts(
+            40,
+            (1, 10),
+            (2, 21)
+        )));
+        assert!(!objects.take_cancelled_link(&Link::from_parts(
+            40,
+            (1, 10),
+            (2, 21)
+        )));
+    }
+
+    #[test]
+    fn unlinking_only_pending_links_is_answered() {
+        let objects = Arc::new(RwLock::new(with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ])));
+        PipeWireEvent::_link_command(
+            objects.clone(),
+            1,
+            2,
+            LinkStrategy::default(),
+            |_, _| Ok(()),
+        )
+        .unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let sender = Arc::new(RwLock::new(sender));
+
+        let response = PipeWireEvent::_unlink_nodes_command(
+            objects.clone(),
+            None,
+            &sender,
+            1,
+            2,
+        );
+        assert_eq!(
+            response,
+            Ok(Some(ConnectorEvent::UnlinkUpdate(1, 2)))
+        );
+        assert!(receiver.try_iter().next().is_none());
+        assert!(!objects.read().unwrap().is_linked(1, 2));
+
+        let response = PipeWireEvent::_unlink_nodes_command(
+            objects, None, &sender, 1, 2,
+        );
+        assert_eq!(
+            response,
+            Ok(Some(ConnectorEvent::NothingToUnlink(1, 2)))
+        );
+    }
+
+    #[test]
+    fn pending_links_expire_when_never_announced() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ]);
+        let mut pending = Link::pending((1, 10), (2, 21));
+        pending.pending_since =
+            Instant::now().checked_sub(PENDING_LINK_TIMEOUT);
+        objects.insert_pending_link(pending);
+        assert!(!objects.is_linked(1, 2));
+        assert_eq!(objects.link_count_between(1, 2), 0);
+
+        objects.insert_pending_link(Link::pending((1, 12), (2, 23)));
+        assert_eq!(objects.links.len(), 1);
+        assert_eq!(objects.pending_link_count_between(1, 2), 1);
+    }
+
+    #[test]
+    fn logical_links_are_removed_as_one() {
+        let objects = Arc::new(RwLock::new(PipeWireObjects {
+            links: vec![
+                Link::from_parts(31, (1, 12), (2, 23)),
+                Link::from_parts(32, (2, 20), (1, 11)),
+                Link::from_parts(30, (1, 10), (2, 21)),
+            ],
+            ..with_nodes([
+                stereo_node(1, "source", 10),
+                stereo_node(2, "sink", 20),
+            ])
+        }));
+        let logical_links = objects.read().unwrap().logical_links();
+        assert_eq!(
+            logical_links,
+            vec![
+                LogicalLink {
+                    output_node: 1,
+                    input_node: 2,
+                    link_ids: vec![30, 31],
+                },
+                LogicalLink {
+                    output_node: 2,
+                    input_node: 1,
+                    link_ids: vec![32],
+                },
+            ]
+        );
+        assert_eq!(logical_links[0].channel_count(), 2);
+
+        let (sender, _receiver) = mpsc::channel();
+        let result = PipeWireEvent::_remove_links_command(
+            objects.clone(),
+            None,
+            &logical_links[0].link_ids,
+            Arc::new(RwLock::new(sender)),
+        );
+        assert_eq!(result, (2, 0));
+        assert_eq!(objects.read().unwrap().logical_links().len(), 1);
+    }
+
+    #[test]
+    fn failed_controls_are_reported_as_errors() {
+        let (sender, receiver) = mpsc::channel();
+
+        let response = PipeWireEvent::_node_control(
+            Rc::new(RwLock::new(BoundProxies::default())),
+            &Arc::new(RwLock::new(sender)),
+            5,
+            |bound| bound.set_mute(true),
+        );
+        assert_eq!(response, Err(ConnectorEvent::ControlFailed(5)));
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![ConnectorEvent::Error(
+                "Failed to control node 5: Node 5 is not bound"
+                    .to_owned()
+            )]
+        );
     }
 }
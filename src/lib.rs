@@ -1,11 +1,33 @@
+#[macro_use]
+mod logging;
+
+mod callbacks;
+pub mod changes;
+pub mod control;
+mod dot;
 mod event;
-mod link;
+pub mod link;
 pub mod manager;
-mod node;
+pub mod metadata;
+pub mod meter;
+pub mod node;
 pub mod objects;
 pub mod port;
+mod props;
+mod proxies;
+pub mod retry;
+pub mod rules;
+pub mod server;
+pub mod snapshot;
+#[cfg(feature = "async")]
+mod stream;
+pub mod sync;
+pub mod transaction;
 mod utils;
 
+#[cfg(test)]
+mod test_utils;
+
 #[cfg(test)]
 mod tests {
     use crate::manager::PipeWireManager;
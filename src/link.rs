@@ -1,8 +1,246 @@
-use std::{rc::Rc, sync::RwLock};
+use std::{
+    rc::Rc,
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
 
-use super::utils::val;
+use super::control::ControlError;
+use super::event::ConnectorEvent;
+use super::node::{LinkStrategy, NodeError};
+use super::objects::PipeWireObjects;
+use super::port::{AudioChannel, PortDirection, PortError};
+use super::sync::RwLock;
+use super::utils::{discovery_debug, val_opt};
 use libspa::utils::dict::DictRef;
+use pipewire::link::{
+    Link as LinkProxy, LinkListener, LinkState as PwLinkState,
+};
+use pipewire::permissions::PermissionFlags;
+use pipewire::properties::{properties, Properties};
 use pipewire::registry::{GlobalObject, Registry};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum LinkError {
+    #[error("Node {0} was not found")]
+    NodeNotFound(u32),
+    #[error("Node {0} does not have a port named {1}")]
+    PortNotFound(u32, String),
+    #[error("Failed to link node {0} into node {1}")]
+    LinkFailed(u32, u32),
+    #[error("Failed to unlink node {0} from node {1}")]
+    UnlinkFailed(u32, u32),
+    #[error("Timed out waiting for nodes {0} and {1}")]
+    Timeout(u32, u32),
+    #[error("Node {0} cannot be linked into itself")]
+    SameNode(u32),
+    #[error("Node {0} is already linked into node {1}")]
+    AlreadyLinked(u32, u32),
+    #[error("Linking node {0} into node {1} would create a cycle")]
+    WouldCreateCycle(u32, u32),
+    #[error(
+        "Node {0} does not have both an FL and an FR {1:?} port"
+    )]
+    NotStereo(u32, PortDirection),
+    #[error(
+        "Node {0} does not have an {2:?} port for channel {1:?}"
+    )]
+    ChannelNotFound(u32, AudioChannel, PortDirection),
+    #[error("Removed {removed} links but failed to remove {failed}")]
+    UnlinkAllFailed { removed: usize, failed: usize },
+    /// The backend answered a command with an event that does not
+    /// belong to it.
+    #[error("Unexpected answer from the backend: {0}")]
+    UnexpectedEvent(String),
+    #[error(transparent)]
+    Node(#[from] NodeError),
+    #[error(transparent)]
+    Port(#[from] PortError),
+    #[error(transparent)]
+    Control(#[from] ControlError),
+    #[error(
+        "Linked node {0} into node {1} but failed to set its volume: {2}"
+    )]
+    LinkedButVolumeFailed(u32, u32, #[source] ControlError),
+    #[error(transparent)]
+    Wait(#[from] WaitError),
+    /// A blocking call was made from an event callback or the
+    /// backend thread, which would wait forever for events only
+    /// delivered once it returns.
+    #[error(
+        "Blocking calls cannot be made from event callbacks or the backend thread"
+    )]
+    ReentrantCall,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum WaitError {
+    #[error("Link {0} was not found")]
+    LinkNotFound(u32),
+    #[error("Link {0} failed: {1}")]
+    LinkFailed(u32, String),
+    #[error("Timed out waiting for link {0}")]
+    Timeout(u32),
+    #[error(
+        "Timed out waiting for nodes {0} and {1} to be unlinked"
+    )]
+    StillLinked(u32, u32),
+    #[error(
+        "Timed out waiting for the links of nodes {0} and {1} to be announced"
+    )]
+    StillPending(u32, u32),
+    #[error(
+        "Blocking calls cannot be made from event callbacks or the backend thread"
+    )]
+    ReentrantCall,
+}
+
+/// Why a link global could not be read.
+#[derive(Error, Debug, PartialEq)]
+pub enum LinkParseError {
+    #[error("Link {0} does not have any property")]
+    NoProperties(u32),
+    #[error("Link {0} does not have a {1} property")]
+    MissingProperty(u32, &'static str),
+    #[error("Link {0} has an invalid {1}: {2:?}")]
+    InvalidProperty(u32, &'static str, String),
+}
+
+/// Where a link is in its negotiation, audio only flows through
+/// [`LinkState::Active`] links.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum LinkState {
+    Error(String),
+    Unlinked,
+    #[default]
+    Init,
+    Negotiating,
+    Allocating,
+    Paused,
+    Active,
+}
+
+impl From<PwLinkState<'_>> for LinkState {
+    fn from(state: PwLinkState<'_>) -> Self {
+        match state {
+            PwLinkState::Error(error) => {
+                LinkState::Error(error.to_owned())
+            }
+            PwLinkState::Unlinked => LinkState::Unlinked,
+            PwLinkState::Init => LinkState::Init,
+            PwLinkState::Negotiating => LinkState::Negotiating,
+            PwLinkState::Allocating => LinkState::Allocating,
+            PwLinkState::Paused => LinkState::Paused,
+            PwLinkState::Active => LinkState::Active,
+        }
+    }
+}
+
+/// Two nodes linked through the manager, remembered by their
+/// [`Node::fingerprint`](crate::node::Node::fingerprint) so that
+/// they can be linked again once they come back with new ids.
+/// Nodes of the same name, e.g. two identical devices, are told
+/// apart by their media class and object path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub output_node: String,
+    pub input_node: String,
+    pub strategy: LinkStrategy,
+    /// The `(output, input)` port names linked one by one through
+    /// the port level APIs, e.g. [`PipeWireManager::link_stereo`].
+    /// Empty when the nodes were linked as a whole with `strategy`.
+    ///
+    /// [`PipeWireManager::link_stereo`]: crate::manager::PipeWireManager::link_stereo
+    pub ports: Vec<(String, String)>,
+}
+
+/// Every link from the ports of a node into the ports of another, as
+/// users think of a connection: a stereo connection is one logical
+/// link of two channels. See [`crate::objects::PipeWireObjects::logical_links`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogicalLink {
+    pub output_node: u32,
+    pub input_node: u32,
+    /// Ids of the links, one per channel, in ascending order.
+    pub link_ids: Vec<u32>,
+}
+
+impl LogicalLink {
+    pub fn channel_count(&self) -> usize {
+        self.link_ids.len()
+    }
+}
+
+/// How the links made by the manager behave.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkOptions {
+    /// Keep the link once the manager is gone (`object.linger`).
+    pub linger: bool,
+    /// Make a passive link (`link.passive`): it does not keep the
+    /// nodes it links running on its own, so a sink only linked
+    /// into e.g. a monitoring tap may still be suspended. Audio
+    /// flows through it whenever the nodes run for other reasons.
+    pub passive: bool,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        Self {
+            linger: true,
+            passive: false,
+        }
+    }
+}
+
+impl LinkOptions {
+    /// The properties of a link from the output port into the input
+    /// port, `(node, port)` pairs.
+    pub(crate) fn properties(
+        &self,
+        output: (u32, u32),
+        input: (u32, u32),
+    ) -> Properties {
+        let mut properties = properties! {
+            "link.output.node" => output.0.to_string(),
+            "link.output.port" => output.1.to_string(),
+            "link.input.node" => input.0.to_string(),
+            "link.input.port" => input.1.to_string(),
+        };
+        if self.linger {
+            properties.insert("object.linger", "1");
+        }
+        if self.passive {
+            properties.insert("link.passive", "true");
+        }
+        properties
+    }
+}
+
+/// What reconciling the links with a desired set of linked nodes
+/// changed, as `(output_node, input_node)` pairs, see
+/// [`crate::manager::PipeWireManager::reconcile_links`].
+#[derive(Debug, Default, PartialEq)]
+pub struct ReconcileReport {
+    pub added: Vec<(u32, u32)>,
+    pub removed: Vec<(u32, u32)>,
+    /// Pairs that could not be linked or unlinked.
+    pub failed: Vec<((u32, u32), LinkError)>,
+}
+
+impl ReconcileReport {
+    /// Whether the links now match the desired set.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// An owned summary of a [`Link`], detached from the objects lock,
+/// the same as the links of a [`crate::snapshot::GraphSnapshot`].
+pub type LinkInfo = crate::snapshot::LinkSnapshot;
 
 #[allow(dead_code)]
 pub struct Link {
@@ -11,31 +249,45 @@ pub struct Link {
     pub(crate) input_port: u32,
     pub(crate) output_node: u32,
     pub(crate) input_node: u32,
+    pub(crate) state: LinkState,
+    /// Whether the client is allowed to destroy the link.
+    pub(crate) destroyable: bool,
+    /// When the manager created the link, while it is pending.
+    pub(crate) pending_since: Option<Instant>,
 }
 
 impl Link {
-    pub fn new(global: &GlobalObject<&DictRef>) -> Self {
-        let props = global.props.unwrap();
+    pub fn new(
+        global: &GlobalObject<&DictRef>,
+    ) -> Result<Self, LinkParseError> {
+        let props = global
+            .props
+            .ok_or(LinkParseError::NoProperties(global.id))?;
+        let id_prop = |key| {
+            let value = val_opt(props, key).ok_or(
+                LinkParseError::MissingProperty(global.id, key),
+            )?;
+            value.parse().map_err(|_| {
+                LinkParseError::InvalidProperty(global.id, key, value)
+            })
+        };
         let node = Self {
             id: global.id,
-            output_port: val(props, "link.output.port")
-                .parse()
-                .unwrap(),
-            input_port: val(props, "link.input.port")
-                .parse()
-                .unwrap(),
-            output_node: val(props, "link.output.node")
-                .parse()
-                .unwrap(),
-            input_node: val(props, "link.input.node")
-                .parse()
-                .unwrap(),
+            output_port: id_prop("link.output.port")?,
+            input_port: id_prop("link.input.port")?,
+            output_node: id_prop("link.output.node")?,
+            input_node: id_prop("link.input.node")?,
+            state: LinkState::default(),
+            destroyable: global
+                .permissions
+                .contains(PermissionFlags::X),
+            pending_since: None,
         };
-        log::debug!(
+        discovery_debug!(
             "Creating new Link from global object: {:?}",
             node.id
         );
-        node
+        Ok(node)
     }
     pub async fn remove_link(
         target_id: u32,
@@ -43,24 +295,184 @@ impl Link {
     ) {
         let registry = registry.read();
         if let Err(e) = registry {
-            log::error!("Failed to lock registry: {e}");
+            log_error!("Failed to lock registry: {e}");
             return;
         }
         let registry = registry.unwrap();
         let result =
             registry.destroy_global(target_id).into_async_result();
         if let Err(e) = result {
-            log::error!("Failed to destroy global object: {e}");
+            log_error!("Failed to destroy global object: {e}");
         } else {
-            log::info!(
+            log_info!(
                 "Successfully destroyed global object: {target_id}"
             );
         }
     }
 }
 
+impl Link {
+    /// A link the manager just created, known before the server
+    /// announces it. It is replaced by the announced link once its
+    /// global arrives, see [`Link::is_pending`].
+    pub(crate) fn pending(
+        (output_node, output_port): (u32, u32),
+        (input_node, input_port): (u32, u32),
+    ) -> Self {
+        Self {
+            id: PENDING_LINK_ID,
+            output_port,
+            input_port,
+            output_node,
+            input_node,
+            state: LinkState::default(),
+            destroyable: true,
+            pending_since: Some(Instant::now()),
+        }
+    }
+
+    /// Whether the link is pending for longer than
+    /// [`PENDING_LINK_TIMEOUT`], meaning the server will most likely
+    /// never announce it.
+    pub(crate) fn is_expired(&self) -> bool {
+        self.pending_since.is_some_and(|since| {
+            since.elapsed() >= PENDING_LINK_TIMEOUT
+        })
+    }
+
+    /// The global id of the link, [`PENDING_LINK_ID`] while it is
+    /// pending.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Whether the link was created by the manager but not announced
+    /// by the server yet. It has no global id until then, so it
+    /// cannot be removed or controlled.
+    pub fn is_pending(&self) -> bool {
+        self.id == PENDING_LINK_ID
+    }
+
+    pub fn output_node(&self) -> u32 {
+        self.output_node
+    }
+
+    pub fn input_node(&self) -> u32 {
+        self.input_node
+    }
+
+    pub fn output_port(&self) -> u32 {
+        self.output_port
+    }
+
+    pub fn input_port(&self) -> u32 {
+        self.input_port
+    }
+
+    pub fn state(&self) -> &LinkState {
+        &self.state
+    }
+}
+
+/// Id of the links created by the manager until the server announces
+/// them.
+pub const PENDING_LINK_ID: u32 = u32::MAX;
+
+/// How long a created link stays pending before it is dropped, when
+/// the server never announces it, e.g. because it refused to create
+/// it after all.
+pub const PENDING_LINK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A link bound by the backend thread to follow its state.
+pub(crate) struct BoundLink {
+    _proxy: LinkProxy,
+    _listener: LinkListener,
+}
+
+impl BoundLink {
+    /// Bind the link, mirroring its state into the objects and
+    /// reporting every change through the sender.
+    pub(crate) fn bind(
+        registry: &Registry,
+        global: &GlobalObject<&DictRef>,
+        objects: Arc<RwLock<PipeWireObjects>>,
+        sender: Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
+    ) -> Result<Self, pipewire::Error> {
+        let link_id = global.id;
+        let proxy = registry.bind::<LinkProxy, _>(global)?;
+        let listener = proxy
+            .add_listener_local()
+            .info(move |info| {
+                let state = LinkState::from(info.state());
+                let mut objects = objects.write().unwrap();
+                let Some(link) = objects
+                    .links
+                    .iter_mut()
+                    .find(|link| link.id == link_id)
+                else {
+                    return;
+                };
+                if link.state == state {
+                    return;
+                }
+                log_debug!("Link {link_id} is now {state:?}");
+                link.state = state.clone();
+                let _result = sender.read().unwrap().send(
+                    ConnectorEvent::LinkStateChanged(link_id, state),
+                );
+            })
+            .register();
+        Ok(Self {
+            _proxy: proxy,
+            _listener: listener,
+        })
+    }
+}
+
 impl Drop for Link {
     fn drop(&mut self) {
-        log::debug!("Link {} was removed", self.id,);
+        discovery_debug!("Link {} was removed", self.id,);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pipewire::permissions::PermissionFlags;
+    use pipewire::registry::GlobalObject;
+    use pipewire::types::ObjectType;
+
+    use super::{Link, LinkOptions, LinkParseError};
+
+    #[test]
+    fn links_without_properties_are_rejected() {
+        let bare = GlobalObject {
+            id: 7,
+            permissions: PermissionFlags::all(),
+            type_: ObjectType::Link,
+            version: 3,
+            props: None,
+        };
+        assert_eq!(
+            Link::new(&bare).err(),
+            Some(LinkParseError::NoProperties(7))
+        );
+    }
+
+    #[test]
+    fn link_options_map_to_properties() {
+        let properties =
+            LinkOptions::default().properties((1, 10), (2, 21));
+        assert_eq!(properties.get("link.output.port"), Some("10"));
+        assert_eq!(properties.get("link.input.node"), Some("2"));
+        assert_eq!(properties.get("object.linger"), Some("1"));
+        assert_eq!(properties.get("link.passive"), None);
+
+        let passive = LinkOptions {
+            linger: false,
+            passive: true,
+        };
+        let properties = passive.properties((1, 10), (2, 21));
+        assert_eq!(properties.get("link.passive"), Some("true"));
+        assert_eq!(properties.get("object.linger"), None);
     }
 }
@@ -0,0 +1,163 @@
+//! Internal logging. Every message goes to the `log` facade and to
+//! the logger the manager logging it was given with
+//! [`crate::manager::PipeWireManagerBuilder::with_logger`], if any.
+use std::cell::RefCell;
+use std::fmt::Arguments;
+use std::sync::Arc;
+
+use log::{Level, LevelFilter};
+
+pub(crate) type LogSink = dyn Fn(Level, &str) + Send + Sync;
+
+/// A logger set through the builder of the manager.
+#[derive(Clone)]
+pub(crate) struct Logger {
+    pub(crate) sink: Arc<LogSink>,
+    /// Most verbose level delivered to the sink.
+    pub(crate) level: LevelFilter,
+}
+
+impl std::fmt::Debug for Logger {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str("Logger")
+    }
+}
+
+thread_local! {
+    /// The logger of the manager the current thread works for.
+    static LOGGER: RefCell<Option<Logger>> = const { RefCell::new(None) };
+}
+
+/// Puts back the logger the thread had before [`scope`] on drop.
+pub(crate) struct LoggerScope(Option<Logger>);
+
+impl Drop for LoggerScope {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        LOGGER.with(|logger| *logger.borrow_mut() = previous);
+    }
+}
+
+/// Deliver the messages logged on the current thread to `logger` as
+/// well, until the returned scope is dropped.
+pub(crate) fn scope(logger: Option<Logger>) -> LoggerScope {
+    LoggerScope(LOGGER.with(|current| current.replace(logger)))
+}
+
+pub(crate) fn emit(level: Level, target: &str, args: Arguments) {
+    let sink = LOGGER.with(|logger| {
+        logger
+            .borrow()
+            .as_ref()
+            .filter(|logger| level <= logger.level)
+            .map(|logger| logger.sink.clone())
+    });
+    // Called without the logger borrowed, in case it logs itself
+    if let Some(sink) = sink {
+        sink(level, &args.to_string());
+    }
+    log::log!(target: target, level, "{args}");
+}
+
+macro_rules! log_at {
+    ($level:ident, target: $target:expr, $($arg:tt)+) => {
+        $crate::logging::emit(
+            log::Level::$level,
+            $target,
+            format_args!($($arg)+),
+        )
+    };
+    ($level:ident, $($arg:tt)+) => {
+        $crate::logging::emit(
+            log::Level::$level,
+            module_path!(),
+            format_args!($($arg)+),
+        )
+    };
+}
+
+macro_rules! log_error {
+    ($($arg:tt)+) => { log_at!(Error, $($arg)+) };
+}
+
+macro_rules! log_warn {
+    ($($arg:tt)+) => { log_at!(Warn, $($arg)+) };
+}
+
+macro_rules! log_info {
+    ($($arg:tt)+) => { log_at!(Info, $($arg)+) };
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)+) => { log_at!(Debug, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use log::{Level, LevelFilter};
+
+    use super::{scope, Logger};
+    use crate::node::Node;
+    use crate::test_utils::LOGGING;
+
+    type Received = Arc<Mutex<Vec<(Level, String)>>>;
+
+    fn logger(level: LevelFilter) -> (Logger, Received) {
+        let received = Received::default();
+        let logger = Logger {
+            sink: Arc::new({
+                let received = received.clone();
+                move |level, message: &str| {
+                    received
+                        .lock()
+                        .unwrap()
+                        .push((level, message.to_owned()))
+                }
+            }),
+            level,
+        };
+        (logger, received)
+    }
+
+    #[test]
+    fn custom_logger_receives_discovery_messages() {
+        let _logging = LOGGING.lock().unwrap();
+        let (logger, received) = logger(LevelFilter::Trace);
+        let scope = scope(Some(logger));
+        let _node = Node::from_parts(1, "speakers");
+        // Other threads, e.g. of other managers, keep their own
+        thread::spawn(|| drop(Node::from_parts(2, "headphones")))
+            .join()
+            .unwrap();
+        drop(scope);
+        let _node = Node::from_parts(3, "microphone");
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![(
+                Level::Debug,
+                "Creating new Node from global object: \"speakers\""
+                    .to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn custom_logger_only_receives_its_levels() {
+        let (logger, received) = logger(LevelFilter::Info);
+        let _scope = scope(Some(logger));
+        log_debug!("left out");
+        log_warn!("kept");
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![(Level::Warn, "kept".to_owned())]
+        );
+    }
+}
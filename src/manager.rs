@@ -1,80 +1,551 @@
-use crate::link::Link;
-use crate::node::Node;
-use crate::objects::PipeWireObjects;
-use crate::port::Port;
-use event::{ConnectorEvent, PipeWireEvent};
+use crate::callbacks::{self, EventCallbacks};
+use crate::changes::{DiffError, GraphChange};
+use crate::control::{
+    validate_quantum, validate_volume, ControlError, GlobalMute,
+    NodeHandle,
+};
+use crate::link::{
+    Link, LinkError, LinkOptions, LinkState, LogicalLink,
+    ReconcileReport, WaitError,
+};
+use crate::logging::{self, Logger};
+use crate::metadata::{
+    DefaultKind, MetadataStore, FORCE_QUANTUM_KEY,
+};
+use crate::meter::{LevelCallback, LevelMonitor};
+use crate::node::{
+    required_node_name, ChannelEqualization, CreateNodeError,
+    DriverInfo, LinkStrategy, MediaRole, Node, NodeError, NodeInfo,
+    NodeKind, NodeSelector, VirtualNodeSpec,
+};
+use crate::objects::{OtherObject, PipeWireObjects};
+use crate::port::{
+    AudioChannel, Port, PortDirection, PortError, PortInfo,
+};
+use crate::proxies::BoundProxies;
+use crate::retry::RetryPolicy;
+use crate::rules::{LinkRule, LinkRuleId, NodeMatch};
+use crate::server::ServerInfo;
+use crate::snapshot::GraphSnapshot;
+#[cfg(feature = "async")]
+use crate::stream::EventStreams;
+use crate::sync::RwLock;
+use crate::transaction::Transaction;
+use crate::utils::{self, discovery_debug};
+#[cfg(test)]
+use event::SequencedSender;
+use event::{ConnectorEvent, Event, PipeWireEvent};
 use futures::executor::block_on;
 use libspa::utils::dict::DictRef;
 use pipewire as pw;
 use pipewire::channel;
 use pipewire::core::Core;
 use pipewire::registry::{GlobalObject, Registry};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
-use std::sync::mpsc::TryRecvError;
-use std::sync::{mpsc, Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+use std::sync::{mpsc, Arc};
 use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
 
 use crate::event;
 
+/// Why the backend thread could not start.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum InitError {
+    #[error("Failed to create the main loop: {0}")]
+    MainLoopFailed(String),
+    #[error("Failed to create the context: {0}")]
+    ContextFailed(String),
+    #[error("Failed to connect to the PipeWire server: {0}")]
+    ConnectFailed(String),
+    #[error("Failed to get the registry: {0}")]
+    RegistryFailed(String),
+    #[error("The backend thread exited before connecting")]
+    BackendExited,
+    /// The previous backend thread did not stop in time, see
+    /// [`PipeWireManager::reinit`].
+    #[error("The previous backend thread did not stop")]
+    BackendStuck,
+}
+
+/// Decides from the properties of a node whether it is tracked.
+#[derive(Clone)]
+struct NodeFilter(Arc<dyn Fn(&DictRef) -> bool + Send + Sync>);
+
+impl NodeFilter {
+    fn admits(&self, global: &GlobalObject<&DictRef>) -> bool {
+        global.props.is_some_and(|props| (self.0)(props))
+    }
+}
+
+impl std::fmt::Debug for NodeFilter {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str("NodeFilter")
+    }
+}
+
+/// Backend settings chosen through the [`PipeWireManagerBuilder`].
+#[derive(Debug, Clone, Default)]
+struct BackendOptions {
+    auto_reconnect: bool,
+    restore_links_on_reconnect: bool,
+    retry_policy: RetryPolicy,
+    node_filter: Option<NodeFilter>,
+    realtime_priority: Option<i32>,
+    record_unknown_objects: bool,
+    logger: Option<Logger>,
+}
+
+/// How long [`PipeWireManager::move_node_output`] waits for the new
+/// links to carry audio before giving up on the move.
+const MOVE_ACTIVATION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long [`PipeWireManager::reinit`] waits for the previous
+/// backend thread to stop.
+const REINIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Name of the backend thread, as shown by debuggers and profilers.
+pub const BACKEND_THREAD_NAME: &str = "easy-pw-mainloop";
+
+/// A blocking call was made from an event callback or the backend
+/// thread, turned into the `ReentrantCall` variant of the error of
+/// the call.
+pub(crate) struct ReentrantCall;
+
+impl From<ReentrantCall> for LinkError {
+    fn from(_: ReentrantCall) -> Self {
+        LinkError::ReentrantCall
+    }
+}
+
+impl From<ReentrantCall> for WaitError {
+    fn from(_: ReentrantCall) -> Self {
+        WaitError::ReentrantCall
+    }
+}
+
+impl From<ReentrantCall> for ControlError {
+    fn from(_: ReentrantCall) -> Self {
+        ControlError::ReentrantCall
+    }
+}
+
+impl From<ReentrantCall> for CreateNodeError {
+    fn from(_: ReentrantCall) -> Self {
+        CreateNodeError::ReentrantCall
+    }
+}
+
 pub struct PipeWireManager {
     #[allow(dead_code)]
     pub(crate) objects: Arc<RwLock<PipeWireObjects>>,
-    pub _main_thread: thread::JoinHandle<()>,
-    pub _receiver: mpsc::Receiver<event::ConnectorEvent>,
-    _sender: channel::Sender<event::PipeWireEvent>,
+    /// Replaced by [`PipeWireManager::reinit`].
+    pub _main_thread: RwLock<thread::JoinHandle<()>>,
+    pub _receiver: mpsc::Receiver<Event>,
+    _sender: RwLock<channel::Sender<event::PipeWireEvent>>,
+    /// Handed to every new backend thread.
+    main_sender: mpsc::Sender<event::ConnectorEvent>,
+    backend_options: BackendOptions,
     pub _event_locker: Arc<RwLock<()>>,
+    retry_policy: RetryPolicy,
+    global_mute: RwLock<GlobalMute>,
+    #[cfg(feature = "async")]
+    event_streams: EventStreams,
+    event_callbacks: EventCallbacks,
+    /// Cleared when the backend thread stops, even by panicking.
+    running: Arc<AtomicBool>,
+    next_monitor_id: AtomicU32,
+}
+
+/// Clears the running flag of the manager when the backend thread
+/// stops.
+struct RunningGuard(Arc<AtomicBool>);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
 }
 
 unsafe impl Sync for PipeWireManager {}
 
+impl std::fmt::Debug for PipeWireManager {
+    /// Counts the known objects without waiting for their lock, which
+    /// the backend thread or the caller may be holding.
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let mut debug = f.debug_struct("PipeWireManager");
+        debug.field("running", &self.is_running());
+        match self.objects.try_read() {
+            Ok(objects) => debug
+                .field("nodes", &objects.nodes.len())
+                .field("links", &objects.links.len()),
+            Err(_) => {
+                debug.field("objects", &format_args!("<locked>"))
+            }
+        };
+        debug.finish_non_exhaustive()
+    }
+}
+
 impl Default for PipeWireManager {
     fn default() -> Self {
-        let (main_sender, main_receiver) =
-            mpsc::channel::<event::ConnectorEvent>();
+        Self::builder().build()
+    }
+}
+
+/// Configures a [`PipeWireManager`] before connecting it.
+#[derive(Debug, Default)]
+pub struct PipeWireManagerBuilder {
+    retry_policy: RetryPolicy,
+    auto_reconnect: bool,
+    restore_links_on_reconnect: bool,
+    node_filter: Option<NodeFilter>,
+    realtime_priority: Option<i32>,
+    record_unknown_objects: bool,
+    sorted_nodes: bool,
+    channel_equalization: ChannelEqualization,
+    discovery_log_level: Option<log::LevelFilter>,
+    change_log_capacity: Option<usize>,
+    logger: Option<Logger>,
+    logger_level: Option<log::LevelFilter>,
+}
+
+impl PipeWireManagerBuilder {
+    /// How linking nodes is retried while their ports are still
+    /// being announced, and how the backend retries creating links
+    /// that PipeWire failed to create for transient reasons.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Connect again whenever the connection to the server is lost,
+    /// e.g. when the daemon restarts. The known objects are cleared
+    /// and announced again once reconnected.
+    /// Reconnection attempts are spaced by the retry policy.
+    pub fn auto_reconnect(mut self, auto_reconnect: bool) -> Self {
+        self.auto_reconnect = auto_reconnect;
+        self
+    }
+
+    /// Once reconnected, link again the nodes that were linked
+    /// through the manager, matching them by their
+    /// [`Node::fingerprint`] since they come back with new ids.
+    /// Links made with `object.linger` are not restored: they
+    /// survive the manager disconnecting on their own.
+    /// Implies [`Self::auto_reconnect`].
+    pub fn restore_links_on_reconnect(
+        mut self,
+        restore: bool,
+    ) -> Self {
+        self.restore_links_on_reconnect = restore;
+        self
+    }
+
+    /// Only track the nodes whose properties match the filter, e.g.
+    /// `|props| props.get("media.class").is_some_and(|class|
+    /// class.starts_with("Audio"))`.
+    /// Filtered nodes, their ports and their links never appear in
+    /// any query or event.
+    pub fn node_filter(
+        mut self,
+        filter: impl Fn(&DictRef) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.node_filter = Some(NodeFilter(Arc::new(filter)));
+        self
+    }
+
+    /// Keep the globals of the types the manager does not handle
+    /// (clients, devices, factories, modules...) in
+    /// [`PipeWireObjects::other_objects`], to see what the server
+    /// announces. Off by default, as they are otherwise ignored.
+    pub fn record_unknown_objects(mut self, record: bool) -> Self {
+        self.record_unknown_objects = record;
+        self
+    }
+
+    /// Run the backend thread with real-time (`SCHED_FIFO`)
+    /// scheduling at `priority`, to react to the graph without being
+    /// delayed by busy threads. PipeWire's RT module uses 88 for its
+    /// data threads; the backend only handles control messages, so a
+    /// lower priority is usually enough.
+    ///
+    /// The thread needs the same privileges as the RT module (e.g.
+    /// membership of the `pipewire` group set up with an
+    /// `RLIMIT_RTPRIO` limit). Without them a warning is logged and
+    /// the thread keeps the default scheduling.
+    pub fn realtime_priority(mut self, priority: i32) -> Self {
+        self.realtime_priority = Some(priority);
+        self
+    }
+
+    /// Keep the nodes sorted by id and find them by binary search
+    /// instead of a hash index, see
+    /// [`PipeWireObjects::keep_nodes_sorted`].
+    pub fn sorted_nodes(mut self, sorted: bool) -> Self {
+        self.sorted_nodes = sorted;
+        self
+    }
+
+    /// How nodes whose channel layouts do not match are linked,
+    /// e.g. a mono microphone into stereo speakers. Defaults to
+    /// [`ChannelEqualization::DuplicateAndMix`].
+    pub fn channel_equalization(
+        mut self,
+        equalization: ChannelEqualization,
+    ) -> Self {
+        self.channel_equalization = equalization;
+        self
+    }
+
+    /// How many node and link changes are kept for
+    /// [`PipeWireManager::changes_since`], see
+    /// [`PipeWireObjects::set_change_log_capacity`].
+    pub fn change_log_capacity(mut self, capacity: usize) -> Self {
+        self.change_log_capacity = Some(capacity);
+        self
+    }
+
+    /// Deliver the messages this manager logs to `logger` as well,
+    /// e.g. to show them in a log panel of the application. They
+    /// still go through the `log` facade, under the `easy_pw`
+    /// targets. Other managers keep their own logger.
+    pub fn with_logger(
+        mut self,
+        logger: impl Fn(log::Level, &str) + Send + Sync + 'static,
+    ) -> Self {
+        self.logger = Some(Logger {
+            sink: Arc::new(logger),
+            level: log::LevelFilter::Trace,
+        });
+        self
+    }
+
+    /// Most verbose level of the messages delivered to the logger of
+    /// [`Self::with_logger`], every level by default. The messages
+    /// above it are not even formatted for the logger.
+    pub fn logger_level(mut self, level: log::LevelFilter) -> Self {
+        self.logger_level = Some(level);
+        self
+    }
+
+    /// Most verbose level of the logs about every node, port and
+    /// link the server announces or removes, which are very verbose
+    /// at debug level. They use the `easy_pw::discovery` target and
+    /// still go through the global `log` level; this only lowers
+    /// them, e.g. `LevelFilter::Info` keeps debug logging for the
+    /// application while silencing the enumeration.
+    /// The level is shared by every manager of the process.
+    pub fn discovery_log_level(
+        mut self,
+        level: log::LevelFilter,
+    ) -> Self {
+        self.discovery_log_level = Some(level);
+        self
+    }
+
+    /// Connect the manager without waiting for the backend thread.
+    /// If the backend cannot start, the error is only logged; see
+    /// [`Self::try_build`] to handle it instead.
+    pub fn build(self) -> PipeWireManager {
+        self.start().0
+    }
+
+    /// Connect the manager, waiting for the backend thread to be
+    /// connected to the server.
+    pub fn try_build(self) -> Result<PipeWireManager, InitError> {
+        let (manager, init_receiver) = self.start();
+        PipeWireManager::_wait_for_init(&init_receiver)?;
+        Ok(manager)
+    }
+
+    fn start(
+        self,
+    ) -> (PipeWireManager, mpsc::Receiver<Result<(), InitError>>)
+    {
+        if let Some(level) = self.discovery_log_level {
+            utils::set_discovery_log_level(level);
+        }
+        let logger = self.logger.map(|logger| Logger {
+            level: self.logger_level.unwrap_or(logger.level),
+            ..logger
+        });
+        let (main_sender, main_receiver) = mpsc::channel::<Event>();
+        #[cfg(feature = "async")]
+        let (main_sender, event_streams) =
+            EventStreams::forward(main_sender);
+        let (main_sender, event_callbacks) =
+            EventCallbacks::forward(main_sender, logger.clone());
+        let main_sender = event::sequence(main_sender);
         let (pw_sender, pw_receiver) =
             channel::channel::<event::PipeWireEvent>();
         // Store nodes in thread-safe container
-        let nodes = Arc::new(RwLock::new(PipeWireObjects::default()));
+        let mut objects = PipeWireObjects::default();
+        objects.keep_nodes_sorted(self.sorted_nodes);
+        objects.channel_equalization = self.channel_equalization;
+        if let Some(capacity) = self.change_log_capacity {
+            objects.set_change_log_capacity(capacity);
+        }
+        let nodes = Arc::new(RwLock::new(objects));
         let event_locker = Arc::new(RwLock::new(()));
+        let (init_sender, init_receiver) = mpsc::sync_channel(1);
+        let running = Arc::new(AtomicBool::new(true));
+        let backend_options = BackendOptions {
+            auto_reconnect: self.auto_reconnect
+                || self.restore_links_on_reconnect,
+            restore_links_on_reconnect: self
+                .restore_links_on_reconnect,
+            retry_policy: self.retry_policy,
+            node_filter: self.node_filter,
+            realtime_priority: self.realtime_priority,
+            record_unknown_objects: self.record_unknown_objects,
+            logger,
+        };
 
-        Self {
+        let main_thread = PipeWireManager::_start_thread(
+            event_locker.clone(),
+            main_sender.clone(),
+            pw_receiver,
+            nodes.clone(),
+            backend_options.clone(),
+            init_sender,
+            RunningGuard(running.clone()),
+        );
+
+        let manager = PipeWireManager {
             objects: nodes.clone(),
-            _main_thread: Self::_start_thread(
-                event_locker.clone(),
-                main_sender,
-                pw_receiver,
-                nodes.clone(),
-            ),
+            _main_thread: RwLock::new(main_thread),
             _receiver: main_receiver,
-            _sender: pw_sender,
+            _sender: RwLock::new(pw_sender),
+            main_sender,
+            backend_options,
             _event_locker: event_locker,
-        }
+            retry_policy: self.retry_policy,
+            global_mute: RwLock::new(GlobalMute::default()),
+            #[cfg(feature = "async")]
+            event_streams,
+            event_callbacks,
+            running,
+            next_monitor_id: AtomicU32::new(0),
+        };
+        (manager, init_receiver)
     }
 }
 
 impl PipeWireManager {
+    /// Connect a manager with the default settings.
+    ///
+    /// Every error of the crate implements [`std::error::Error`], so
+    /// they can all be propagated with `?`:
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use easy_pw::manager::PipeWireManager;
+    ///
+    /// fn route(
+    ///     player: u32,
+    ///     speakers: u32,
+    /// ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ///     let manager = PipeWireManager::new()?;
+    ///     manager.link_stereo(player, speakers)?;
+    ///     manager.set_node_volume(speakers, 0.5)?;
+    ///     let timeout = Duration::from_secs(1);
+    ///     let link_ids =
+    ///         manager.wait_for_link_ids(player, speakers, timeout)?;
+    ///     for link_id in link_ids {
+    ///         manager.wait_for_link_active(link_id, timeout)?;
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn new() -> Result<Self, InitError> {
+        Self::builder().try_build()
+    }
+
+    pub fn builder() -> PipeWireManagerBuilder {
+        PipeWireManagerBuilder::default()
+    }
+
+    /// Wait for the backend thread to report whether it connected.
+    fn _wait_for_init(
+        init_receiver: &mpsc::Receiver<Result<(), InitError>>,
+    ) -> Result<(), InitError> {
+        init_receiver
+            .recv()
+            .unwrap_or(Err(InitError::BackendExited))
+    }
+
+    /// Create the main loop, the context, the connection and the
+    /// registry, reporting which step failed.
+    fn _connect() -> Result<
+        (
+            pw::main_loop::MainLoop,
+            pw::context::Context,
+            Core,
+            Registry,
+        ),
+        InitError,
+    > {
+        let mainloop = pw::main_loop::MainLoop::new(None)
+            .map_err(|e| InitError::MainLoopFailed(e.to_string()))?;
+        let context = pw::context::Context::new(&mainloop)
+            .map_err(|e| InitError::ContextFailed(e.to_string()))?;
+        let core = context
+            .connect(None)
+            .map_err(|e| InitError::ConnectFailed(e.to_string()))?;
+        let registry = core
+            .get_registry()
+            .map_err(|e| InitError::RegistryFailed(e.to_string()))?;
+        Ok((mainloop, context, core, registry))
+    }
+
     fn _start_thread(
         _event_locker: Arc<RwLock<()>>,
         _sender: mpsc::Sender<event::ConnectorEvent>,
         _receiver: channel::Receiver<event::PipeWireEvent>,
         objects: Arc<RwLock<PipeWireObjects>>,
+        options: BackendOptions,
+        init_sender: mpsc::SyncSender<Result<(), InitError>>,
+        running: RunningGuard,
     ) -> thread::JoinHandle<()> {
-        thread::spawn(move || {
+        Self::_spawn_backend_thread(move || {
+            let _running = running;
+            let _logger = logging::scope(options.logger.clone());
+            if let Some(priority) = options.realtime_priority {
+                match utils::set_realtime_priority(priority) {
+                    Ok(()) => log_debug!(
+                        "(Pipewire) Real-time priority set to {priority}"
+                    ),
+                    Err(e) => log_warn!(
+                        "(Pipewire) Keeping the default scheduling, real-time priority {priority} was refused: {e}"
+                    ),
+                }
+            }
             // Initialize PipeWire
             pw::init();
-            let mainloop = pw::main_loop::MainLoop::new(None)
-                .expect("Failed to create main loop");
-            let context = pw::context::Context::new(&mainloop)
-                .expect("Failed to create context");
-            let core = context
-                .connect(None)
-                .expect("Failed to connect to core");
-            let registry =
-                core.get_registry().expect("Failed to get registry");
+            let (mainloop, context, core, registry) =
+                match Self::_connect() {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        log_error!("(Pipewire) {e}");
+                        let _result = init_sender.send(Err(e));
+                        return;
+                    }
+                };
+            let _result = init_sender.send(Ok(()));
 
             // Clone for use in callback
-            let objects_clone = objects.clone();
-            let objects_clone_remove = objects.clone();
             let objects_clone_event = objects.clone();
 
             let _sender_arcmtx: Arc<
@@ -87,96 +558,527 @@ impl PipeWireManager {
             let registry_lock: Rc<RwLock<Registry>> =
                 Rc::new(RwLock::new(registry));
 
-            let registry_lock_read = registry_lock.read().unwrap();
-
-            let event_handler_sender = _sender_arcmtx.clone();
-            let event_remove_handler_sender = _sender_arcmtx.clone();
-            // Add registry listener
-            let _listener = registry_lock_read
-                .add_listener_local()
-                .global(move |global| {
-                    Self::_pw_event_handler(
-                        global,
-                        &objects_clone.clone(),
-                        event_handler_sender.clone(),
-                    )
-                })
-                .global_remove(move |object_id| {
-                    Self::_pw_remove_event_handler(
-                        object_id,
-                        &objects_clone_remove,
-                        event_remove_handler_sender.clone(),
-                    )
-                })
-                .register();
-
-            drop(registry_lock_read);
+            let proxies_lock: Rc<RwLock<BoundProxies>> =
+                Rc::new(RwLock::new(BoundProxies::default()));
 
             let manager_events_sender = _sender_arcmtx.clone();
+            let event_core_lock = core_lock.clone();
+            let event_registry_lock = registry_lock.clone();
+            let event_proxies_lock = proxies_lock.clone();
+            let event_mainloop = mainloop.downgrade();
+            let handle_event = move |event: &PipeWireEvent| {
+                let _sender_mtx =
+                    manager_events_sender.read().unwrap();
+                let objects = objects_clone_event.clone();
+                let core = event_core_lock.clone();
+                let event_result = event.handle(
+                    _event_locker.clone(),
+                    objects,
+                    core,
+                    manager_events_sender.clone(),
+                    event_registry_lock.clone(),
+                    event_proxies_lock.clone(),
+                    &options.retry_policy,
+                );
+                if let Ok(Some(response)) | Err(response) =
+                    event_result
+                {
+                    _sender_mtx.send(response).unwrap();
+                }
+            };
             let _receiver =
                 _receiver.attach(mainloop.loop_(), move |event| {
-                    let _sender_mtx = _sender_arcmtx.read().unwrap();
-                    let objects = objects_clone_event.clone();
-                    let core = core_lock.clone();
-                    let event_result = event.handle(
-                        _event_locker.clone(),
-                        objects,
-                        core,
-                        manager_events_sender.clone(),
-                        registry_lock.clone(),
-                    );
-                    if let Err(event_result) = event_result {
-                        _sender_mtx.send(event_result).unwrap();
+                    match event {
+                        PipeWireEvent::ShutdownCommand => {
+                            log_debug!("(Pipewire) Shutting down");
+                            if let Some(mainloop) =
+                                event_mainloop.upgrade()
+                            {
+                                mainloop.quit();
+                            }
+                        }
+                        // Handled within this callback, the registry
+                        // events wait for the next iteration
+                        PipeWireEvent::TransactionCommand(events) => {
+                            events.iter().for_each(&handle_event);
+                        }
+                        event => handle_event(&event),
                     }
                 });
 
-            // Process events to populate nodes
-            mainloop.run();
+            let disconnected = Rc::new(Cell::new(false));
+            let mut reconnected = false;
+            loop {
+                let _listeners = Self::_listen(
+                    &mainloop,
+                    &objects,
+                    &_sender_arcmtx,
+                    &core_lock,
+                    &registry_lock,
+                    &proxies_lock,
+                    &disconnected,
+                    reconnected && options.restore_links_on_reconnect,
+                    options.retry_policy,
+                    options.node_filter.clone(),
+                    options.record_unknown_objects,
+                );
+
+                // Process events to populate nodes
+                mainloop.run();
+
+                drop(_listeners);
+                if !disconnected.get() || !options.auto_reconnect {
+                    break;
+                }
+                disconnected.set(false);
+                reconnected = true;
+
+                log_warn!(
+                    "(Pipewire) Lost the connection, reconnecting"
+                );
+                *proxies_lock.write().unwrap() =
+                    BoundProxies::default();
+                objects.write().unwrap().clear_graph();
+                let (core, registry) =
+                    Self::_reconnect(&context, options.retry_policy);
+                *registry_lock.write().unwrap() = registry;
+                *core_lock.write().unwrap() = core;
+            }
         })
     }
+
+    fn _spawn_backend_thread(
+        backend: impl FnOnce() + Send + 'static,
+    ) -> thread::JoinHandle<()> {
+        thread::Builder::new()
+            .name(BACKEND_THREAD_NAME.to_owned())
+            .spawn(backend)
+            .expect("Failed to spawn the backend thread")
+    }
+
+    /// Listen to the registry and to the core of the current
+    /// connection, returning the listeners to keep alive.
+    #[allow(clippy::too_many_arguments)]
+    fn _listen(
+        mainloop: &pw::main_loop::MainLoop,
+        objects: &Arc<RwLock<PipeWireObjects>>,
+        sender: &Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
+        core_lock: &Rc<RwLock<Core>>,
+        registry_lock: &Rc<RwLock<Registry>>,
+        proxies_lock: &Rc<RwLock<BoundProxies>>,
+        disconnected: &Rc<Cell<bool>>,
+        restore_links: bool,
+        retry_policy: RetryPolicy,
+        node_filter: Option<NodeFilter>,
+        record_unknown_objects: bool,
+    ) -> (pw::registry::Listener, pw::core::Listener) {
+        let objects_clone = objects.clone();
+        let objects_clone_remove = objects.clone();
+        let event_handler_sender = sender.clone();
+        let event_remove_handler_sender = sender.clone();
+        let bind_registry = registry_lock.clone();
+        let bind_proxies = proxies_lock.clone();
+        let remove_proxies = proxies_lock.clone();
+        // Nodes to check against the link rules, once their ports
+        // were announced too
+        let rule_nodes: Rc<RefCell<HashSet<u32>>> = Rc::default();
+        let rule_seq = Rc::new(Cell::new(None));
+        let global_rule_nodes = rule_nodes.clone();
+        let global_rule_seq = rule_seq.clone();
+        let global_core = core_lock.clone();
+
+        let registry_lock_read = registry_lock.read().unwrap();
+        // Add registry listener
+        let registry_listener = registry_lock_read
+            .add_listener_local()
+            .global(move |global| {
+                if Self::_is_cancelled_link(global, &objects_clone) {
+                    log_debug!(
+                        "(Pipewire) Link {} was unlinked before being announced, destroying it",
+                        global.id
+                    );
+                    block_on(Link::remove_link(
+                        global.id,
+                        bind_registry.clone(),
+                    ));
+                    return;
+                }
+                let tracked = Self::_pw_event_handler(
+                    global,
+                    &objects_clone.clone(),
+                    event_handler_sender.clone(),
+                    node_filter.as_ref(),
+                    record_unknown_objects,
+                );
+                if tracked {
+                    bind_proxies.write().unwrap().bind(
+                        &bind_registry.read().unwrap(),
+                        global,
+                        &objects_clone,
+                        &event_handler_sender,
+                    );
+                }
+                if tracked
+                    && global.type_ == pw::types::ObjectType::Node
+                    && !objects_clone
+                        .read()
+                        .unwrap()
+                        .link_rules
+                        .is_empty()
+                {
+                    global_rule_nodes.borrow_mut().insert(global.id);
+                    // The ports of the node are announced before the
+                    // server answers
+                    global_rule_seq.set(
+                        global_core.read().unwrap().sync(0).ok(),
+                    );
+                }
+            })
+            .global_remove(move |object_id| {
+                remove_proxies.write().unwrap().remove(object_id);
+                Self::_pw_remove_event_handler(
+                    object_id,
+                    &objects_clone_remove,
+                    event_remove_handler_sender.clone(),
+                )
+            })
+            .register();
+        drop(registry_lock_read);
+
+        let core = core_lock.read().unwrap();
+        // Once the server answers this sync, every object that
+        // existed when connecting has been announced
+        let ready_seq = core.sync(0).ok();
+        let ready_objects = objects.clone();
+        let ready_sender = sender.clone();
+        let weak_mainloop = mainloop.downgrade();
+        let disconnected = disconnected.clone();
+        let restore_objects = objects.clone();
+        let restore_core = core_lock.clone();
+        let restore_sender = sender.clone();
+        let error_sender = sender.clone();
+        let rule_objects = objects.clone();
+        let rule_core = core_lock.clone();
+        let rule_sender = sender.clone();
+        let info_objects = objects.clone();
+        let core_listener = core
+            .add_listener_local()
+            .info(move |info| {
+                log_debug!(
+                    "(Pipewire) Connected to {} {}",
+                    info.name(),
+                    info.version()
+                );
+                info_objects.write().unwrap().server_info =
+                    Some(ServerInfo::from(info));
+            })
+            .error(move |id, _seq, res, message| {
+                if id != pw::core::PW_ID_CORE || res != -libc::EPIPE {
+                    return;
+                }
+                event::report_error(
+                    &error_sender.read().unwrap(),
+                    format!("(Pipewire) Disconnected: {message}"),
+                );
+                disconnected.set(true);
+                if let Some(mainloop) = weak_mainloop.upgrade() {
+                    mainloop.quit();
+                }
+            })
+            .done(move |id, seq| {
+                if id == pw::core::PW_ID_CORE
+                    && Some(seq) == ready_seq
+                {
+                    Self::_set_ready(&ready_objects);
+                    let _result = ready_sender
+                        .read()
+                        .unwrap()
+                        .send(ConnectorEvent::Ready);
+                }
+                if id == pw::core::PW_ID_CORE
+                    && Some(seq) == ready_seq
+                    && restore_links
+                {
+                    Self::_restore_routes(
+                        &restore_objects,
+                        event::link_through(
+                            &restore_core,
+                            retry_policy,
+                        ),
+                        &restore_sender,
+                    );
+                }
+                if id == pw::core::PW_ID_CORE
+                    && Some(seq) == rule_seq.get()
+                {
+                    let added: Vec<u32> =
+                        rule_nodes.borrow_mut().drain().collect();
+                    Self::_apply_link_rules(
+                        &rule_objects,
+                        event::link_through(&rule_core, retry_policy),
+                        &rule_sender,
+                        &added,
+                    );
+                }
+            })
+            .register();
+        (registry_listener, core_listener)
+    }
+
+    /// Connect again, waiting between attempts as described by the
+    /// retry policy and then by its longest delay.
+    fn _reconnect(
+        context: &pw::context::Context,
+        retry_policy: RetryPolicy,
+    ) -> (Core, Registry) {
+        let mut delays = retry_policy.delays();
+        loop {
+            let delay =
+                delays.next().unwrap_or(retry_policy.max_delay);
+            thread::sleep(delay);
+            let connection = context.connect(None).and_then(|core| {
+                let registry = core.get_registry()?;
+                Ok((core, registry))
+            });
+            match connection {
+                Ok(connection) => {
+                    log_info!("(Pipewire) Reconnected");
+                    return connection;
+                }
+                Err(e) => log_debug!("Failed to reconnect: {e}"),
+            }
+        }
+    }
+
+    /// Link again the nodes and ports linked through the manager
+    /// before the connection was lost.
+    fn _restore_routes(
+        objects: &Arc<RwLock<PipeWireObjects>>,
+        link_ports: impl Fn(&Port, &Port) -> Result<(), PortError>,
+        sender: &Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
+    ) {
+        let routes = objects.read().unwrap().routes_to_restore();
+        for (output_node, input_node, strategy) in routes {
+            log_info!(
+                "Restoring the link between nodes {output_node} and {input_node}"
+            );
+            if let Err(e) = PipeWireEvent::_link_command(
+                objects.clone(),
+                output_node,
+                input_node,
+                strategy,
+                &link_ports,
+            ) {
+                event::report_error(
+                    &sender.read().unwrap(),
+                    format!("Failed to restore link: {e}"),
+                );
+                continue;
+            }
+            let _result = sender.read().unwrap().send(
+                ConnectorEvent::LinkUpdate(output_node, input_node),
+            );
+        }
+        let pairs = objects.read().unwrap().port_routes_to_restore();
+        for ((output_node, output_port), (input_node, input_port)) in
+            pairs
+        {
+            log_info!(
+                "Restoring the link between ports {output_port} and {input_port}"
+            );
+            let result = {
+                let objects = objects.read().unwrap();
+                match (
+                    objects.find_port_by_id(output_port),
+                    objects.find_port_by_id(input_port),
+                ) {
+                    (Some(output), Some(input)) => {
+                        link_ports(output, input)
+                            .map_err(|e| e.to_string())
+                    }
+                    _ => Err("port not found".to_owned()),
+                }
+            };
+            if let Err(e) = result {
+                event::report_error(
+                    &sender.read().unwrap(),
+                    format!("Failed to restore link: {e}"),
+                );
+                continue;
+            }
+            objects.write().unwrap().insert_pending_link(
+                Link::pending(
+                    (output_node, output_port),
+                    (input_node, input_port),
+                ),
+            );
+            let _result = sender.read().unwrap().send(
+                ConnectorEvent::LinkUpdate(output_node, input_node),
+            );
+        }
+    }
+
+    /// Create the links the link rules ask for now that the nodes
+    /// were added.
+    fn _apply_link_rules(
+        objects: &Arc<RwLock<PipeWireObjects>>,
+        link_ports: impl Fn(&Port, &Port) -> Result<(), PortError>,
+        sender: &Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
+        added: &[u32],
+    ) {
+        let links = objects.read().unwrap().links_for_rules(added);
+        for (output_node, input_node, strategy) in links {
+            log_info!(
+                "Linking nodes {output_node} and {input_node} following a link rule"
+            );
+            if let Err(e) = PipeWireEvent::_link_command(
+                objects.clone(),
+                output_node,
+                input_node,
+                strategy,
+                &link_ports,
+            ) {
+                event::report_error(
+                    &sender.read().unwrap(),
+                    format!("Failed to apply a link rule: {e}"),
+                );
+                continue;
+            }
+            let _result = sender.read().unwrap().send(
+                ConnectorEvent::LinkUpdate(output_node, input_node),
+            );
+        }
+    }
+
+    /// Store the announced object, returning whether it is tracked
+    /// or was left out by the node filter.
     fn _pw_event_handler(
         global: &GlobalObject<&DictRef>,
         objects: &Arc<RwLock<PipeWireObjects>>,
         _sender: Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
-    ) {
+        node_filter: Option<&NodeFilter>,
+        record_unknown_objects: bool,
+    ) -> bool {
         // Filter by only node ones
         let mut objects_guard = objects.write().unwrap();
         let mut _sender_guard = _sender.read().unwrap();
         match global.type_ {
             pw::types::ObjectType::Node => {
-                let node = Node::new(global);
-                objects_guard.nodes.push(node);
+                if node_filter.is_some_and(|f| !f.admits(global)) {
+                    objects_guard.filtered_nodes.insert(global.id);
+                    return false;
+                }
+                let Some(node) = Node::new(global) else {
+                    event::report_error(
+                        &_sender_guard,
+                        format!(
+                            "Node {} has no properties or object.serial, ignoring it",
+                            global.id
+                        ),
+                    );
+                    return false;
+                };
+                if objects_guard
+                    .find_node_by_exact_id(global.id)
+                    .is_some()
+                {
+                    // The id was reused before the removal of the
+                    // previous node reached us
+                    log_warn!(
+                        "Node {} was announced again, replacing it",
+                        global.id
+                    );
+                    objects_guard.remove_node(global.id);
+                }
+                objects_guard.insert_node(node);
             }
             pw::types::ObjectType::Port => {
                 let port = Port::new(global);
+                if port.node_id.is_none() {
+                    event::report_error(
+                        &_sender_guard,
+                        format!(
+                            "Port {} has an invalid node.id, ignoring it",
+                            port.id
+                        ),
+                    );
+                    return false;
+                }
+                if port.node_id.is_some_and(|node_id| {
+                    objects_guard.filtered_nodes.contains(&node_id)
+                }) {
+                    return false;
+                }
                 objects_guard._ports_to_be_added.push(port);
-                log::debug!(
+                discovery_debug!(
                     "(Pipewire)Received PORT event: {:?} \n{:#?}",
                     global,
                     global.props
                 );
             }
             pw::types::ObjectType::Link => {
-                let link = Link::new(global);
-                log::debug!(
+                let link = match Link::new(global) {
+                    Ok(link) => link,
+                    Err(e) => {
+                        log_warn!("{e}, ignoring it");
+                        return false;
+                    }
+                };
+                discovery_debug!(
                     "(Pipewire) Received LINK event: {:?} \n{:#?}",
                     global,
                     global.props
                 );
                 let first_id = link.output_node;
                 let second_id = link.input_node;
-                objects_guard.links.push(link);
-                let _result = _sender_guard.send(
-                    ConnectorEvent::LinkUpdate(first_id, second_id),
-                );
+                if objects_guard.filtered_nodes.contains(&first_id)
+                    || objects_guard
+                        .filtered_nodes
+                        .contains(&second_id)
+                {
+                    return false;
+                }
+                // Links created by the manager were already reported
+                let link_id = link.id;
+                if objects_guard.insert_link(link) {
+                    let _result = _sender_guard
+                        .send(ConnectorEvent::LinkAnnounced(link_id));
+                } else {
+                    let _result = _sender_guard.send(
+                        ConnectorEvent::LinkUpdate(
+                            first_id, second_id,
+                        ),
+                    );
+                }
             }
             _ => {
-                log::debug!("(Pipewire)Received non-handled event: {:?} \n{:#?}", global.type_, global.props);
+                discovery_debug!("(Pipewire)Received non-handled event: {:?} \n{:#?}", global.type_, global.props);
+                if record_unknown_objects {
+                    objects_guard
+                        .other_objects
+                        .push(OtherObject::new(global));
+                }
                 let _result =
                     _sender_guard.send(ConnectorEvent::None);
             }
         }
-        objects_guard.update_nodes();
+        for node_id in objects_guard.update_nodes() {
+            let _result = _sender_guard
+                .send(ConnectorEvent::NodePortsComplete(node_id));
+        }
+        true
+    }
+
+    /// Whether the global is a link the manager created and then
+    /// unlinked while it was pending.
+    fn _is_cancelled_link(
+        global: &GlobalObject<&DictRef>,
+        objects: &Arc<RwLock<PipeWireObjects>>,
+    ) -> bool {
+        global.type_ == pw::types::ObjectType::Link
+            && Link::new(global).is_ok_and(|link| {
+                objects.write().unwrap().take_cancelled_link(&link)
+            })
     }
 
     fn _pw_remove_event_handler(
@@ -185,15 +1087,24 @@ impl PipeWireManager {
         _sender: Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
     ) {
         let mut objs = objects.write().unwrap();
+        if objs.filtered_nodes.remove(&object_id) {
+            return;
+        }
+        objs.other_objects.retain(|other| other.id != object_id);
+        if objs.metadata.global_id == Some(object_id) {
+            // Its properties went away with it
+            objs.metadata = MetadataStore::default();
+            return;
+        }
         PipeWireManager::remove_object(&mut objs, object_id, _sender);
     }
 
     fn _raise_event(&self, event: PipeWireEvent) {
         let event_info = event.to_string();
-        if let Err(e) = self._sender.send(event) {
-            log::error!("Failed to send event: {e:?}");
+        if let Err(e) = self._sender.read().unwrap().send(event) {
+            log_error!("Failed to send event: {e:?}");
         }
-        log::debug!("Event raised: {event_info:?}");
+        log_debug!("Event raised: {event_info:?}");
         let _thread_locker = self._event_locker.read().unwrap();
     }
 
@@ -204,16 +1115,25 @@ impl PipeWireManager {
     ) {
         if objects.find_linked_nodes_by_link_id_mut(obj_id).is_some()
         {
-            let link =
-                block_on(objects.remove_link(obj_id, None, _sender));
+            let link = block_on(objects.remove_link(
+                obj_id,
+                None,
+                _sender.clone(),
+            ));
             if let Err(err) = link {
-                log::error!("Failed to remove link: {err}");
+                event::report_error(
+                    &_sender.read().unwrap(),
+                    format!("Failed to remove link: {err}"),
+                );
                 return;
             }
         }
-        if let Some(node) = objects.find_node_by_id(obj_id) {
-            objects.remove_node(node.id);
+        // Port ids would otherwise resolve to their node
+        if objects.remove_port(obj_id).is_some() {
+            return;
         }
+        objects._ports_to_be_added.retain(|port| port.id != obj_id);
+        objects.remove_node(obj_id);
     }
 
     /// Create a link between two nodes
@@ -224,71 +1144,4497 @@ impl PipeWireManager {
         first_node_id: u32,
         second_node_id: u32,
     ) {
-        self._raise_event(PipeWireEvent::LinkCommand(
+        self.link_nodes_with_strategy(
             first_node_id,
             second_node_id,
-        ));
-        self.wait_for_event(|event: &ConnectorEvent| {
-            *event
-                == ConnectorEvent::LinkUpdate(
-                    first_node_id,
-                    second_node_id,
-                )
-                || *event
-                    == ConnectorEvent::LinkFailed(
-                        first_node_id,
-                        second_node_id,
-                    )
-        });
+            LinkStrategy::default(),
+        );
     }
 
-    /// Get the first link between two nodes and remove it
-    pub fn unlink_nodes(
+    /// Create a link between two nodes, pairing their ports as
+    /// described by `strategy`
+    ///
+    /// Nodes that were just created may not be known yet, or may
+    /// still lack their ports; linking them is retried following the
+    /// manager's [`RetryPolicy`].
+    pub fn link_nodes_with_strategy(
         &self,
         first_node_id: u32,
         second_node_id: u32,
+        strategy: LinkStrategy,
     ) {
-        self._raise_event(PipeWireEvent::UnlinkCommand(
+        let _result = self.try_link_nodes_with_strategy(
             first_node_id,
             second_node_id,
-        ));
-        log::debug!("waiting!");
+            strategy,
+        );
+    }
 
-        self.wait_for_event(|event: &ConnectorEvent| {
-            *event
-                == ConnectorEvent::UnlinkUpdate(
+    /// Same as [`Self::link_nodes_with_strategy`], reporting whether
+    /// the nodes were linked.
+    fn try_link_nodes_with_strategy(
+        &self,
+        first_node_id: u32,
+        second_node_id: u32,
+        strategy: LinkStrategy,
+    ) -> Result<(), LinkError> {
+        let _logger = self._log_scope();
+        self.check_not_dispatching()?;
+        let link_failed =
+            LinkError::LinkFailed(first_node_id, second_node_id);
+        let mut delays = self.retry_policy.delays();
+        loop {
+            self._raise_event(PipeWireEvent::LinkCommand(
+                first_node_id,
+                second_node_id,
+                strategy,
+            ));
+            let event =
+                self.wait_for_event(|event: &ConnectorEvent| {
+                    *event
+                        == ConnectorEvent::LinkUpdate(
+                            first_node_id,
+                            second_node_id,
+                        )
+                        || *event
+                            == ConnectorEvent::LinkFailed(
+                                first_node_id,
+                                second_node_id,
+                            )
+                })?;
+            if event
+                != ConnectorEvent::LinkFailed(
                     first_node_id,
                     second_node_id,
                 )
-                || *event
-                    == ConnectorEvent::UnLinkFailed(
-                        first_node_id,
-                        second_node_id,
-                    )
-        });
+            {
+                return Ok(());
+            }
+            if !self.is_link_failure_transient(
+                first_node_id,
+                second_node_id,
+            ) {
+                return Err(link_failed);
+            }
+            let Some(delay) = delays.next() else {
+                return Err(link_failed);
+            };
+            log_debug!("Retrying to link nodes {first_node_id} and {second_node_id} in {delay:?}");
+            thread::sleep(delay);
+        }
     }
 
-    fn wait_for_event<F: Fn(&ConnectorEvent) -> bool>(
+    /// Link and unlink nodes in one go, e.g. to swap the source of a
+    /// recorder without another client's change landing in between.
+    ///
+    /// `f` only queues the operations on the [`Transaction`]; once it
+    /// returns, they are sent together and the backend handles them
+    /// one after the other before processing any registry event.
+    /// Returns what `f` returned, or the first operation that failed,
+    /// the following ones being applied anyway. Unlike
+    /// [`Self::link_nodes`], linking is not retried while the ports
+    /// of the nodes are still being announced.
+    ///
+    /// The backend does nothing else while the transaction is
+    /// handled, so transactions should stay short. Like any other
+    /// call waiting for the backend, this fails with
+    /// [`LinkError::ReentrantCall`] when called from a callback
+    /// running on the backend thread, such as
+    /// [`Self::on_default_changed`].
+    pub fn transaction<R>(
         &self,
-        checker: F,
-    ) {
-        let mut event_result: ConnectorEvent = ConnectorEvent::None;
-        // Lock the thread and wait for the event to be processed
-        while !checker(&event_result) {
-            let result = self._receiver.try_recv();
+        f: impl FnOnce(&Transaction) -> R,
+    ) -> Result<R, LinkError> {
+        let transaction = Transaction::default();
+        let result = f(&transaction);
+        let operations = transaction.into_operations();
+        if operations.is_empty() {
+            return Ok(result);
+        }
+        self.check_not_dispatching()?;
+        let responses: Vec<(u32, u32, bool)> = operations
+            .iter()
+            .filter_map(|operation| match *operation {
+                PipeWireEvent::LinkCommand(out_node, in_node, _) => {
+                    Some((out_node, in_node, true))
+                }
+                PipeWireEvent::UnlinkCommand(out_node, in_node) => {
+                    Some((out_node, in_node, false))
+                }
+                _ => None,
+            })
+            .collect();
+        self._raise_event(PipeWireEvent::TransactionCommand(
+            operations,
+        ));
 
-            if let Err(e) = result {
-                if e == TryRecvError::Disconnected {
-                    log::error!("Failed to receive event: {e}");
+        let mut first_error = None;
+        for (out_node, in_node, link) in responses {
+            let outcome = if link {
+                let event = self.wait_for_event(|event| {
+                    *event
+                        == ConnectorEvent::LinkUpdate(
+                            out_node, in_node,
+                        )
+                        || *event
+                            == ConnectorEvent::LinkFailed(
+                                out_node, in_node,
+                            )
+                })?;
+                match event {
+                    ConnectorEvent::LinkFailed(..) => {
+                        Err(LinkError::LinkFailed(out_node, in_node))
+                    }
+                    _ => Ok(()),
                 }
-                continue;
+            } else {
+                let event = self.wait_for_event(|event| {
+                    Self::is_unlink_response(event, out_node, in_node)
+                })?;
+                Self::unlink_result(event, out_node, in_node)
+            };
+            if let Err(e) = outcome {
+                first_error.get_or_insert(e);
             }
-            event_result = result.unwrap();
         }
-        log::debug!("(Connector) Received event: {event_result:?}")
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(result),
+        }
     }
 
-    pub fn get_objects(&self) -> Arc<RwLock<PipeWireObjects>> {
-        self.objects.clone()
+    /// Create the links between the nodes that linking them would
+    /// and that are missing, returning whether links were created.
+    /// Unlike [`Self::link_nodes`], calling it again does not create
+    /// duplicate links, and a node only partly linked, e.g. a single
+    /// channel of two, gets the other links. This makes it suited to
+    /// converge the graph towards a desired state.
+    pub fn ensure_linked(
+        &self,
+        out_node: u32,
+        in_node: u32,
+    ) -> Result<bool, LinkError> {
+        self.check_not_dispatching()?;
+        let missing = self
+            .objects
+            .read()
+            .unwrap()
+            .missing_links(out_node, in_node)?;
+        for &(out_port, in_port) in &missing {
+            self.link_port_ids(
+                (out_node, out_port),
+                (in_node, in_port),
+                LinkOptions::default(),
+            )?;
+        }
+        Ok(!missing.is_empty())
+    }
+
+    /// Link the nodes, then set the volume of the output node, e.g.
+    /// to route a source into a recorder at a given level.
+    ///
+    /// An invalid volume fails before linking anything. If the nodes
+    /// were linked but the volume could not be set, the link is kept
+    /// and [`LinkError::LinkedButVolumeFailed`] is returned.
+    pub fn link_and_set_volume(
+        &self,
+        out_node: u32,
+        in_node: u32,
+        volume: f32,
+    ) -> Result<(), LinkError> {
+        let volume = validate_volume(volume)?;
+        self.try_link_nodes_with_strategy(
+            out_node,
+            in_node,
+            LinkStrategy::default(),
+        )?;
+        self.set_node_volume(out_node, volume).map_err(|e| {
+            LinkError::LinkedButVolumeFailed(out_node, in_node, e)
+        })
+    }
+
+    /// Move the output of a node from one sink to another, e.g. an
+    /// application from the speakers to headphones, without a gap.
+    ///
+    /// The node is linked into `to_sink` first, and only unlinked
+    /// from `from_sink` once every new link is active, so audio keeps
+    /// flowing to one of them during the move. If the new links fail
+    /// or do not become active in time, they are removed again and
+    /// the node is left playing into `from_sink`.
+    pub fn move_node_output(
+        &self,
+        node_id: u32,
+        from_sink: u32,
+        to_sink: u32,
+    ) -> Result<(), LinkError> {
+        let _logger = self._log_scope();
+        let existing = self
+            .objects
+            .read()
+            .unwrap()
+            .find_link_ids_between(node_id, to_sink);
+        self.try_link_nodes_with_strategy(
+            node_id,
+            to_sink,
+            LinkStrategy::default(),
+        )?;
+        if let Err(e) = self.wait_for_links_active(
+            node_id,
+            to_sink,
+            MOVE_ACTIVATION_TIMEOUT,
+        ) {
+            log_error!("Not moving node {node_id} to {to_sink}: {e}");
+            // Only the links made for the move are removed
+            let created: Vec<u32> = {
+                let mut objects = self.objects.write().unwrap();
+                objects.cancel_pending_links(node_id, to_sink);
+                objects
+                    .find_link_ids_between(node_id, to_sink)
+                    .into_iter()
+                    .filter(|id| !existing.contains(id))
+                    .collect()
+            };
+            self.remove_links(created)?;
+            return Err(e);
+        }
+        self.try_unlink_nodes(node_id, from_sink)
+    }
+
+    /// Block until the output node is linked into the input node and
+    /// every one of these links is active.
+    fn wait_for_links_active(
+        &self,
+        out_node: u32,
+        in_node: u32,
+        timeout: Duration,
+    ) -> Result<(), LinkError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let states: Vec<(u32, LinkState)> = {
+                let objects = self.objects.read().unwrap();
+                objects
+                    .find_link_ids_between(out_node, in_node)
+                    .into_iter()
+                    .filter_map(|id| {
+                        objects
+                            .find_links_by_id(id)
+                            .map(|link| (id, link.state().clone()))
+                    })
+                    .collect()
+            };
+            if let Some((id, LinkState::Error(error))) =
+                states.iter().find(|(_, state)| {
+                    matches!(state, LinkState::Error(_))
+                })
+            {
+                return Err(WaitError::LinkFailed(
+                    *id,
+                    error.clone(),
+                )
+                .into());
+            }
+            if !states.is_empty()
+                && states
+                    .iter()
+                    .all(|(_, state)| *state == LinkState::Active)
+            {
+                return Ok(());
+            }
+
+            let remaining =
+                deadline.saturating_duration_since(Instant::now());
+            let event = self.wait_for_event_timeout(
+                |event: &ConnectorEvent| {
+                    matches!(
+                        event,
+                        ConnectorEvent::LinkStateChanged(..)
+                    )
+                },
+                remaining,
+            )?;
+            if event.is_none() {
+                return Err(LinkError::Timeout(out_node, in_node));
+            }
+        }
+    }
+
+    /// Whether linking the nodes failed because they, or their
+    /// ports, were not announced yet.
+    fn is_link_failure_transient(
+        &self,
+        first_node_id: u32,
+        second_node_id: u32,
+    ) -> bool {
+        matches!(
+            self.can_link(first_node_id, second_node_id),
+            Err(LinkError::NodeNotFound(_))
+                | Err(LinkError::Node(
+                    NodeError::IncorrectTypeOfChannelDirection(..)
+                ))
+        )
+    }
+
+    /// Check whether `link_nodes` would accept linking the output
+    /// node into the input node, without creating anything.
+    /// Useful to show whether a connection is valid before making it.
+    pub fn can_link(
+        &self,
+        out_node: u32,
+        in_node: u32,
+    ) -> Result<(), LinkError> {
+        self.objects.read().unwrap().can_link(out_node, in_node)
+    }
+
+    /// The nodes the output node can be linked into.
+    /// See [`PipeWireObjects::valid_targets_for`].
+    pub fn valid_targets_for(&self, output_node: u32) -> Vec<u32> {
+        self.objects.read().unwrap().valid_targets_for(output_node)
+    }
+
+    /// How many links go from the output node into the input node,
+    /// one per linked channel.
+    /// See [`PipeWireObjects::link_count_between`].
+    pub fn link_count_between(
+        &self,
+        out_node: u32,
+        in_node: u32,
+    ) -> usize {
+        self.objects
+            .read()
+            .unwrap()
+            .link_count_between(out_node, in_node)
+    }
+
+    /// Whether at least one link goes from the output node into the
+    /// input node.
+    /// See [`PipeWireObjects::is_linked`].
+    pub fn is_linked(&self, out_node: u32, in_node: u32) -> bool {
+        self.objects.read().unwrap().is_linked(out_node, in_node)
+    }
+
+    /// Link a single port of a node into a port of another node,
+    /// resolving both ports by their names (e.g. "capture_AUX0"
+    /// into "playback_FL").
+    pub fn link_ports_by_name(
+        &self,
+        out_node: u32,
+        out_port_name: &str,
+        in_node: u32,
+        in_port_name: &str,
+    ) -> Result<(), LinkError> {
+        self.link_ports_by_name_with_options(
+            out_node,
+            out_port_name,
+            in_node,
+            in_port_name,
+            LinkOptions::default(),
+        )
+    }
+
+    /// Same as [`Self::link_ports_by_name`], making the link as
+    /// described by `options`, e.g. a passive link for a monitoring
+    /// tap.
+    pub fn link_ports_by_name_with_options(
+        &self,
+        out_node: u32,
+        out_port_name: &str,
+        in_node: u32,
+        in_port_name: &str,
+        options: LinkOptions,
+    ) -> Result<(), LinkError> {
+        let (out_port, in_port) = {
+            let objects = self.objects.read().unwrap();
+            (
+                objects
+                    .find_port_by_name(out_node, out_port_name)?
+                    .id,
+                objects.find_port_by_name(in_node, in_port_name)?.id,
+            )
+        };
+        self.link_port_ids(
+            (out_node, out_port),
+            (in_node, in_port),
+            options,
+        )
+    }
+
+    /// Link the FL output port of a node into the FL input port of
+    /// another, and FR into FR, failing if either node is not
+    /// stereo.
+    pub fn link_stereo(
+        &self,
+        out_node: u32,
+        in_node: u32,
+    ) -> Result<(), LinkError> {
+        let pairs = {
+            let objects = self.objects.read().unwrap();
+            let stereo_ports = |node_id, direction: PortDirection| {
+                let node = objects
+                    .find_node_by_exact_id(node_id)
+                    .ok_or(LinkError::NodeNotFound(node_id))?;
+                match (
+                    node.port(direction.clone(), &AudioChannel::FL),
+                    node.port(direction.clone(), &AudioChannel::FR),
+                ) {
+                    (Some(left), Some(right)) => {
+                        Ok((left.id, right.id))
+                    }
+                    _ => {
+                        Err(LinkError::NotStereo(node_id, direction))
+                    }
+                }
+            };
+            let (out_left, out_right) =
+                stereo_ports(out_node, PortDirection::Out)?;
+            let (in_left, in_right) =
+                stereo_ports(in_node, PortDirection::In)?;
+            [(out_left, in_left), (out_right, in_right)]
+        };
+        for (out_port, in_port) in pairs {
+            self.link_port_ids(
+                (out_node, out_port),
+                (in_node, in_port),
+                LinkOptions::default(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Link the output ports of a node into the input ports of
+    /// another as described by `mapping`, every `(output channel,
+    /// input channel)` pair linking one port into another, e.g.
+    /// `[(FL, FR), (FR, FL)]` to swap the left and right channels.
+    /// Returns the result of every pair, in order.
+    pub fn link_with_mapping(
+        &self,
+        out_node: u32,
+        in_node: u32,
+        mapping: &[(AudioChannel, AudioChannel)],
+    ) -> Vec<Result<(), LinkError>> {
+        let ports = self
+            .objects
+            .read()
+            .unwrap()
+            .resolve_channel_mapping(out_node, in_node, mapping);
+        ports
+            .into_iter()
+            .map(|ports| {
+                let (out_port, in_port) = ports?;
+                self.link_port_ids(
+                    (out_node, out_port),
+                    (in_node, in_port),
+                    LinkOptions::default(),
+                )
+            })
+            .collect()
+    }
+
+    /// Link the output port into the input port, `(node, port)`
+    /// pairs.
+    fn link_port_ids(
+        &self,
+        (out_node, out_port): (u32, u32),
+        (in_node, in_port): (u32, u32),
+        options: LinkOptions,
+    ) -> Result<(), LinkError> {
+        self.check_not_dispatching()?;
+        self._raise_event(PipeWireEvent::LinkPortsCommand(
+            out_node, out_port, in_node, in_port, options,
+        ));
+        // Another link between the same nodes must not be mistaken
+        // for this one
+        let event =
+            self.wait_for_event(|event: &ConnectorEvent| {
+                *event
+                    == ConnectorEvent::PortsLinked(out_port, in_port)
+                    || *event
+                        == ConnectorEvent::PortsLinkFailed(
+                            out_port, in_port,
+                        )
+            })?;
+        match event {
+            ConnectorEvent::PortsLinkFailed(..) => {
+                Err(LinkError::LinkFailed(out_node, in_node))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove every link between two nodes
+    pub fn unlink_nodes(
+        &self,
+        first_node_id: u32,
+        second_node_id: u32,
+    ) {
+        let _logger = self._log_scope();
+        if let Err(e) =
+            self.try_unlink_nodes(first_node_id, second_node_id)
+        {
+            log_error!("{e}");
+        }
+    }
+
+    /// Remove every link between two nodes, reporting whether the
+    /// backend failed to do so.
+    /// Unlinking nodes that are not linked is not an error.
+    pub fn try_unlink_nodes(
+        &self,
+        first_node_id: u32,
+        second_node_id: u32,
+    ) -> Result<(), LinkError> {
+        let _logger = self._log_scope();
+        self.check_not_dispatching()?;
+        self._raise_event(PipeWireEvent::UnlinkCommand(
+            first_node_id,
+            second_node_id,
+        ));
+        log_debug!("waiting!");
+
+        let event =
+            self.wait_for_event(|event: &ConnectorEvent| {
+                Self::is_unlink_response(
+                    event,
+                    first_node_id,
+                    second_node_id,
+                )
+            })?;
+        Self::unlink_result(event, first_node_id, second_node_id)
+    }
+
+    /// Same as [`Self::try_unlink_nodes`], but gives up waiting for
+    /// the backend after `timeout`.
+    pub fn try_unlink_nodes_timeout(
+        &self,
+        first_node_id: u32,
+        second_node_id: u32,
+        timeout: Duration,
+    ) -> Result<(), LinkError> {
+        self.check_not_dispatching()?;
+        self._raise_event(PipeWireEvent::UnlinkCommand(
+            first_node_id,
+            second_node_id,
+        ));
+
+        let event = self.wait_for_event_timeout(
+            |event: &ConnectorEvent| {
+                Self::is_unlink_response(
+                    event,
+                    first_node_id,
+                    second_node_id,
+                )
+            },
+            timeout,
+        )?;
+        match event {
+            Some(event) => Self::unlink_result(
+                event,
+                first_node_id,
+                second_node_id,
+            ),
+            None => {
+                Err(LinkError::Timeout(first_node_id, second_node_id))
+            }
+        }
+    }
+
+    /// Remove the links between the nodes if there are any,
+    /// returning whether links were removed. The dual of
+    /// [`Self::ensure_linked`]: unlike [`Self::try_unlink_nodes`],
+    /// nodes that are not linked are told apart from removed links.
+    pub fn ensure_unlinked(
+        &self,
+        out_node: u32,
+        in_node: u32,
+    ) -> Result<bool, LinkError> {
+        if !self.is_linked(out_node, in_node) {
+            return Ok(false);
+        }
+        self.check_not_dispatching()?;
+        self._raise_event(PipeWireEvent::UnlinkCommand(
+            out_node, in_node,
+        ));
+        let event =
+            self.wait_for_event(|event: &ConnectorEvent| {
+                Self::is_unlink_response(event, out_node, in_node)
+            })?;
+        // The links may have been removed by someone else meanwhile
+        let removed = event
+            != ConnectorEvent::NothingToUnlink(out_node, in_node);
+        Self::unlink_result(event, out_node, in_node)?;
+        Ok(removed)
+    }
+
+    /// Converge the links of the graph towards `desired`, a set of
+    /// `(output_node, input_node)` pairs: the missing pairs are
+    /// linked with [`Self::ensure_linked`] and every other pair of
+    /// linked nodes is unlinked with [`Self::ensure_unlinked`].
+    ///
+    /// Extra links are removed first, so that the desired links
+    /// cannot be refused for closing a cycle. See
+    /// [`Self::reconcile_managed_links`] to leave alone the links
+    /// the manager did not make.
+    pub fn reconcile_links(
+        &self,
+        desired: &[(u32, u32)],
+    ) -> ReconcileReport {
+        self.reconcile(desired, false)
+    }
+
+    /// Same as [`Self::reconcile_links`], except that only the links
+    /// made through the manager are removed, leaving e.g. the links
+    /// of the session manager as they are.
+    pub fn reconcile_managed_links(
+        &self,
+        desired: &[(u32, u32)],
+    ) -> ReconcileReport {
+        self.reconcile(desired, true)
+    }
+
+    fn reconcile(
+        &self,
+        desired: &[(u32, u32)],
+        managed_only: bool,
+    ) -> ReconcileReport {
+        let desired_pairs: HashSet<(u32, u32)> =
+            desired.iter().copied().collect();
+        let extras: Vec<(u32, u32)> = {
+            let objects = self.objects.read().unwrap();
+            objects
+                .node_adjacency()
+                .into_iter()
+                .filter(|pair| !desired_pairs.contains(pair))
+                .filter(|&(output_node, input_node)| {
+                    !managed_only
+                        || objects.is_route(output_node, input_node)
+                })
+                .collect()
+        };
+        let mut report = ReconcileReport::default();
+        for (output_node, input_node) in extras {
+            match self.ensure_unlinked(output_node, input_node) {
+                Ok(true) => {
+                    report.removed.push((output_node, input_node))
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    report.failed.push(((output_node, input_node), e))
+                }
+            }
+        }
+        let mut seen = HashSet::new();
+        for &(output_node, input_node) in desired {
+            if !seen.insert((output_node, input_node)) {
+                continue;
+            }
+            match self.ensure_linked(output_node, input_node) {
+                Ok(true) => {
+                    report.added.push((output_node, input_node))
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    report.failed.push(((output_node, input_node), e))
+                }
+            }
+        }
+        report
+    }
+
+    /// Run `callback` whenever the default sink or source changes,
+    /// with the id of the new default node. The id is `None` when
+    /// the default was unset or is a node that is not known yet.
+    ///
+    /// The callback runs on the backend thread.
+    pub fn on_default_changed<F>(&self, callback: F)
+    where
+        F: Fn(DefaultKind, Option<u32>) + Send + Sync + 'static,
+    {
+        self.objects
+            .write()
+            .unwrap()
+            .default_callbacks
+            .push(Arc::new(callback));
+    }
+
+    /// Run `callback` with the new value of the `key` property of a
+    /// node whenever it changes, e.g. its `node.latency`, without
+    /// going through every change of the node. The callback is
+    /// dropped along with the node once it is removed.
+    ///
+    /// The callback runs on the backend thread.
+    pub fn subscribe_node_property<F>(
+        &self,
+        node_id: u32,
+        key: &str,
+        callback: F,
+    ) where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.objects
+            .write()
+            .unwrap()
+            .property_callbacks
+            .entry((node_id, key.to_owned()))
+            .or_default()
+            .push(Arc::new(callback));
+    }
+
+    /// Run `callback` on every event sent by the backend from now
+    /// on, along with its sequence number. See
+    /// [`Self::on_event_filtered`] to only get some of them.
+    pub fn on_event<F>(&self, callback: F)
+    where
+        F: Fn(&Event) + Send + Sync + 'static,
+    {
+        self.on_event_filtered(|_| true, callback);
+    }
+
+    /// Run `callback` on the events sent by the backend from now on
+    /// whose kind matches `predicate`, e.g. only the
+    /// [`ConnectorEvent::LinkFailed`] ones.
+    ///
+    /// Callbacks run in order on a thread of their own, once the
+    /// methods of the manager waiting for the event got it, so a slow
+    /// callback only delays the following callbacks, and a panicking
+    /// one is logged and skipped. They must not make blocking calls
+    /// to the manager, such as [`Self::link_nodes`] or
+    /// [`Self::try_unlink_nodes`], which would compete with the
+    /// caller for the events it waits for. Such calls fail with a
+    /// `ReentrantCall` error there instead, e.g.
+    /// [`LinkError::ReentrantCall`].
+    pub fn on_event_filtered<P, F>(&self, predicate: P, callback: F)
+    where
+        P: Fn(&ConnectorEvent) -> bool + Send + Sync + 'static,
+        F: Fn(&Event) + Send + Sync + 'static,
+    {
+        self.event_callbacks.subscribe(predicate, callback);
+    }
+
+    /// Whether every object that existed when the backend connected
+    /// was announced. It is false again while reconnecting, until
+    /// the objects of the new connection are all announced too.
+    pub fn is_ready(&self) -> bool {
+        self.objects.read().unwrap().ready
+    }
+
+    /// Run `callback` once the initial enumeration of the graph is
+    /// complete, without blocking. If it already is, `callback` runs
+    /// right away on the calling thread; otherwise it runs on the
+    /// backend thread, right before [`ConnectorEvent::Ready`] is
+    /// sent, and must not make blocking calls to the manager.
+    ///
+    /// It runs exactly once, even if the connection is lost and the
+    /// graph is enumerated again; see [`Self::on_each_ready`] to be
+    /// told every time.
+    pub fn on_ready<F>(&self, callback: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut objects = self.objects.write().unwrap();
+        // Checked under the lock the backend marks the objects ready
+        // with, so the callback cannot be missed in between
+        if !objects.ready {
+            objects
+                .ready_callbacks
+                .get_mut()
+                .unwrap()
+                .push(Box::new(callback));
+            return;
+        }
+        drop(objects);
+        callback();
+    }
+
+    /// Mark the objects as all announced and run the callbacks
+    /// waiting for it, see [`Self::on_ready`].
+    fn _set_ready(objects: &Arc<RwLock<PipeWireObjects>>) {
+        let callbacks = objects.write().unwrap().set_ready();
+        for callback in callbacks {
+            callback();
+        }
+    }
+
+    /// Run `callback` every time the graph was enumerated, after
+    /// connecting and after every reconnection, from now on. Unlike
+    /// [`Self::on_ready`], it does not run if the manager is already
+    /// ready.
+    pub fn on_each_ready<F>(&self, callback: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_event_filtered(
+            |event| *event == ConnectorEvent::Ready,
+            move |_| callback(),
+        );
+    }
+
+    /// Whether the backend thread is still alive. Once it stopped,
+    /// e.g. after losing the connection without
+    /// [`PipeWireManagerBuilder::auto_reconnect`], the manager cannot
+    /// do anything anymore and has to be created again.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+            && !self._main_thread.read().unwrap().is_finished()
+    }
+
+    /// Stop the backend thread and start a new one connected to the
+    /// server again, e.g. after the daemon restarted, so that the
+    /// manager can be kept instead of being created again.
+    ///
+    /// The objects are cleared first and then enumerated again by
+    /// the new thread. Events, callbacks, routes and link rules are
+    /// kept, while the level monitors and virtual nodes of the
+    /// previous thread are gone. The backend options are those the
+    /// manager was built with; a thread still waiting to reconnect
+    /// through [`PipeWireManagerBuilder::auto_reconnect`] cannot be
+    /// stopped, which is reported as [`InitError::BackendStuck`].
+    pub fn reinit(&self) -> Result<(), InitError> {
+        self.reinit_with(Self::_start_thread)
+    }
+
+    /// Same as [`Self::reinit`], starting the new backend thread with
+    /// `start`, which takes the arguments of `_start_thread`.
+    fn reinit_with(
+        &self,
+        start: impl FnOnce(
+            Arc<RwLock<()>>,
+            mpsc::Sender<ConnectorEvent>,
+            channel::Receiver<PipeWireEvent>,
+            Arc<RwLock<PipeWireObjects>>,
+            BackendOptions,
+            mpsc::SyncSender<Result<(), InitError>>,
+            RunningGuard,
+        ) -> thread::JoinHandle<()>,
+    ) -> Result<(), InitError> {
+        let _logger = self._log_scope();
+        let mut main_thread = self._main_thread.write().unwrap();
+        if !main_thread.is_finished() {
+            self._raise_event(PipeWireEvent::ShutdownCommand);
+        }
+        let deadline = Instant::now() + REINIT_TIMEOUT;
+        while !main_thread.is_finished() {
+            if Instant::now() >= deadline {
+                return Err(InitError::BackendStuck);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        log_info!("(Pipewire) Starting a new backend thread");
+        self.objects.write().unwrap().clear_graph();
+
+        let (pw_sender, pw_receiver) =
+            channel::channel::<event::PipeWireEvent>();
+        let (init_sender, init_receiver) = mpsc::sync_channel(1);
+        self.running.store(true, Ordering::SeqCst);
+        *main_thread = start(
+            self._event_locker.clone(),
+            self.main_sender.clone(),
+            pw_receiver,
+            self.objects.clone(),
+            self.backend_options.clone(),
+            init_sender,
+            RunningGuard(self.running.clone()),
+        );
+        *self._sender.write().unwrap() = pw_sender;
+        drop(main_thread);
+        Self::_wait_for_init(&init_receiver)
+    }
+
+    /// Every event received so far and not yet consumed, in order,
+    /// without blocking. Empty when nothing is pending.
+    pub fn drain_events(&self) -> Vec<Event> {
+        self._receiver.try_iter().collect()
+    }
+
+    /// A consistent copy of the whole graph, see
+    /// [`PipeWireObjects::take_snapshot`].
+    pub fn export_graph(&self) -> GraphSnapshot {
+        self.objects.read().unwrap().take_snapshot()
+    }
+
+    /// Write the graph in the DOT language to `writer` as it goes,
+    /// without building it in memory first, e.g. to log a large
+    /// graph to a file. See [`PipeWireObjects::to_dot`]; the objects
+    /// stay locked until it is written.
+    pub fn export_graph_to_writer<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+    ) -> std::io::Result<()> {
+        self.objects.read().unwrap().write_dot(writer)
+    }
+
+    /// Which node drives the graph, with its quantum and rate, see
+    /// [`PipeWireObjects::driver_info`].
+    pub fn driver_info(&self) -> Option<DriverInfo> {
+        self.objects.read().unwrap().driver_info()
+    }
+
+    /// An owned summary of the node, `None` if it does not exist.
+    pub fn node_info(&self, id: u32) -> Option<NodeInfo> {
+        self.find_node(NodeSelector::Id(id))
+    }
+
+    /// An owned summary of the node `by` designates, `None` if there
+    /// is none. See [`NodeSelector`] for which selector to keep.
+    pub fn find_node(&self, by: NodeSelector) -> Option<NodeInfo> {
+        self.objects
+            .read()
+            .unwrap()
+            .find_node_by(&by)
+            .map(NodeInfo::from)
+    }
+
+    /// An owned summary of the port, with the id of its node, `None`
+    /// if it does not exist.
+    pub fn port_info(&self, port_id: u32) -> Option<PortInfo> {
+        let objects = self.objects.read().unwrap();
+        let node_id = objects.node_id_of_port(port_id)?;
+        objects
+            .find_port_by_id(port_id)
+            .map(|port| PortInfo::new(node_id, port))
+    }
+
+    /// The available sinks, see [`Node::is_available`].
+    pub fn list_sinks(&self) -> Vec<NodeInfo> {
+        self.list_nodes(NodeKind::Sink, false)
+    }
+
+    /// The available sources, see [`Node::is_available`].
+    pub fn list_sources(&self) -> Vec<NodeInfo> {
+        self.list_nodes(NodeKind::Source, false)
+    }
+
+    /// The nodes of the given kind, including the unavailable ones
+    /// if `include_unavailable` is set.
+    pub fn list_nodes(
+        &self,
+        kind: NodeKind,
+        include_unavailable: bool,
+    ) -> Vec<NodeInfo> {
+        self.nodes_matching(|node| {
+            node.kind() == kind
+                && (include_unavailable || node.is_available())
+        })
+    }
+
+    /// Summaries of the nodes `predicate` accepts. The objects are
+    /// only locked while filtering, so the result can be kept and
+    /// used freely; `predicate` must not call the manager.
+    pub fn nodes_matching<F: Fn(&Node) -> bool>(
+        &self,
+        predicate: F,
+    ) -> Vec<NodeInfo> {
+        self.objects
+            .read()
+            .unwrap()
+            .nodes
+            .iter()
+            .filter(|node| predicate(node))
+            .map(NodeInfo::from)
+            .collect()
+    }
+
+    /// Summaries of every node, without ever waiting for the objects
+    /// to be unlocked. `None` means the objects are busy, most likely
+    /// being updated by the backend thread: try again later.
+    ///
+    /// The summaries are still allocated, so this does not belong in
+    /// a real-time thread.
+    pub fn try_nodes_snapshot(&self) -> Option<Vec<NodeInfo>> {
+        let objects = self.objects.try_read().ok()?;
+        Some(objects.nodes.iter().map(NodeInfo::from).collect())
+    }
+
+    /// The events sent by the backend from now on, in order, along
+    /// with their sequence numbers.
+    ///
+    /// Every stream gets its own copy of the events, without taking
+    /// them away from the methods of the manager waiting for them.
+    #[cfg(feature = "async")]
+    pub fn event_stream(&self) -> impl futures::Stream<Item = Event> {
+        self.event_streams.subscribe()
+    }
+
+    /// Same as [`Self::event_stream`], holding at most `capacity`
+    /// events. When the stream is full, the events are dropped and
+    /// counted, then reported by a [`ConnectorEvent::Lagged`] event
+    /// once there is room again, so that slow consumers do not pile
+    /// up events forever but still know what they missed.
+    #[cfg(feature = "async")]
+    pub fn bounded_event_stream(
+        &self,
+        capacity: usize,
+    ) -> impl futures::Stream<Item = Event> {
+        self.event_streams.subscribe_bounded(capacity)
+    }
+
+    /// Remove every link of the graph the client is allowed to
+    /// destroy, returning how many were removed and how many were
+    /// skipped, as `(removed, skipped)`. Links the client may not
+    /// destroy are skipped.
+    pub fn unlink_everything(
+        &self,
+    ) -> Result<(usize, usize), LinkError> {
+        self.unlink_everything_matching(|_| true)
+    }
+
+    /// Same as [`Self::unlink_everything`], limited to the links
+    /// accepted by `filter`.
+    pub fn unlink_everything_matching(
+        &self,
+        filter: impl Fn(&Link) -> bool,
+    ) -> Result<(usize, usize), LinkError> {
+        let _logger = self._log_scope();
+        let (link_ids, skipped) = {
+            let objects = self.objects.read().unwrap();
+            let (destroyable, skipped): (Vec<&Link>, Vec<&Link>) =
+                objects
+                    .links
+                    .iter()
+                    // Pending links have no id to remove them by yet
+                    .filter(|link| !link.is_pending() && filter(link))
+                    .partition(|link| link.destroyable);
+            let link_ids: Vec<u32> =
+                destroyable.iter().map(|link| link.id).collect();
+            (link_ids, skipped.len())
+        };
+        if skipped > 0 {
+            log_info!(
+                "Skipping {skipped} links the client may not destroy"
+            );
+        }
+        let removed = self.remove_links(link_ids)?;
+        Ok((removed, skipped))
+    }
+
+    /// Remove every link of the logical link at once, e.g. both
+    /// channels of a stereo connection, returning how many were
+    /// removed.
+    pub fn unlink_logical(
+        &self,
+        logical_link: &LogicalLink,
+    ) -> Result<usize, LinkError> {
+        self.remove_links(logical_link.link_ids.clone())
+    }
+
+    fn remove_links(
+        &self,
+        link_ids: Vec<u32>,
+    ) -> Result<usize, LinkError> {
+        if link_ids.is_empty() {
+            return Ok(0);
+        }
+        self.check_not_dispatching()?;
+        self._raise_event(PipeWireEvent::RemoveLinksCommand(
+            link_ids,
+        ));
+        let event =
+            self.wait_for_event(|event: &ConnectorEvent| {
+                matches!(event, ConnectorEvent::LinksRemoved { .. })
+            })?;
+        match event {
+            ConnectorEvent::LinksRemoved { removed, failed: 0 } => {
+                Ok(removed)
+            }
+            ConnectorEvent::LinksRemoved { removed, failed } => {
+                Err(LinkError::UnlinkAllFailed { removed, failed })
+            }
+            event => {
+                Err(LinkError::UnexpectedEvent(format!("{event:?}")))
+            }
+        }
+    }
+
+    /// Block until the server announced every link the manager
+    /// created from the output node into the input node, returning
+    /// the ids of the links between them.
+    ///
+    /// Created links are known right away but only get an id once
+    /// announced, see [`Link::is_pending`].
+    pub fn wait_for_link_ids(
+        &self,
+        out_node: u32,
+        in_node: u32,
+        timeout: Duration,
+    ) -> Result<Vec<u32>, WaitError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            {
+                let objects = self.objects.read().unwrap();
+                if objects
+                    .pending_link_count_between(out_node, in_node)
+                    == 0
+                {
+                    return Ok(objects
+                        .find_link_ids_between(out_node, in_node));
+                }
+            }
+            let remaining =
+                deadline.saturating_duration_since(Instant::now());
+            let event = self.wait_for_event_timeout(
+                |event: &ConnectorEvent| {
+                    matches!(event, ConnectorEvent::LinkAnnounced(_))
+                },
+                remaining,
+            )?;
+            if event.is_none() {
+                return Err(WaitError::StillPending(
+                    out_node, in_node,
+                ));
+            }
+        }
+    }
+
+    /// Block until the link reaches the [`LinkState::Active`] state,
+    /// meaning audio is flowing through it.
+    ///
+    /// A link appears as soon as it is created, but it still has to
+    /// negotiate a format and allocate buffers before it is active.
+    pub fn wait_for_link_active(
+        &self,
+        link_id: u32,
+        timeout: Duration,
+    ) -> Result<(), WaitError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let state = self
+                .objects
+                .read()
+                .unwrap()
+                .find_links_by_id(link_id)
+                .map(|link| link.state().clone());
+            match state {
+                None => return Err(WaitError::LinkNotFound(link_id)),
+                Some(LinkState::Active) => return Ok(()),
+                Some(LinkState::Error(error)) => {
+                    return Err(WaitError::LinkFailed(link_id, error))
+                }
+                Some(_) => {}
+            }
+
+            let remaining =
+                deadline.saturating_duration_since(Instant::now());
+            let event = self.wait_for_event_timeout(
+                |event: &ConnectorEvent| {
+                    matches!(
+                        event,
+                        ConnectorEvent::LinkStateChanged(id, _)
+                            if *id == link_id
+                    )
+                },
+                remaining,
+            )?;
+            if event.is_none() {
+                return Err(WaitError::Timeout(link_id));
+            }
+        }
+    }
+
+    /// Block until the link is gone, e.g. to make sure an old route
+    /// stopped carrying audio before creating its replacement.
+    /// A link that is not known is considered removed.
+    pub fn wait_for_link_removed(
+        &self,
+        link_id: u32,
+        timeout: Duration,
+    ) -> Result<(), WaitError> {
+        if self
+            .objects
+            .read()
+            .unwrap()
+            .find_links_by_id(link_id)
+            .is_none()
+        {
+            return Ok(());
+        }
+        let event = self.wait_for_event_timeout(
+            |event: &ConnectorEvent| {
+                *event == ConnectorEvent::LinkRemoved(link_id)
+            },
+            timeout,
+        )?;
+        match event {
+            Some(_) => Ok(()),
+            None => Err(WaitError::Timeout(link_id)),
+        }
+    }
+
+    /// Block until no link goes from the output node into the input
+    /// node anymore.
+    ///
+    /// Every channel of a route is a link of its own, removed with
+    /// its own event: a single [`ConnectorEvent::UnlinkUpdate`] does
+    /// not mean that the nodes are unlinked, e.g. before opening a
+    /// device exclusively.
+    pub fn wait_for_fully_unlinked(
+        &self,
+        out_node: u32,
+        in_node: u32,
+        timeout: Duration,
+    ) -> Result<(), WaitError> {
+        let deadline = Instant::now() + timeout;
+        while self.link_count_between(out_node, in_node) > 0 {
+            let remaining =
+                deadline.saturating_duration_since(Instant::now());
+            let event = self.wait_for_event_timeout(
+                |event: &ConnectorEvent| {
+                    *event
+                        == ConnectorEvent::UnlinkUpdate(
+                            out_node, in_node,
+                        )
+                },
+                remaining,
+            )?;
+            if event.is_none() {
+                return Err(WaitError::StillLinked(
+                    out_node, in_node,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn is_unlink_response(
+        event: &ConnectorEvent,
+        first_node_id: u32,
+        second_node_id: u32,
+    ) -> bool {
+        *event
+            == ConnectorEvent::UnlinkUpdate(
+                first_node_id,
+                second_node_id,
+            )
+            || *event
+                == ConnectorEvent::UnLinkFailed(
+                    first_node_id,
+                    second_node_id,
+                )
+            || *event
+                == ConnectorEvent::NothingToUnlink(
+                    first_node_id,
+                    second_node_id,
+                )
+    }
+
+    fn unlink_result(
+        event: ConnectorEvent,
+        first_node_id: u32,
+        second_node_id: u32,
+    ) -> Result<(), LinkError> {
+        match event {
+            ConnectorEvent::UnLinkFailed(..) => {
+                Err(LinkError::UnlinkFailed(
+                    first_node_id,
+                    second_node_id,
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Deliver what the calling thread logs to the logger of the
+    /// manager as well, until the scope is dropped.
+    fn _log_scope(&self) -> logging::LoggerScope {
+        logging::scope(self.backend_options.logger.clone())
+    }
+
+    /// Fail blocking calls made from an event callback or the backend
+    /// thread: they would wait for events that the thread they run on
+    /// is the one to deliver.
+    fn check_not_dispatching(&self) -> Result<(), ReentrantCall> {
+        let on_backend =
+            thread::current().name() == Some(BACKEND_THREAD_NAME);
+        if callbacks::is_dispatching() || on_backend {
+            let _logger = self._log_scope();
+            log_error!(
+                "Blocking call made from an event callback or the backend thread"
+            );
+            return Err(ReentrantCall);
+        }
+        Ok(())
+    }
+
+    fn wait_for_event<F: Fn(&ConnectorEvent) -> bool>(
+        &self,
+        checker: F,
+    ) -> Result<ConnectorEvent, ReentrantCall> {
+        let _logger = self._log_scope();
+        self.check_not_dispatching()?;
+        let mut event_result: ConnectorEvent = ConnectorEvent::None;
+        // Lock the thread and wait for the event to be processed
+        while !checker(&event_result) {
+            let result = self._receiver.try_recv();
+
+            if let Err(e) = result {
+                if e == TryRecvError::Disconnected {
+                    log_error!("Failed to receive event: {e}");
+                }
+                continue;
+            }
+            event_result = result.unwrap().kind;
+        }
+        log_debug!("(Connector) Received event: {event_result:?}");
+        Ok(event_result)
+    }
+
+    /// Wait for an event accepted by `checker`, returning `None` if
+    /// none arrived before `timeout`.
+    fn wait_for_event_timeout<F: Fn(&ConnectorEvent) -> bool>(
+        &self,
+        checker: F,
+        timeout: Duration,
+    ) -> Result<Option<ConnectorEvent>, ReentrantCall> {
+        let _logger = self._log_scope();
+        self.check_not_dispatching()?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining =
+                deadline.saturating_duration_since(Instant::now());
+            match self._receiver.recv_timeout(remaining) {
+                Ok(Event { kind, .. }) if checker(&kind) => {
+                    log_debug!(
+                        "(Connector) Received event: {kind:?}"
+                    );
+                    return Ok(Some(kind));
+                }
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(e) => {
+                    log_error!("Failed to receive event: {e}");
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Store `value` under `key` in the default metadata object, with
+    /// the node as subject.
+    ///
+    /// PipeWire metadata properties are addressed by a subject, the
+    /// global id of the object they describe, and a key. Since node
+    /// ids are handed out by the server, a property lives as long as
+    /// the node does: it outlives this application and is visible to
+    /// any client watching the metadata (e.g. `pw-metadata`), but a
+    /// recreated node gets a new id and none of the old properties.
+    ///
+    /// Returns once the server has acknowledged the change.
+    pub fn set_node_metadata(
+        &self,
+        node_id: u32,
+        key: &str,
+        value: &str,
+    ) -> Result<(), ControlError> {
+        if self
+            .objects
+            .read()
+            .unwrap()
+            .find_node_by_exact_id(node_id)
+            .is_none()
+        {
+            return Err(ControlError::NodeNotFound(node_id));
+        }
+
+        self.set_metadata(node_id, key, None, value)
+    }
+
+    /// Route a stream node to the node of the given `object.serial`,
+    /// by setting the `target.object` metadata of the stream.
+    ///
+    /// Unlike [`Self::link_nodes`], which creates links that vanish
+    /// whenever either node does, this only records where the stream
+    /// should go: the session manager creates the links itself, and
+    /// moves the stream back once the target reappears (e.g. a
+    /// headset reconnecting). This is the preferred way to route
+    /// application streams.
+    pub fn set_node_target(
+        &self,
+        stream_node_id: u32,
+        target_node_serial: u64,
+    ) -> Result<(), ControlError> {
+        {
+            let objects = self.objects.read().unwrap();
+            if objects.find_node_by_exact_id(stream_node_id).is_none()
+            {
+                return Err(ControlError::NodeNotFound(
+                    stream_node_id,
+                ));
+            }
+            let serial = target_node_serial.to_string();
+            if !objects
+                .nodes
+                .iter()
+                .any(|node| node.object_serial == serial)
+            {
+                return Err(ControlError::SerialNotFound(
+                    target_node_serial,
+                ));
+            }
+        }
+
+        self.set_metadata(
+            stream_node_id,
+            "target.object",
+            Some("Spa:Id"),
+            &target_node_serial.to_string(),
+        )
+    }
+
+    fn set_metadata(
+        &self,
+        subject: u32,
+        key: &str,
+        type_: Option<&str>,
+        value: &str,
+    ) -> Result<(), ControlError> {
+        self.check_not_dispatching()?;
+        self._raise_event(PipeWireEvent::SetMetadataCommand(
+            subject,
+            key.to_owned(),
+            type_.map(str::to_owned),
+            value.to_owned(),
+        ));
+        self.wait_for_metadata(subject, key)
+    }
+
+    /// Wait for the server to acknowledge a change of the `key`
+    /// property of `subject`.
+    fn wait_for_metadata(
+        &self,
+        subject: u32,
+        key: &str,
+    ) -> Result<(), ControlError> {
+        let event = self.wait_for_event(|event: &ConnectorEvent| {
+            matches!(
+                event,
+                ConnectorEvent::MetadataUpdate(changed_subject, changed)
+                | ConnectorEvent::MetadataFailed(changed_subject, changed)
+                    if *changed_subject == subject && changed == key
+            )
+        })?;
+        match event {
+            ConnectorEvent::MetadataFailed(..) => Err(
+                ControlError::MetadataFailed(subject, key.to_owned()),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Force the quantum of the whole graph, the amount of samples
+    /// processed on every cycle, e.g. 128 for a low and steady
+    /// latency. It must be a power of two within
+    /// [`crate::control::MIN_QUANTUM`] and
+    /// [`crate::control::MAX_QUANTUM`].
+    ///
+    /// This sets `clock.force-quantum` in the `settings` metadata
+    /// object, where PipeWire reads its clock settings, which needs
+    /// write access to it. It affects every client of the server and
+    /// lasts until [`Self::clear_graph_quantum`] or a restart of the
+    /// server.
+    pub fn set_graph_quantum(
+        &self,
+        quantum: u32,
+    ) -> Result<(), ControlError> {
+        validate_quantum(quantum)?;
+        self.set_setting(FORCE_QUANTUM_KEY, &quantum.to_string())
+    }
+
+    /// Let the graph pick its quantum again, see
+    /// [`Self::set_graph_quantum`].
+    pub fn clear_graph_quantum(&self) -> Result<(), ControlError> {
+        self.set_setting(FORCE_QUANTUM_KEY, "0")
+    }
+
+    fn set_setting(
+        &self,
+        key: &str,
+        value: &str,
+    ) -> Result<(), ControlError> {
+        self.check_not_dispatching()?;
+        self._raise_event(PipeWireEvent::SetSettingCommand(
+            key.to_owned(),
+            value.to_owned(),
+        ));
+        self.wait_for_metadata(0, key)
+    }
+
+    /// Mute or unmute a node through its `Props` param.
+    pub fn set_node_mute(
+        &self,
+        node_id: u32,
+        mute: bool,
+    ) -> Result<(), ControlError> {
+        self.bind_node(node_id)?.set_mute(mute)
+    }
+
+    /// Set the linear volume of every channel of a node through its
+    /// `Props` param, 1.0 being 100%. Volumes above
+    /// [`crate::control::MAX_VOLUME`] are clamped, negative ones are
+    /// rejected.
+    pub fn set_node_volume(
+        &self,
+        node_id: u32,
+        volume: f32,
+    ) -> Result<(), ControlError> {
+        self.bind_node(node_id)?.set_volume(volume)
+    }
+
+    /// Override the `node.description` of a node, e.g. to label a
+    /// virtual sink, through its `Props` param. The cached
+    /// [`Node::description`] is updated right away, and again by the
+    /// info of the node once the server applied it.
+    pub fn set_node_description(
+        &self,
+        node_id: u32,
+        description: &str,
+    ) -> Result<(), ControlError> {
+        self.bind_node(node_id)?.set_description(description)?;
+        if let Some(node) = self
+            .objects
+            .write()
+            .unwrap()
+            .find_node_by_exact_id_mut(node_id)
+        {
+            node.description = Some(description.to_owned());
+        }
+        Ok(())
+    }
+
+    /// Call `callback` with the peak level of the node on every
+    /// processing cycle, linear with 1.0 being full scale, until the
+    /// returned handle is dropped.
+    ///
+    /// Levels are read through a passive capture stream connected to
+    /// the node, to the monitor ports of sinks. This is not free: the
+    /// server converts the samples of the node for the stream, and
+    /// the backend thread wakes up on every cycle (e.g. 47 times a
+    /// second with a quantum of 1024 at 48 kHz) to call `callback`.
+    /// Keep it cheap and do not call the manager from it, as it runs
+    /// on the backend thread.
+    pub fn monitor_levels(
+        &self,
+        node_id: u32,
+        callback: impl Fn(f32) + Send + Sync + 'static,
+    ) -> Result<LevelMonitor, ControlError> {
+        if self
+            .objects
+            .read()
+            .unwrap()
+            .find_node_by_exact_id(node_id)
+            .is_none()
+        {
+            return Err(ControlError::NodeNotFound(node_id));
+        }
+        self.check_not_dispatching()?;
+        let monitor_id =
+            self.next_monitor_id.fetch_add(1, Ordering::SeqCst);
+        self._raise_event(PipeWireEvent::MonitorLevelsCommand(
+            monitor_id,
+            node_id,
+            LevelCallback(Arc::new(callback)),
+        ));
+        let event =
+            self.wait_for_event(|event: &ConnectorEvent| {
+                *event == ConnectorEvent::MonitorStarted(monitor_id)
+                    || *event
+                        == ConnectorEvent::MonitorFailed(monitor_id)
+            })?;
+        match event {
+            ConnectorEvent::MonitorStarted(_) => Ok(LevelMonitor {
+                id: monitor_id,
+                node_id,
+                sender: self._sender.read().unwrap().clone(),
+            }),
+            _ => Err(ControlError::MonitorFailed(node_id)),
+        }
+    }
+
+    /// A handle to control the node repeatedly, see [`NodeHandle`].
+    pub fn bind_node(
+        &self,
+        node_id: u32,
+    ) -> Result<NodeHandle<'_>, ControlError> {
+        if self
+            .objects
+            .read()
+            .unwrap()
+            .find_node_by_exact_id(node_id)
+            .is_none()
+        {
+            return Err(ControlError::NodeNotFound(node_id));
+        }
+        Ok(NodeHandle {
+            manager: self,
+            node_id,
+        })
+    }
+
+    /// Send a control command of a node and wait for the backend to
+    /// apply it.
+    pub(crate) fn send_node_control(
+        &self,
+        command: PipeWireEvent,
+    ) -> Result<(), ControlError> {
+        let node_id = match command {
+            PipeWireEvent::SetMuteCommand(node_id, _)
+            | PipeWireEvent::SetVolumeCommand(node_id, _)
+            | PipeWireEvent::SetDescriptionCommand(node_id, _) => {
+                node_id
+            }
+            _ => unreachable!("{command} does not control a node"),
+        };
+        self.check_not_dispatching()?;
+        self._raise_event(command);
+        let event =
+            self.wait_for_event(|event: &ConnectorEvent| {
+                *event == ConnectorEvent::ControlUpdate(node_id)
+                    || *event
+                        == ConnectorEvent::ControlFailed(node_id)
+            })?;
+        match event {
+            ConnectorEvent::ControlFailed(_) => {
+                Err(ControlError::ParamsFailed(node_id))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Mute every sink, remembering whether each of them was muted
+    /// so that [`Self::unmute_all_outputs`] can restore them.
+    ///
+    /// Sinks added while the outputs are muted are left alone, and
+    /// are not touched when unmuting either. Calling this again mutes
+    /// them too.
+    pub fn mute_all_outputs(&self) -> Result<(), ControlError> {
+        let _logger = self._log_scope();
+        let sinks = {
+            let objects = self.objects.read().unwrap();
+            self.global_mute.write().unwrap().mute(&objects.nodes)
+        };
+        let mut result = Ok(());
+        for sink in sinks {
+            if let Err(e) = self.set_node_mute(sink, true) {
+                log_error!("{e}");
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    /// Return every sink muted by [`Self::mute_all_outputs`] to the
+    /// mute state it had before.
+    pub fn unmute_all_outputs(&self) -> Result<(), ControlError> {
+        let _logger = self._log_scope();
+        let states = {
+            let objects = self.objects.read().unwrap();
+            self.global_mute.write().unwrap().restore(&objects.nodes)
+        };
+        let mut result = Ok(());
+        for (sink, mute) in states {
+            if let Err(e) = self.set_node_mute(sink, mute) {
+                log_error!("{e}");
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    /// Whether the outputs are muted by [`Self::mute_all_outputs`].
+    pub fn is_output_muted(&self) -> bool {
+        self.global_mute.read().unwrap().is_active()
+    }
+
+    /// Read the value stored under `key` for the node in the default
+    /// metadata object, see [`Self::set_node_metadata`].
+    pub fn get_node_metadata(
+        &self,
+        node_id: u32,
+        key: &str,
+    ) -> Option<String> {
+        self.objects
+            .read()
+            .unwrap()
+            .metadata
+            .get(node_id, key)
+            .map(str::to_owned)
+    }
+
+    /// The current version of the graph, see
+    /// [`PipeWireObjects::graph_version`].
+    pub fn graph_version(&self) -> u64 {
+        self.objects.read().unwrap().graph_version()
+    }
+
+    /// The nodes and links added or removed since the graph was at
+    /// `version`, see [`PipeWireObjects::changes_since`].
+    pub fn changes_since(
+        &self,
+        version: u64,
+    ) -> Result<Vec<GraphChange>, DiffError> {
+        self.objects.read().unwrap().changes_since(version)
+    }
+
+    /// How many nodes of each kind currently exist, see
+    /// [`PipeWireObjects::counts_by_kind`].
+    pub fn counts_by_kind(&self) -> HashMap<NodeKind, usize> {
+        self.objects.read().unwrap().counts_by_kind()
+    }
+
+    /// The name and version of the PipeWire server, `None` until the
+    /// server sent them.
+    pub fn server_info(&self) -> Option<ServerInfo> {
+        self.objects.read().unwrap().server_info.clone()
+    }
+
+    /// Create a virtual node from the spec, e.g. a sink applications
+    /// can be routed into, and return its id.
+    ///
+    /// The node lives as long as the manager. PipeWire announces it
+    /// a little after creating it, which is waited for following the
+    /// manager's [`RetryPolicy`].
+    pub fn create_virtual_node(
+        &self,
+        spec: VirtualNodeSpec,
+    ) -> Result<u32, CreateNodeError> {
+        spec.validate()?;
+        self.create_node_with_properties(spec.properties())
+    }
+
+    /// Create a node from raw properties, passed as they are to
+    /// PipeWire's `adapter` factory, and return its id. This allows
+    /// setting properties [`VirtualNodeSpec`] has no field for, e.g.
+    /// `media.role` or custom tags.
+    ///
+    /// Every [`crate::node::REQUIRED_NODE_PROPERTIES`] must be set. Like
+    /// [`Self::create_virtual_node`], the node lives as long as the
+    /// manager and its announcement is waited for.
+    pub fn create_node_with_properties(
+        &self,
+        properties: HashMap<String, String>,
+    ) -> Result<u32, CreateNodeError> {
+        let name = required_node_name(&properties)?.to_owned();
+        if self
+            .objects
+            .read()
+            .unwrap()
+            .find_node_id_by_name(&name)
+            .is_some()
+        {
+            return Err(CreateNodeError::NameTaken(name));
+        }
+        self.check_not_dispatching()?;
+        self._raise_event(PipeWireEvent::CreateNodeCommand(
+            name.clone(),
+            properties,
+        ));
+        let event =
+            self.wait_for_event(|event: &ConnectorEvent| {
+                matches!(
+                    event,
+                    ConnectorEvent::NodeCreated(created)
+                        | ConnectorEvent::NodeCreationFailed(created)
+                        if *created == name
+                )
+            })?;
+        if let ConnectorEvent::NodeCreationFailed(_) = event {
+            return Err(CreateNodeError::CreationFailed(name));
+        }
+        let mut delays = self.retry_policy.delays();
+        loop {
+            let id = self
+                .objects
+                .read()
+                .unwrap()
+                .find_node_id_by_name(&name);
+            if let Some(id) = id {
+                return Ok(id);
+            }
+            let Some(delay) = delays.next() else {
+                return Err(CreateNodeError::NotAnnounced(name));
+            };
+            thread::sleep(delay);
+        }
+    }
+
+    /// Link the nodes matching the rule whenever one of them appears,
+    /// for as long as the manager lives. Nodes that already exist
+    /// are left as they are.
+    pub fn add_link_rule(&self, rule: LinkRule) -> LinkRuleId {
+        let mut objects = self.objects.write().unwrap();
+        let id = LinkRuleId(objects.next_link_rule_id);
+        objects.next_link_rule_id += 1;
+        objects.link_rules.push((id, rule));
+        id
+    }
+
+    /// Link every stream of the `role`, e.g. every
+    /// [`MediaRole::Communication`] stream into a headset, like the
+    /// session manager routes by role. Streams that already exist are
+    /// linked right away, and the ones that appear later through a
+    /// link rule, which removing the returned rule stops.
+    pub fn route_by_role(
+        &self,
+        role: MediaRole,
+        target_node: u32,
+    ) -> LinkRuleId {
+        let rule_id = self.add_link_rule(LinkRule {
+            output_match: NodeMatch::media_role(role.as_str()),
+            input_match: NodeMatch::id(target_node),
+            strategy: LinkStrategy::default(),
+        });
+        // The target appearing is what links the existing streams
+        let links = self
+            .objects
+            .read()
+            .unwrap()
+            .links_for_rules(&[target_node]);
+        for (output_node, input_node, strategy) in links {
+            self.link_nodes_with_strategy(
+                output_node,
+                input_node,
+                strategy,
+            );
+        }
+        rule_id
+    }
+
+    /// Stop applying a rule, returning whether it existed. The links
+    /// it created are kept.
+    pub fn remove_link_rule(&self, id: LinkRuleId) -> bool {
+        let mut objects = self.objects.write().unwrap();
+        let count = objects.link_rules.len();
+        objects.link_rules.retain(|(rule_id, _)| *rule_id != id);
+        objects.link_rules.len() != count
+    }
+
+    /// The globals the manager does not handle, empty unless built
+    /// with [`PipeWireManagerBuilder::record_unknown_objects`].
+    pub fn other_objects(&self) -> Vec<OtherObject> {
+        self.objects.read().unwrap().other_objects.clone()
+    }
+
+    pub fn get_objects(&self) -> Arc<RwLock<PipeWireObjects>> {
+        self.objects.clone()
+    }
+}
+
+#[cfg(test)]
+impl PipeWireManager {
+    /// A manager without a PipeWire backend. Events that the backend
+    /// would answer with are fed through the returned sender instead.
+    pub(crate) fn mocked() -> (Self, SequencedSender) {
+        let (manager, main_sender) = Self::mocked_parts();
+        (manager, SequencedSender::new(main_sender))
+    }
+
+    /// Same as [`Self::mocked`], except that the events fed through
+    /// the returned sender also reach the event callbacks.
+    pub(crate) fn mocked_with_callbacks() -> (Self, SequencedSender) {
+        let (mut manager, main_sender) = Self::mocked_parts();
+        let (sender, event_callbacks) =
+            EventCallbacks::forward(main_sender, None);
+        manager.event_callbacks = event_callbacks;
+        (manager, SequencedSender::new(sender))
+    }
+
+    /// Same as [`Self::mocked`], except that the events fed through
+    /// the returned sender also reach the event streams.
+    #[cfg(feature = "async")]
+    pub(crate) fn mocked_streamed() -> (Self, SequencedSender) {
+        let (mut manager, main_sender) = Self::mocked_parts();
+        let (sender, event_streams) =
+            EventStreams::forward(main_sender);
+        manager.event_streams = event_streams;
+        (manager, SequencedSender::new(sender))
+    }
+
+    /// The mocked manager, along with the sender of its events.
+    fn mocked_parts() -> (Self, mpsc::Sender<Event>) {
+        let (main_sender, main_receiver) = mpsc::channel::<Event>();
+        #[cfg(feature = "async")]
+        let (_, event_streams) =
+            EventStreams::forward(main_sender.clone());
+        let (_, event_callbacks) =
+            EventCallbacks::forward(main_sender.clone(), None);
+        let (pw_sender, _) =
+            channel::channel::<event::PipeWireEvent>();
+        let manager = Self {
+            objects: Arc::new(
+                RwLock::new(PipeWireObjects::default()),
+            ),
+            _main_thread: RwLock::new(thread::spawn(|| {})),
+            running: Arc::new(AtomicBool::new(false)),
+            next_monitor_id: AtomicU32::new(0),
+            _receiver: main_receiver,
+            _sender: RwLock::new(pw_sender),
+            main_sender: event::sequence(main_sender.clone()),
+            backend_options: BackendOptions::default(),
+            _event_locker: Arc::new(RwLock::new(())),
+            retry_policy: RetryPolicy::default(),
+            global_mute: RwLock::new(GlobalMute::default()),
+            #[cfg(feature = "async")]
+            event_streams,
+            event_callbacks,
+        };
+        (manager, main_sender)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use libspa::utils::dict::DictRef;
+    use log::LevelFilter;
+    use pipewire::properties::properties;
+    use pipewire::types::ObjectType;
+
+    use super::{
+        InitError, NodeFilter, PipeWireManager, RunningGuard,
+        BACKEND_THREAD_NAME,
+    };
+    use crate::changes::GraphChange;
+    use crate::control::ControlError;
+    use crate::event::{ConnectorEvent, Event, SequencedSender};
+    use crate::link::{
+        Link, LinkError, LinkParseError, LinkState, ReconcileReport,
+        WaitError,
+    };
+    use crate::logging::{self, Logger};
+    use crate::metadata::{self, DefaultKind};
+    use crate::node::{
+        apply_node_props, CreateNodeError, DriverInfo, LinkStrategy,
+        MediaRole, Node, NodeInfo, NodeKind, NodeSelector,
+        VirtualNodeSpec,
+    };
+    use crate::objects::PipeWireObjects;
+    use crate::port::{AudioChannel, Port, PortDirection};
+    use crate::rules::{LinkRule, NodeMatch};
+    use crate::server::ServerInfo;
+    use crate::sync::RwLock;
+    use crate::test_utils::{global, stereo_node};
+
+    /// Send `events` from another thread once the command under test
+    /// had the time to reach the backend, the way it would answer.
+    fn answer(
+        backend: &SequencedSender,
+        events: Vec<ConnectorEvent>,
+    ) -> thread::JoinHandle<()> {
+        let backend = backend.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            for event in events {
+                backend.send(event).unwrap();
+            }
+        })
+    }
+
+    /// A logger collecting the commands sent to the backend, as
+    /// logged on the thread it is in scope on.
+    fn raised_commands() -> (Logger, Arc<Mutex<Vec<String>>>) {
+        let raised = Arc::new(Mutex::new(vec![]));
+        let logger = Logger {
+            sink: Arc::new({
+                let raised = raised.clone();
+                move |_, message: &str| {
+                    if let Some(command) =
+                        message.strip_prefix("Event raised: ")
+                    {
+                        raised
+                            .lock()
+                            .unwrap()
+                            .push(command.to_owned());
+                    }
+                }
+            }),
+            level: LevelFilter::Debug,
+        };
+        (logger, raised)
+    }
+
+    #[test]
+    fn ensure_linked_only_links_once() {
+        let (manager, backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "source", 10));
+            objects.insert_node(stereo_node(2, "sink", 20));
+        }
+        backend.send(ConnectorEvent::PortsLinked(10, 21)).unwrap();
+        backend.send(ConnectorEvent::PortsLinked(12, 23)).unwrap();
+
+        assert_eq!(manager.ensure_linked(1, 2), Ok(true));
+        assert!(manager.drain_events().is_empty());
+        // The backend announces the links it created
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_link(Link::from_parts(
+                30,
+                (1, 10),
+                (2, 21),
+            ));
+            objects.insert_link(Link::from_parts(
+                31,
+                (1, 12),
+                (2, 23),
+            ));
+        }
+        // Nothing is asked to the backend the second time
+        assert_eq!(manager.ensure_linked(1, 2), Ok(false));
+        assert_eq!(manager.link_count_between(1, 2), 2);
+
+        // Only the right channel is relinked once its link is gone
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .links
+            .retain(|link| link.id != 31);
+        backend.send(ConnectorEvent::PortsLinked(12, 23)).unwrap();
+        assert_eq!(manager.ensure_linked(1, 2), Ok(true));
+        assert!(manager.drain_events().is_empty());
+    }
+
+    #[test]
+    fn ensure_unlinked_only_unlinks_once() {
+        let (manager, backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "source", 10));
+            objects.insert_node(stereo_node(2, "sink", 20));
+            objects.insert_link(Link::from_parts(
+                30,
+                (1, 10),
+                (2, 21),
+            ));
+        }
+        backend.send(ConnectorEvent::UnlinkUpdate(1, 2)).unwrap();
+        backend.send(ConnectorEvent::UnlinkUpdate(1, 2)).unwrap();
+
+        assert_eq!(manager.ensure_unlinked(1, 2), Ok(true));
+        manager.objects.write().unwrap().links.clear();
+        assert_eq!(manager.ensure_unlinked(1, 2), Ok(false));
+        // Nothing was asked to the backend the second time
+        assert_eq!(
+            manager._receiver.try_recv().map(|event| event.kind),
+            Ok(ConnectorEvent::UnlinkUpdate(1, 2))
+        );
+
+        // Removed by someone else before the backend got to it
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_link(Link::from_parts(31, (1, 10), (2, 21)));
+        backend.send(ConnectorEvent::NothingToUnlink(1, 2)).unwrap();
+        assert_eq!(manager.ensure_unlinked(1, 2), Ok(false));
+    }
+
+    #[test]
+    fn reconcile_links_converges_to_the_desired_links() {
+        let (manager, backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            for (id, name) in
+                [(1, "source"), (2, "sink"), (3, "other")]
+            {
+                objects.insert_node(stereo_node(id, name, id * 10));
+            }
+            objects.insert_link(Link::from_parts(
+                40,
+                (1, 10),
+                (2, 21),
+            ));
+            objects.insert_link(Link::from_parts(
+                41,
+                (1, 12),
+                (2, 23),
+            ));
+            objects.insert_link(Link::from_parts(
+                42,
+                (2, 20),
+                (3, 31),
+            ));
+            objects.insert_link(Link::from_parts(
+                44,
+                (2, 22),
+                (3, 33),
+            ));
+        }
+        let desired = [(1, 3), (2, 3), (1, 3)];
+        backend.send(ConnectorEvent::UnlinkUpdate(1, 2)).unwrap();
+        backend.send(ConnectorEvent::PortsLinked(10, 31)).unwrap();
+        backend.send(ConnectorEvent::PortsLinked(12, 33)).unwrap();
+
+        assert_eq!(
+            manager.reconcile_links(&desired),
+            ReconcileReport {
+                added: vec![(1, 3)],
+                removed: vec![(1, 2)],
+                failed: vec![],
+            }
+        );
+        // The backend applies the changes
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.links.retain(|link| link.output_node != 1);
+            objects.insert_link(Link::from_parts(
+                43,
+                (1, 10),
+                (3, 31),
+            ));
+            objects.insert_link(Link::from_parts(
+                45,
+                (1, 12),
+                (3, 33),
+            ));
+        }
+        assert_eq!(
+            manager.objects.read().unwrap().node_adjacency(),
+            vec![(1, 3), (2, 3)]
+        );
+        assert_eq!(
+            manager.reconcile_links(&desired),
+            ReconcileReport::default()
+        );
+
+        // None of these links were made through the manager
+        assert_eq!(
+            manager.reconcile_managed_links(&[]),
+            ReconcileReport::default()
+        );
+    }
+
+    #[test]
+    fn reconcile_managed_links_relinks_and_unlinks_managed_links() {
+        let (manager, backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            for (id, name) in
+                [(1, "source"), (2, "sink"), (3, "recorder")]
+            {
+                objects.insert_node(stereo_node(id, name, id * 10));
+            }
+            // Linked through the manager, then removed by someone
+            // else
+            objects.record_route(1, 2, LinkStrategy::default());
+            // Linked port by port through the manager
+            objects.insert_link(Link::from_parts(
+                40,
+                (1, 10),
+                (3, 31),
+            ));
+            objects.record_port_route(10, 31);
+        }
+        backend.send(ConnectorEvent::UnlinkUpdate(1, 3)).unwrap();
+        backend.send(ConnectorEvent::PortsLinked(10, 21)).unwrap();
+        backend.send(ConnectorEvent::PortsLinked(12, 23)).unwrap();
+
+        assert_eq!(
+            manager.reconcile_managed_links(&[(1, 2)]),
+            ReconcileReport {
+                added: vec![(1, 2)],
+                removed: vec![(1, 3)],
+                failed: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn link_ports_by_name_links_the_named_ports() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager.objects.write().unwrap().extend([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ]);
+        manager.drain_events();
+
+        let backend_thread = answer(
+            &backend,
+            vec![ConnectorEvent::PortsLinked(12, 21)],
+        );
+        assert_eq!(
+            manager.link_ports_by_name(
+                1,
+                "monitor_FR",
+                2,
+                "playback_FL"
+            ),
+            Ok(())
+        );
+        backend_thread.join().unwrap();
+        assert!(manager.drain_events().is_empty());
+
+        let backend_thread = answer(
+            &backend,
+            vec![
+                ConnectorEvent::PortsLinked(10, 21),
+                ConnectorEvent::PortsLinkFailed(10, 23),
+            ],
+        );
+        assert_eq!(
+            manager.link_ports_by_name(
+                1,
+                "monitor_FL",
+                2,
+                "playback_FR"
+            ),
+            Err(LinkError::LinkFailed(1, 2))
+        );
+        backend_thread.join().unwrap();
+        assert!(manager.drain_events().is_empty());
+
+        assert_eq!(
+            manager.link_ports_by_name(
+                1,
+                "capture_AUX0",
+                2,
+                "playback_FL"
+            ),
+            Err(LinkError::PortNotFound(
+                1,
+                "capture_AUX0".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn try_unlink_nodes_without_links_is_ok() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager.drain_events();
+
+        let backend_thread = answer(
+            &backend,
+            vec![ConnectorEvent::NothingToUnlink(1, 2)],
+        );
+        assert_eq!(manager.try_unlink_nodes(1, 2), Ok(()));
+        backend_thread.join().unwrap();
+        assert!(manager.drain_events().is_empty());
+    }
+
+    #[test]
+    fn try_unlink_nodes_reports_failures() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager.drain_events();
+
+        // The links between other nodes are not the answer
+        let backend_thread = answer(
+            &backend,
+            vec![
+                ConnectorEvent::UnlinkUpdate(3, 4),
+                ConnectorEvent::UnLinkFailed(1, 2),
+            ],
+        );
+        assert_eq!(
+            manager.try_unlink_nodes(1, 2),
+            Err(LinkError::UnlinkFailed(1, 2))
+        );
+        backend_thread.join().unwrap();
+        assert!(manager.drain_events().is_empty());
+    }
+
+    #[test]
+    fn try_unlink_nodes_timeout_gives_up() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        manager.drain_events();
+
+        assert_eq!(
+            manager.try_unlink_nodes_timeout(
+                1,
+                2,
+                Duration::from_millis(10)
+            ),
+            Err(LinkError::Timeout(1, 2))
+        );
+    }
+
+    #[test]
+    fn node_metadata_round_trip() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(1, "sink", 10));
+
+        assert_eq!(
+            manager.get_node_metadata(1, "myapp.routed"),
+            None
+        );
+
+        let objects = manager.get_objects();
+        let server = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(5));
+            // What the listener of the metadata object does once the
+            // server echoes the property
+            metadata::apply_property(
+                &objects,
+                1,
+                Some("myapp.routed"),
+                Some("true"),
+            );
+            backend
+                .send(ConnectorEvent::MetadataUpdate(
+                    1,
+                    "myapp.routed".to_owned(),
+                ))
+                .unwrap();
+        });
+        assert_eq!(
+            manager.set_node_metadata(1, "myapp.routed", "true"),
+            Ok(())
+        );
+        // Acknowledged, so already mirrored
+        assert_eq!(
+            manager.get_node_metadata(1, "myapp.routed"),
+            Some("true".to_owned())
+        );
+        server.join().unwrap();
+        assert_eq!(manager.get_node_metadata(1, "myapp.other"), None);
+        assert_eq!(
+            manager.get_node_metadata(2, "myapp.routed"),
+            None
+        );
+    }
+
+    #[test]
+    fn node_metadata_goes_away_with_the_metadata_object() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "sink", 10));
+            objects.metadata.global_id = Some(40);
+        }
+        metadata::apply_property(
+            &manager.objects,
+            1,
+            Some("myapp.routed"),
+            Some("true"),
+        );
+        let (sender, _receiver) = mpsc::channel();
+        let sender = Arc::new(RwLock::new(sender));
+
+        // Another object going away keeps the properties
+        PipeWireManager::_pw_remove_event_handler(
+            41,
+            &manager.objects,
+            sender.clone(),
+        );
+        assert_eq!(
+            manager.get_node_metadata(1, "myapp.routed"),
+            Some("true".to_owned())
+        );
+
+        PipeWireManager::_pw_remove_event_handler(
+            40,
+            &manager.objects,
+            sender,
+        );
+        assert_eq!(
+            manager.get_node_metadata(1, "myapp.routed"),
+            None
+        );
+        assert!(manager
+            .objects
+            .read()
+            .unwrap()
+            .find_node_by_exact_id(1)
+            .is_some());
+    }
+
+    #[test]
+    fn set_node_metadata_reports_failures() {
+        let (manager, backend) = PipeWireManager::mocked();
+        assert_eq!(
+            manager.set_node_metadata(1, "myapp.routed", "true"),
+            Err(ControlError::NodeNotFound(1))
+        );
+
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(1, "sink", 10));
+        backend
+            .send(ConnectorEvent::MetadataFailed(
+                1,
+                "myapp.routed".to_owned(),
+            ))
+            .unwrap();
+        assert_eq!(
+            manager.set_node_metadata(1, "myapp.routed", "true"),
+            Err(ControlError::MetadataFailed(
+                1,
+                "myapp.routed".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn graph_quantum_is_written_to_the_settings() {
+        let (manager, backend) = PipeWireManager::mocked();
+        assert_eq!(
+            manager.set_graph_quantum(100),
+            Err(ControlError::InvalidQuantum(100))
+        );
+        assert!(manager._receiver.try_recv().is_err());
+
+        let update = ConnectorEvent::MetadataUpdate(
+            0,
+            "clock.force-quantum".to_owned(),
+        );
+        backend.send(update.clone()).unwrap();
+        assert_eq!(manager.set_graph_quantum(128), Ok(()));
+        backend.send(update).unwrap();
+        assert_eq!(manager.clear_graph_quantum(), Ok(()));
+
+        backend
+            .send(ConnectorEvent::MetadataFailed(
+                0,
+                "clock.force-quantum".to_owned(),
+            ))
+            .unwrap();
+        assert_eq!(
+            manager.set_graph_quantum(256),
+            Err(ControlError::MetadataFailed(
+                0,
+                "clock.force-quantum".to_owned()
+            ))
+        );
+        assert!(manager._receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn link_nodes_retries_until_the_nodes_appear() {
+        let (manager, backend) = PipeWireManager::mocked();
+        backend.send(ConnectorEvent::LinkFailed(1, 2)).unwrap();
+        backend.send(ConnectorEvent::LinkUpdate(1, 2)).unwrap();
+
+        manager.link_nodes(1, 2);
+        assert!(manager._receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn link_nodes_does_not_retry_permanent_failures() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager.objects.write().unwrap().extend([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ]);
+        backend.send(ConnectorEvent::LinkFailed(1, 1)).unwrap();
+        backend.send(ConnectorEvent::LinkUpdate(1, 1)).unwrap();
+
+        manager.link_nodes(1, 1);
+        assert_eq!(
+            manager._receiver.try_recv().map(|event| event.kind),
+            Ok(ConnectorEvent::LinkUpdate(1, 1))
+        );
+    }
+
+    #[test]
+    fn set_node_target_writes_target_object() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager.objects.write().unwrap().extend([
+            stereo_node(1, "stream", 10),
+            stereo_node(2, "sink", 20),
+        ]);
+        assert_eq!(
+            manager.set_node_target(1, 9),
+            Err(ControlError::SerialNotFound(9))
+        );
+        assert_eq!(
+            manager.set_node_target(3, 2),
+            Err(ControlError::NodeNotFound(3))
+        );
+
+        backend
+            .send(ConnectorEvent::MetadataUpdate(
+                1,
+                "myapp.routed".to_owned(),
+            ))
+            .unwrap();
+        backend
+            .send(ConnectorEvent::MetadataUpdate(
+                1,
+                "target.object".to_owned(),
+            ))
+            .unwrap();
+        manager.objects.write().unwrap().metadata.update(
+            1,
+            Some("target.object"),
+            Some("2"),
+        );
+        assert_eq!(manager.set_node_target(1, 2), Ok(()));
+        assert_eq!(
+            manager.get_node_metadata(1, "target.object"),
+            Some("2".to_owned())
+        );
+        assert!(manager._receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn wait_for_link_active_follows_state_changes() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .links
+            .push(Link::from_parts(30, (1, 10), (2, 21)));
+
+        let objects = manager.get_objects();
+        let backend_thread = thread::spawn(move || {
+            for state in [
+                LinkState::Negotiating,
+                LinkState::Allocating,
+                LinkState::Paused,
+                LinkState::Active,
+            ] {
+                thread::sleep(Duration::from_millis(5));
+                objects.write().unwrap().links[0].state =
+                    state.clone();
+                backend
+                    .send(ConnectorEvent::LinkStateChanged(30, state))
+                    .unwrap();
+            }
+        });
+
+        assert_eq!(
+            manager.wait_for_link_active(30, Duration::from_secs(5)),
+            Ok(())
+        );
+        backend_thread.join().unwrap();
+    }
+
+    #[test]
+    fn move_node_output_unlinks_once_the_new_links_are_active() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .links
+            .push(Link::from_parts(20, (1, 10), (2, 21)));
+
+        let objects = manager.get_objects();
+        let backend_thread = thread::spawn(move || {
+            backend.send(ConnectorEvent::LinkUpdate(1, 3)).unwrap();
+            thread::sleep(Duration::from_millis(5));
+            objects.write().unwrap().links.extend([
+                Link::from_parts(30, (1, 10), (3, 31)),
+                Link::from_parts(31, (1, 12), (3, 33)),
+            ]);
+            for id in [30, 31] {
+                thread::sleep(Duration::from_millis(5));
+                objects
+                    .write()
+                    .unwrap()
+                    .links
+                    .iter_mut()
+                    .find(|link| link.id == id)
+                    .unwrap()
+                    .state = LinkState::Active;
+                backend
+                    .send(ConnectorEvent::LinkStateChanged(
+                        id,
+                        LinkState::Active,
+                    ))
+                    .unwrap();
+            }
+            backend.send(ConnectorEvent::UnlinkUpdate(1, 2)).unwrap();
+        });
+
+        assert_eq!(manager.move_node_output(1, 2, 3), Ok(()));
+        backend_thread.join().unwrap();
+        assert!(manager._receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn move_node_output_keeps_the_old_links_if_the_new_ones_fail() {
+        let (mut manager, backend) = PipeWireManager::mocked();
+        let (logger, raised) = raised_commands();
+        manager.backend_options.logger = Some(logger);
+        manager.objects.write().unwrap().links.extend([
+            Link::from_parts(20, (1, 10), (2, 21)),
+            // Already there before the move
+            Link::from_parts(25, (1, 12), (3, 33)),
+        ]);
+        manager.drain_events();
+
+        let objects = manager.get_objects();
+        let backend_thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            let mut failed = Link::from_parts(30, (1, 10), (3, 31));
+            failed.state = LinkState::Error("no format".to_owned());
+            objects.write().unwrap().links.push(failed);
+            backend.send(ConnectorEvent::LinkUpdate(1, 3)).unwrap();
+            backend
+                .send(ConnectorEvent::LinksRemoved {
+                    removed: 1,
+                    failed: 0,
+                })
+                .unwrap();
+        });
+        assert_eq!(
+            manager.move_node_output(1, 2, 3),
+            Err(LinkError::Wait(WaitError::LinkFailed(
+                30,
+                "no format".to_owned()
+            )))
+        );
+        backend_thread.join().unwrap();
+        assert!(manager.drain_events().is_empty());
+        // Neither the old links nor the ones into the new sink from
+        // before the move are removed
+        assert_eq!(
+            raised.lock().unwrap().last().map(String::as_str),
+            Some("\"RemoveLinksCommand([30])\"")
+        );
+    }
+
+    #[test]
+    fn wait_for_link_removed_follows_remove_events() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager.objects.write().unwrap().links.extend([
+            Link::from_parts(30, (1, 10), (2, 21)),
+            Link::from_parts(31, (1, 12), (2, 23)),
+        ]);
+
+        let objects = manager.get_objects();
+        let backend_thread = thread::spawn(move || {
+            for id in [31, 30] {
+                thread::sleep(Duration::from_millis(5));
+                objects.write().unwrap().links.retain(|l| l.id != id);
+                backend
+                    .send(ConnectorEvent::LinkRemoved(id))
+                    .unwrap();
+                backend
+                    .send(ConnectorEvent::UnlinkUpdate(1, 2))
+                    .unwrap();
+            }
+        });
+
+        assert_eq!(
+            manager.wait_for_link_removed(30, Duration::from_secs(5)),
+            Ok(())
+        );
+        backend_thread.join().unwrap();
+        assert_eq!(
+            manager.wait_for_link_removed(30, Duration::ZERO),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn wait_for_link_removed_times_out_on_lasting_links() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .links
+            .push(Link::from_parts(30, (1, 10), (2, 21)));
+        backend.send(ConnectorEvent::LinkRemoved(31)).unwrap();
+
+        assert_eq!(
+            manager
+                .wait_for_link_removed(30, Duration::from_millis(20)),
+            Err(WaitError::Timeout(30))
+        );
+    }
+
+    #[test]
+    fn wait_for_fully_unlinked_waits_for_every_channel() {
+        let (manager, backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "player", 10));
+            objects.insert_node(stereo_node(2, "speakers", 20));
+            objects.links.push(Link::from_parts(
+                30,
+                (1, 10),
+                (2, 21),
+            ));
+            objects.links.push(Link::from_parts(
+                31,
+                (1, 12),
+                (2, 23),
+            ));
+        }
+
+        let objects = manager.get_objects();
+        let backend_thread = thread::spawn(move || {
+            for link_id in [30, 31] {
+                thread::sleep(Duration::from_millis(20));
+                objects
+                    .write()
+                    .unwrap()
+                    .links
+                    .retain(|link| link.id != link_id);
+                backend
+                    .send(ConnectorEvent::UnlinkUpdate(1, 2))
+                    .unwrap();
+            }
+        });
+
+        assert_eq!(
+            manager.wait_for_fully_unlinked(
+                1,
+                2,
+                Duration::from_secs(5)
+            ),
+            Ok(())
+        );
+        assert_eq!(manager.link_count_between(1, 2), 0);
+        backend_thread.join().unwrap();
+    }
+
+    #[test]
+    fn wait_for_fully_unlinked_times_out_on_partial_unlinks() {
+        let (manager, backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.links.push(Link::from_parts(
+                31,
+                (1, 12),
+                (2, 23),
+            ));
+        }
+        // One channel was removed, the other one is still linked
+        backend.send(ConnectorEvent::UnlinkUpdate(1, 2)).unwrap();
+
+        assert_eq!(
+            manager.wait_for_fully_unlinked(
+                1,
+                2,
+                Duration::from_millis(20)
+            ),
+            Err(WaitError::StillLinked(1, 2))
+        );
+        assert_eq!(
+            manager.wait_for_fully_unlinked(
+                2,
+                1,
+                Duration::from_millis(20)
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn wait_for_link_active_reports_failures() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        assert_eq!(
+            manager
+                .wait_for_link_active(30, Duration::from_millis(10)),
+            Err(WaitError::LinkNotFound(30))
+        );
+
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .links
+            .push(Link::from_parts(30, (1, 10), (2, 21)));
+        assert_eq!(
+            manager
+                .wait_for_link_active(30, Duration::from_millis(10)),
+            Err(WaitError::Timeout(30))
+        );
+
+        manager.objects.write().unwrap().links[0].state =
+            LinkState::Error("no format".to_owned());
+        assert_eq!(
+            manager
+                .wait_for_link_active(30, Duration::from_millis(10)),
+            Err(WaitError::LinkFailed(30, "no format".to_owned()))
+        );
+    }
+
+    #[test]
+    fn global_mute_restores_prior_states() {
+        let (manager, backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            for (id, mute) in [(1, true), (2, false)] {
+                let mut sink = stereo_node(id, "sink", id * 10);
+                sink.media_class = Some("Audio/Sink".to_owned());
+                sink.mute = Some(mute);
+                objects.insert_node(sink);
+            }
+        }
+
+        backend.send(ConnectorEvent::ControlUpdate(1)).unwrap();
+        backend.send(ConnectorEvent::ControlUpdate(2)).unwrap();
+        assert_eq!(manager.mute_all_outputs(), Ok(()));
+        assert!(manager.is_output_muted());
+
+        backend.send(ConnectorEvent::ControlUpdate(1)).unwrap();
+        backend.send(ConnectorEvent::ControlFailed(2)).unwrap();
+        assert_eq!(
+            manager.unmute_all_outputs(),
+            Err(ControlError::ParamsFailed(2))
+        );
+        assert!(!manager.is_output_muted());
+        assert!(manager._receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn monitor_levels_waits_for_the_stream() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(1, "sink", 10));
+        assert!(matches!(
+            manager.monitor_levels(2, |_| {}),
+            Err(ControlError::NodeNotFound(2))
+        ));
+
+        backend.send(ConnectorEvent::MonitorFailed(0)).unwrap();
+        assert!(matches!(
+            manager.monitor_levels(1, |_| {}),
+            Err(ControlError::MonitorFailed(1))
+        ));
+
+        // Another monitor's answer is not mistaken for this one's.
+        backend.send(ConnectorEvent::MonitorStarted(0)).unwrap();
+        backend.send(ConnectorEvent::MonitorStarted(1)).unwrap();
+        let monitor = manager.monitor_levels(1, |_| {}).unwrap();
+        assert_eq!(monitor.node_id(), 1);
+        monitor.stop();
+    }
+
+    #[test]
+    fn debug_never_waits_for_the_objects_lock() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "sink", 10));
+            objects.links.push(Link::from_parts(
+                20,
+                (1, 10),
+                (1, 11),
+            ));
+        }
+        assert_eq!(
+            format!("{manager:?}"),
+            "PipeWireManager { running: false, nodes: 1, links: 1, .. }"
+        );
+
+        let _objects = manager.objects.write().unwrap();
+        assert_eq!(
+            format!("{manager:?}"),
+            "PipeWireManager { running: false, objects: <locked>, .. }"
+        );
+    }
+
+    #[test]
+    fn create_virtual_node_waits_for_the_node_to_appear() {
+        let (manager, backend) = PipeWireManager::mocked();
+        let spec = VirtualNodeSpec {
+            name: "easy_pw_mic".to_owned(),
+            description: None,
+            media_class: "Audio/Source/Virtual".to_owned(),
+            channels: 1,
+            channel_layout: vec![AudioChannel::MONO],
+        };
+
+        let objects = manager.get_objects();
+        let backend_thread = thread::spawn(move || {
+            backend
+                .send(ConnectorEvent::NodeCreated(
+                    "easy_pw_mic".to_owned(),
+                ))
+                .unwrap();
+            thread::sleep(Duration::from_millis(10));
+            objects
+                .write()
+                .unwrap()
+                .insert_node(Node::from_parts(7, "easy_pw_mic"));
+        });
+        assert_eq!(manager.create_virtual_node(spec.clone()), Ok(7));
+        backend_thread.join().unwrap();
+
+        assert_eq!(
+            manager.create_virtual_node(spec),
+            Err(CreateNodeError::NameTaken("easy_pw_mic".to_owned()))
+        );
+    }
+
+    #[test]
+    fn create_node_with_properties_keeps_custom_properties() {
+        let (manager, backend) = PipeWireManager::mocked();
+        let mut properties: HashMap<String, String> = [
+            ("factory.name", "support.null-audio-sink"),
+            ("media.class", "Audio/Sink"),
+            ("node.name", "easy_pw_tagged"),
+            ("media.role", "Music"),
+            ("easy-pw.tag", "living-room"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect();
+
+        let objects = manager.get_objects();
+        let announced = properties.clone();
+        let backend_thread = thread::spawn(move || {
+            backend
+                .send(ConnectorEvent::NodeCreated(
+                    "easy_pw_tagged".to_owned(),
+                ))
+                .unwrap();
+            thread::sleep(Duration::from_millis(10));
+            // What the server announces back
+            let mut props = properties! {
+                "object.serial" => "8",
+            };
+            for (key, value) in &announced {
+                props.insert(key.as_str(), value.as_str());
+            }
+            let node =
+                Node::new(&global(8, ObjectType::Node, &props))
+                    .unwrap();
+            objects.write().unwrap().insert_node(node);
+        });
+        assert_eq!(
+            manager.create_node_with_properties(properties.clone()),
+            Ok(8)
+        );
+        backend_thread.join().unwrap();
+
+        let objects = manager.objects.read().unwrap();
+        let node = objects.find_node_by_exact_id(8).unwrap();
+        assert_eq!(node.media_role.as_deref(), Some("Music"));
+        for key in ["media.role", "easy-pw.tag"] {
+            assert_eq!(node.properties.get(key), properties.get(key));
+        }
+        drop(objects);
+
+        properties.remove("media.class");
+        assert_eq!(
+            manager.create_node_with_properties(properties),
+            Err(CreateNodeError::MissingProperty("media.class"))
+        );
+    }
+
+    #[test]
+    fn server_info_is_none_until_received() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        manager.drain_events();
+        assert_eq!(manager.server_info(), None);
+
+        let info = ServerInfo {
+            name: "pipewire-0".to_owned(),
+            version: "1.2.7".to_owned(),
+            cookie: 1234,
+        };
+        let received = info.clone();
+        let result = manager.reinit_with(
+            |_, _, _, objects, _, init_sender, running| {
+                thread::spawn(move || {
+                    let _running = running;
+                    // What the info listener of the core does
+                    objects.write().unwrap().server_info =
+                        Some(received);
+                    init_sender.send(Ok(())).unwrap();
+                })
+            },
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(manager.server_info(), Some(info));
+        assert!(manager.drain_events().is_empty());
+
+        // Forgotten along with the previous connection
+        let result = manager.reinit_with(
+            |_, _, _, _, _, init_sender, running| {
+                thread::spawn(move || {
+                    let _running = running;
+                    init_sender.send(Ok(())).unwrap();
+                })
+            },
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(manager.server_info(), None);
+    }
+
+    #[test]
+    fn link_nodes_ignores_volume_and_mute_changes() {
+        let (manager, backend) = PipeWireManager::mocked();
+        backend
+            .send(ConnectorEvent::VolumeChanged {
+                node_id: 1,
+                volume: 0.5,
+            })
+            .unwrap();
+        backend
+            .send(ConnectorEvent::MuteChanged {
+                node_id: 2,
+                muted: true,
+            })
+            .unwrap();
+        backend.send(ConnectorEvent::LinkUpdate(1, 2)).unwrap();
+
+        manager.link_nodes(1, 2);
+        assert!(manager._receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn set_node_description_updates_the_cache() {
+        let (manager, backend) = PipeWireManager::mocked();
+        assert_eq!(
+            manager.set_node_description(1, "Music"),
+            Err(ControlError::NodeNotFound(1))
+        );
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(1, "virtual", 10));
+
+        backend.send(ConnectorEvent::ControlFailed(1)).unwrap();
+        assert_eq!(
+            manager.set_node_description(1, "Music"),
+            Err(ControlError::ParamsFailed(1))
+        );
+        assert_eq!(manager.node_info(1).unwrap().description, None);
+
+        backend.send(ConnectorEvent::ControlUpdate(1)).unwrap();
+        assert_eq!(manager.set_node_description(1, "Music"), Ok(()));
+        assert_eq!(
+            manager.node_info(1).unwrap().description.as_deref(),
+            Some("Music")
+        );
+        assert!(manager._receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn node_handle_sets_volume_repeatedly() {
+        let (manager, backend) = PipeWireManager::mocked();
+        assert!(matches!(
+            manager.bind_node(1),
+            Err(ControlError::NodeNotFound(1))
+        ));
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(1, "sink", 10));
+
+        let handle = manager.bind_node(1).unwrap();
+        for volume in [0.1, 0.2, 0.3] {
+            backend.send(ConnectorEvent::ControlUpdate(1)).unwrap();
+            assert_eq!(handle.set_volume(volume), Ok(()));
+        }
+        assert_eq!(
+            handle.set_volume(-1.0),
+            Err(ControlError::InvalidVolume(-1.0))
+        );
+        backend.send(ConnectorEvent::ControlFailed(1)).unwrap();
+        assert_eq!(
+            handle.set_mute(true),
+            Err(ControlError::ParamsFailed(1))
+        );
+        assert!(manager._receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn backend_thread_is_named() {
+        let backend = PipeWireManager::_spawn_backend_thread(|| {
+            assert_eq!(
+                thread::current().name(),
+                Some(BACKEND_THREAD_NAME)
+            );
+        });
+        assert_eq!(
+            backend.thread().name(),
+            Some(BACKEND_THREAD_NAME)
+        );
+        backend.join().unwrap();
+    }
+
+    #[test]
+    fn streams_are_routed_by_role() {
+        let (manager, backend) = PipeWireManager::mocked();
+        let stream = |id: u32, role: &str| {
+            let mut stream = stereo_node(id, "stream", id * 10);
+            stream.media_role = Some(role.to_owned());
+            stream
+        };
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "headset", 10));
+            objects.insert_node(stereo_node(2, "speakers", 20));
+            objects.insert_node(stream(3, "Communication"));
+            objects.insert_node(stream(4, "Music"));
+        }
+        assert_eq!(
+            manager.objects.read().unwrap().nodes[2].role(),
+            Some(MediaRole::Communication)
+        );
+
+        backend.send(ConnectorEvent::LinkUpdate(3, 1)).unwrap();
+        manager.route_by_role(MediaRole::Communication, 1);
+        assert!(manager._receiver.try_recv().is_err());
+
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_link(Link::from_parts(
+                50,
+                (3, 30),
+                (1, 11),
+            ));
+            objects.insert_node(stream(5, "Communication"));
+            objects.insert_node(stream(6, "Game"));
+        }
+        // The streams appearing later are linked by the backend
+        let (sender, receiver) = mpsc::channel();
+        let created = Mutex::new(vec![]);
+        PipeWireManager::_apply_link_rules(
+            &manager.objects,
+            |port, other_port| {
+                created
+                    .lock()
+                    .unwrap()
+                    .push((port.id, other_port.id));
+                Ok(())
+            },
+            &Arc::new(RwLock::new(sender)),
+            &[5, 6],
+        );
+        assert_eq!(
+            created.into_inner().unwrap(),
+            vec![(50, 11), (52, 13)]
+        );
+        assert_eq!(
+            receiver.try_recv(),
+            Ok(ConnectorEvent::LinkUpdate(5, 1))
+        );
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(manager.link_count_between(5, 1), 2);
+        assert_eq!(manager.link_count_between(6, 1), 0);
+    }
+
+    #[test]
+    fn link_rules_apply_until_removed() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        let rule = manager.add_link_rule(LinkRule {
+            output_match: NodeMatch::name("Firefox"),
+            input_match: NodeMatch::name("speakers"),
+            strategy: LinkStrategy::ChannelMatch,
+        });
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "speakers", 10));
+            objects.insert_node(stereo_node(2, "Firefox", 20));
+            objects.update_nodes();
+            assert_eq!(
+                objects.links_for_rules(&[2]),
+                vec![(2, 1, LinkStrategy::ChannelMatch)]
+            );
+        }
+
+        assert!(manager.remove_link_rule(rule));
+        assert!(!manager.remove_link_rule(rule));
+        assert!(manager
+            .objects
+            .read()
+            .unwrap()
+            .links_for_rules(&[2])
+            .is_empty());
+    }
+
+    #[test]
+    fn link_rules_link_matching_nodes_as_they_appear() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        manager.add_link_rule(LinkRule {
+            output_match: NodeMatch::name("Firefox"),
+            input_match: NodeMatch::name("speakers"),
+            strategy: LinkStrategy::ChannelMatch,
+        });
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(1, "speakers", 10));
+        let (sender, receiver) = mpsc::channel();
+        let sender = Arc::new(RwLock::new(sender));
+
+        // Firefox appears
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(2, "Firefox", 20));
+        let created = Mutex::new(vec![]);
+        PipeWireManager::_apply_link_rules(
+            &manager.objects,
+            |port, other_port| {
+                created
+                    .lock()
+                    .unwrap()
+                    .push((port.id, other_port.id));
+                Ok(())
+            },
+            &sender,
+            &[2],
+        );
+        assert_eq!(
+            created.into_inner().unwrap(),
+            vec![(20, 11), (22, 13)]
+        );
+        assert_eq!(
+            receiver.try_recv(),
+            Ok(ConnectorEvent::LinkUpdate(2, 1))
+        );
+        assert_eq!(manager.link_count_between(2, 1), 2);
+    }
+
+    #[test]
+    fn unknown_objects_are_only_recorded_on_demand() {
+        let objects =
+            Arc::new(RwLock::new(PipeWireObjects::default()));
+        let (sender, _receiver) = mpsc::channel();
+        let sender = Arc::new(RwLock::new(sender));
+        let props = properties! {
+            "factory.name" => "support.node.driver",
+            "factory.type.name" => "PipeWire:Interface:Node",
+        };
+        let announce = |id, record| {
+            PipeWireManager::_pw_event_handler(
+                &global(id, ObjectType::Factory, &props),
+                &objects,
+                sender.clone(),
+                None,
+                record,
+            )
+        };
+
+        announce(5, false);
+        assert!(objects.read().unwrap().other_objects.is_empty());
+
+        announce(6, true);
+        {
+            let objects = objects.read().unwrap();
+            let [factory] = objects.other_objects.as_slice() else {
+                panic!("expected a single object");
+            };
+            assert_eq!(factory.id, 6);
+            assert_eq!(factory.type_, ObjectType::Factory);
+            assert_eq!(
+                factory.props["factory.name"],
+                "support.node.driver"
+            );
+        }
+
+        PipeWireManager::_pw_remove_event_handler(
+            6,
+            &objects,
+            sender.clone(),
+        );
+        assert!(objects.read().unwrap().other_objects.is_empty());
+    }
+
+    #[test]
+    fn ports_without_a_node_are_reported_as_errors() {
+        let objects =
+            Arc::new(RwLock::new(PipeWireObjects::default()));
+        let (sender, receiver) = mpsc::channel();
+        let props = properties! {
+            "node.id" => "none",
+            "port.name" => "playback_FL",
+            "port.direction" => "in",
+            "port.alias" => "playback_FL",
+            "port.group" => "stream.0",
+            "object.serial" => "0",
+            "object.path" => "playback_FL",
+        };
+
+        let tracked = PipeWireManager::_pw_event_handler(
+            &global(10, ObjectType::Port, &props),
+            &objects,
+            Arc::new(RwLock::new(sender)),
+            None,
+            false,
+        );
+        assert!(!tracked);
+        assert!(objects
+            .read()
+            .unwrap()
+            ._ports_to_be_added
+            .is_empty());
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![ConnectorEvent::Error(
+                "Port 10 has an invalid node.id, ignoring it"
+                    .to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn removing_a_port_keeps_its_node() {
+        let objects =
+            Arc::new(RwLock::new(PipeWireObjects::default()));
+        objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(1, "sink", 10));
+        let (sender, _receiver) = mpsc::channel();
+        let sender = Arc::new(RwLock::new(sender));
+
+        PipeWireManager::_pw_remove_event_handler(
+            11,
+            &objects,
+            sender.clone(),
+        );
+        {
+            let objects = objects.read().unwrap();
+            let sink = objects.find_node_by_exact_id(1).unwrap();
+            assert!(!sink.has_port_of_id(11));
+            assert_eq!(sink.ports().len(), 3);
+        }
+
+        PipeWireManager::_pw_remove_event_handler(
+            1, &objects, sender,
+        );
+        assert!(objects.read().unwrap().nodes.is_empty());
+    }
+
+    #[test]
+    fn transactions_apply_every_operation() {
+        let (manager, backend) = PipeWireManager::mocked();
+        backend.send(ConnectorEvent::LinkUpdate(1, 2)).unwrap();
+        backend.send(ConnectorEvent::LinkUpdate(3, 2)).unwrap();
+
+        let result = manager.transaction(|transaction| {
+            transaction.link(1, 2);
+            transaction.link(3, 2);
+            "swapped"
+        });
+        assert_eq!(result, Ok("swapped"));
+        assert!(manager._receiver.try_recv().is_err());
+
+        backend.send(ConnectorEvent::UnLinkFailed(1, 2)).unwrap();
+        backend.send(ConnectorEvent::LinkUpdate(3, 2)).unwrap();
+        let result = manager.transaction(|transaction| {
+            transaction.unlink(1, 2);
+            transaction.link(3, 2);
+        });
+        assert_eq!(result, Err(LinkError::UnlinkFailed(1, 2)));
+        assert!(manager._receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn graph_export_streams_the_dot_description() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "source", 10));
+            objects.insert_node(stereo_node(2, "sink", 20));
+            objects.insert_link(Link::from_parts(
+                30,
+                (1, 10),
+                (2, 21),
+            ));
+        }
+
+        let mut written = vec![];
+        manager.export_graph_to_writer(&mut written).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            manager.objects.read().unwrap().to_dot()
+        );
+    }
+
+    #[test]
+    fn reused_node_ids_replace_the_previous_node() {
+        let objects =
+            Arc::new(RwLock::new(PipeWireObjects::default()));
+        let (sender, _receiver) = mpsc::channel();
+        let sender = Arc::new(RwLock::new(sender));
+        objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(1, "speakers", 10));
+        let version = objects.read().unwrap().graph_version();
+
+        let props = properties! {
+            "node.name" => "headphones",
+            "object.serial" => "2",
+        };
+        PipeWireManager::_pw_event_handler(
+            &global(1, ObjectType::Node, &props),
+            &objects,
+            sender,
+            None,
+            false,
+        );
+
+        let objects = objects.read().unwrap();
+        assert_eq!(objects.nodes.len(), 1);
+        assert_eq!(objects.nodes[0].name, "headphones");
+        assert!(objects.nodes[0].ports().is_empty());
+        assert_eq!(objects.node_id_of_port(10), None);
+        assert_eq!(
+            objects.changes_since(version),
+            Ok(vec![
+                GraphChange::NodeRemoved(1),
+                GraphChange::NodeAdded(1)
+            ])
+        );
+    }
+
+    #[test]
+    fn malformed_links_are_skipped() {
+        let objects =
+            Arc::new(RwLock::new(PipeWireObjects::default()));
+        let (sender, receiver) = mpsc::channel();
+        let props = properties! {
+            "link.output.port" => "10",
+            "link.input.node" => "2",
+            "link.input.port" => "20",
+        };
+
+        let tracked = PipeWireManager::_pw_event_handler(
+            &global(30, ObjectType::Link, &props),
+            &objects,
+            Arc::new(RwLock::new(sender)),
+            None,
+            false,
+        );
+        assert!(!tracked);
+        assert!(objects.read().unwrap().links.is_empty());
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(
+            Link::new(&global(30, ObjectType::Link, &props)).err(),
+            Some(LinkParseError::MissingProperty(
+                30,
+                "link.output.node"
+            ))
+        );
+    }
+
+    #[test]
+    fn node_filter_leaves_out_nodes_with_their_ports_and_links() {
+        let objects =
+            Arc::new(RwLock::new(PipeWireObjects::default()));
+        let (sender, receiver) = mpsc::channel();
+        let sender = Arc::new(RwLock::new(sender));
+        let filter = NodeFilter(Arc::new(|props: &DictRef| {
+            props.get("media.class") == Some("Audio/Sink")
+        }));
+        let announce = |id, type_, props| {
+            PipeWireManager::_pw_event_handler(
+                &global(id, type_, &props),
+                &objects,
+                sender.clone(),
+                Some(&filter),
+                false,
+            )
+        };
+
+        let node = |class: &str| {
+            properties! {
+                "node.name" => class,
+                "object.serial" => "0",
+                "media.class" => class,
+            }
+        };
+        let port = |node_id: &str, direction: &str| {
+            properties! {
+                "node.id" => node_id,
+                "port.name" => direction,
+                "port.direction" => direction,
+                "port.alias" => direction,
+                "port.group" => "stream.0",
+                "object.serial" => "0",
+                "object.path" => direction,
+            }
+        };
+        assert!(announce(1, ObjectType::Node, node("Audio/Sink")));
+        assert!(!announce(2, ObjectType::Node, node("Audio/Source")));
+        assert!(announce(10, ObjectType::Port, port("1", "in")));
+        assert!(!announce(11, ObjectType::Port, port("2", "out")));
+        let link = properties! {
+            "link.output.node" => "2",
+            "link.output.port" => "11",
+            "link.input.node" => "1",
+            "link.input.port" => "10",
+        };
+        assert!(!announce(20, ObjectType::Link, link));
+
+        let objects = objects.read().unwrap();
+        let ids: Vec<u32> =
+            objects.nodes.iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![1]);
+        assert_eq!(objects.nodes[0].ports().len(), 1);
+        assert!(objects.links.is_empty());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn init_errors_reach_the_constructor() {
+        let errors = [
+            InitError::MainLoopFailed("no memory".to_owned()),
+            InitError::ContextFailed("no memory".to_owned()),
+            InitError::ConnectFailed("no daemon".to_owned()),
+            InitError::RegistryFailed("no memory".to_owned()),
+        ];
+        let (manager, _backend) = PipeWireManager::mocked();
+        manager.drain_events();
+        for error in errors {
+            let reported = error.clone();
+            let result = manager.reinit_with(
+                |_, _, _, _, _, init_sender, running| {
+                    thread::spawn(move || {
+                        let _running = running;
+                        thread::sleep(Duration::from_millis(20));
+                        init_sender.send(Err(reported)).unwrap();
+                    })
+                },
+            );
+            assert_eq!(result, Err(error));
+        }
+
+        let result = manager.reinit_with(
+            |_, _, _, _, _, init_sender, running| {
+                thread::spawn(move || {
+                    let _running = running;
+                    thread::sleep(Duration::from_millis(20));
+                    drop(init_sender);
+                })
+            },
+        );
+        assert_eq!(result, Err(InitError::BackendExited));
+        assert!(manager.drain_events().is_empty());
+    }
+
+    #[test]
+    fn unlink_everything_skips_links_it_may_not_destroy() {
+        let (manager, backend) = PipeWireManager::mocked();
+        assert_eq!(manager.unlink_everything(), Ok((0, 0)));
+
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.links.push(Link::from_parts(
+                30,
+                (1, 10),
+                (2, 21),
+            ));
+            objects.links.push(Link::from_parts(
+                31,
+                (1, 12),
+                (2, 23),
+            ));
+            let mut locked = Link::from_parts(32, (3, 40), (2, 21));
+            locked.destroyable = false;
+            objects.links.push(locked);
+        }
+        backend
+            .send(ConnectorEvent::LinksRemoved {
+                removed: 2,
+                failed: 0,
+            })
+            .unwrap();
+        assert_eq!(manager.unlink_everything(), Ok((2, 1)));
+
+        backend
+            .send(ConnectorEvent::LinksRemoved {
+                removed: 0,
+                failed: 1,
+            })
+            .unwrap();
+        assert_eq!(
+            manager
+                .unlink_everything_matching(|link| link.id() == 30),
+            Err(LinkError::UnlinkAllFailed {
+                removed: 0,
+                failed: 1
+            })
+        );
+        assert_eq!(
+            manager
+                .unlink_everything_matching(|link| link.id() == 32),
+            Ok((0, 1))
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn event_stream_receives_events_in_order() {
+        use futures::executor::block_on;
+        use futures::StreamExt;
+
+        let (manager, backend) = PipeWireManager::mocked_streamed();
+        let mut stream = manager.event_stream();
+        let events = [
+            ConnectorEvent::LinkUpdate(1, 2),
+            ConnectorEvent::MuteChanged {
+                node_id: 1,
+                muted: true,
+            },
+            ConnectorEvent::UnlinkUpdate(1, 2),
+        ];
+        for event in events.iter().cloned() {
+            backend.send(event).unwrap();
+        }
+
+        let received: Vec<Event> =
+            block_on(stream.by_ref().take(3).collect());
+        let numbered: Vec<Event> = (0..)
+            .zip(events)
+            .map(|(seq, kind)| Event { seq, kind })
+            .collect();
+        assert_eq!(received, numbered);
+        // The manager still sees the events, numbered the same
+        let event =
+            manager._receiver.recv_timeout(Duration::from_secs(1));
+        assert_eq!(event, Ok(numbered[0].clone()));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn bounded_streams_count_the_events_they_miss() {
+        use futures::executor::block_on;
+        use futures::StreamExt;
+
+        let (manager, backend) = PipeWireManager::mocked_streamed();
+        let mut stream = manager.bounded_event_stream(2);
+        let send = |node_id| {
+            backend
+                .send(ConnectorEvent::ControlUpdate(node_id))
+                .unwrap();
+            // Forwarded to the streams before reaching the manager
+            manager
+                ._receiver
+                .recv_timeout(Duration::from_secs(1))
+                .unwrap();
+        };
+        let event = |seq, kind| Event { seq, kind };
+        for node_id in 0..5 {
+            send(node_id);
+        }
+
+        let received: Vec<Event> =
+            block_on(stream.by_ref().take(2).collect());
+        assert_eq!(
+            received,
+            vec![
+                event(0, ConnectorEvent::ControlUpdate(0)),
+                event(1, ConnectorEvent::ControlUpdate(1)),
+            ]
+        );
+        send(5);
+        let received: Vec<Event> =
+            block_on(stream.by_ref().take(2).collect());
+        assert_eq!(
+            received,
+            vec![
+                event(2, ConnectorEvent::Lagged(3)),
+                event(5, ConnectorEvent::ControlUpdate(5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn list_sinks_leaves_out_unavailable_sinks() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        let sink = |id: u32, available: Option<&str>| {
+            let mut props = properties! {
+                "node.name" => format!("sink{id}"),
+                "object.serial" => id.to_string(),
+                "media.class" => "Audio/Sink",
+            };
+            if let Some(available) = available {
+                props.insert("node.available", available);
+            }
+            Node::new(&global(id, ObjectType::Node, &props)).unwrap()
+        };
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(sink(1, Some("true")));
+            objects.insert_node(sink(2, Some("false")));
+            objects.insert_node(sink(3, None));
+            objects.insert_node(Node::from_parts(4, "source"));
+        }
+
+        let ids = |nodes: Vec<NodeInfo>| {
+            nodes.iter().map(|node| node.id).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(manager.list_sinks()), vec![1, 3]);
+        assert_eq!(
+            ids(manager.list_nodes(NodeKind::Sink, true)),
+            vec![1, 2, 3]
+        );
+        assert!(manager.list_sources().is_empty());
+        assert!(!manager.node_info(2).unwrap().available);
+    }
+
+    #[test]
+    fn nodes_matching_filters_with_any_predicate() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "stereo", 10));
+            let mut surround = stereo_node(2, "surround", 20);
+            surround.add_port(Port::from_parts(
+                24,
+                2,
+                "monitor_RL",
+                PortDirection::Out,
+                "RL",
+            ));
+            objects.insert_node(surround);
+        }
+
+        let names: Vec<String> = manager
+            .nodes_matching(|node| node.output_ports().count() > 2)
+            .into_iter()
+            .map(|info| info.name)
+            .collect();
+        assert_eq!(names, vec!["surround".to_owned()]);
+        assert!(manager.nodes_matching(|_| false).is_empty());
+    }
+
+    #[test]
+    fn node_info_summarizes_one_node() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        manager.objects.write().unwrap().insert_node(stereo_node(
+            1,
+            "alsa_output.usb-headset",
+            10,
+        ));
+        assert_eq!(manager.node_info(2), None);
+        // Port ids are not node ids
+        assert_eq!(manager.node_info(10), None);
+
+        let info = manager.node_info(1).unwrap();
+        assert_eq!(info.id, 1);
+        assert_eq!(info.name, "alsa_output.usb-headset");
+        assert_eq!(info.short_name, "Usb Headset");
+        assert_eq!(info.object_serial, "1");
+        assert_eq!(info.kind, NodeKind::Other);
+        assert_eq!((info.input_ports, info.output_ports), (2, 2));
+        assert_eq!((info.mute, info.volume), (None, None));
+    }
+
+    #[test]
+    fn find_node_resolves_every_selector() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        let headset = |id: u32, path: &str| {
+            let props = properties! {
+                "node.name" => "alsa_output.usb-headset",
+                "media.class" => "Audio/Sink",
+                "object.path" => path,
+                "object.serial" => (id + 100).to_string(),
+            };
+            Node::new(&global(id, ObjectType::Node, &props)).unwrap()
+        };
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "speakers", 10));
+            objects.insert_node(headset(
+                2,
+                "alsa:pcm:1:front:1:playback",
+            ));
+        }
+        let found = |by| manager.find_node(by).map(|node| node.id);
+
+        assert_eq!(found(NodeSelector::Id(2)), Some(2));
+        assert_eq!(found(NodeSelector::Id(10)), None);
+        assert_eq!(found(NodeSelector::Serial(102)), Some(2));
+        assert_eq!(found(NodeSelector::Serial(2)), None);
+        assert_eq!(
+            found(NodeSelector::Name("speakers".to_owned())),
+            Some(1)
+        );
+        assert_eq!(
+            found(NodeSelector::ObjectPath(
+                "alsa:pcm:1:front:1:playback".to_owned()
+            )),
+            Some(2)
+        );
+        assert_eq!(
+            found(NodeSelector::ObjectPath("alsa:pcm:9".to_owned())),
+            None
+        );
+        let fingerprint = "Audio/Sink|alsa_output.usb-headset|alsa:pcm:1:front:1:playback";
+        assert_eq!(
+            manager.objects.read().unwrap().nodes[1].fingerprint(),
+            fingerprint
+        );
+        assert_eq!(
+            found(NodeSelector::Fingerprint(fingerprint.to_owned())),
+            Some(2)
+        );
+        assert_eq!(
+            found(NodeSelector::Fingerprint(
+                "Audio/Sink|alsa_output.usb-headset|".to_owned()
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn try_nodes_snapshot_skips_locked_objects() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(1, "speakers", 10));
+
+        let objects = manager.objects.write().unwrap();
+        assert_eq!(manager.try_nodes_snapshot(), None);
+        drop(objects);
+        let snapshot = manager.try_nodes_snapshot().unwrap();
+        assert_eq!(
+            snapshot.iter().map(|node| node.id).collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn port_info_summarizes_one_port() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(1, "speakers", 10));
+        assert_eq!(manager.port_info(99), None);
+        // Node ids are not port ids
+        assert_eq!(manager.port_info(1), None);
+
+        let info = manager.port_info(13).unwrap();
+        assert_eq!(info.id, 13);
+        assert_eq!(info.node_id, 1);
+        assert_eq!(info.name, "playback_FR");
+        assert_eq!(info.alias, "alias:playback_FR");
+        assert_eq!(info.direction, PortDirection::In);
+        assert_eq!(info.audio_channel, AudioChannel::FR);
+        assert_eq!(
+            info.format_dsp.as_deref(),
+            Some("32 bit float mono audio")
+        );
+        assert!(!info.physical);
+    }
+
+    #[test]
+    fn link_and_set_volume_reports_what_was_done() {
+        let (manager, backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "player", 10));
+            objects.insert_node(stereo_node(2, "speakers", 20));
+        }
+
+        assert_eq!(
+            manager.link_and_set_volume(1, 2, -1.0),
+            Err(LinkError::Control(ControlError::InvalidVolume(
+                -1.0
+            )))
+        );
+
+        backend.send(ConnectorEvent::LinkUpdate(1, 2)).unwrap();
+        backend.send(ConnectorEvent::ControlUpdate(1)).unwrap();
+        assert_eq!(manager.link_and_set_volume(1, 2, 0.5), Ok(()));
+
+        backend.send(ConnectorEvent::LinkUpdate(1, 2)).unwrap();
+        backend.send(ConnectorEvent::ControlFailed(1)).unwrap();
+        assert_eq!(
+            manager.link_and_set_volume(1, 2, 0.5),
+            Err(LinkError::LinkedButVolumeFailed(
+                1,
+                2,
+                ControlError::ParamsFailed(1)
+            ))
+        );
+
+        backend.send(ConnectorEvent::LinkFailed(1, 2)).unwrap();
+        assert_eq!(
+            manager.link_and_set_volume(1, 2, 0.5),
+            Err(LinkError::LinkFailed(1, 2))
+        );
+        assert!(manager._receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn link_stereo_requires_stereo_nodes() {
+        let (manager, backend) = PipeWireManager::mocked();
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.insert_node(stereo_node(1, "player", 10));
+            objects.insert_node(stereo_node(2, "speakers", 20));
+            let mut microphone = Node::from_parts(3, "microphone");
+            microphone.add_port(Port::from_parts(
+                30,
+                3,
+                "capture_MONO",
+                PortDirection::Out,
+                "MONO",
+            ));
+            objects.insert_node(microphone);
+        }
+
+        // Another link between the nodes does not answer for FL
+        backend.send(ConnectorEvent::LinkUpdate(1, 2)).unwrap();
+        backend.send(ConnectorEvent::PortsLinked(10, 21)).unwrap();
+        backend.send(ConnectorEvent::PortsLinked(12, 23)).unwrap();
+        assert_eq!(manager.link_stereo(1, 2), Ok(()));
+        assert!(manager._receiver.try_recv().is_err());
+
+        let error = manager.link_stereo(3, 2).unwrap_err();
+        assert_eq!(
+            error,
+            LinkError::NotStereo(3, PortDirection::Out)
+        );
+        assert_eq!(
+            error.to_string(),
+            "Node 3 does not have both an FL and an FR Out port"
+        );
+        assert_eq!(
+            manager.link_stereo(4, 2),
+            Err(LinkError::NodeNotFound(4))
+        );
+    }
+
+    #[test]
+    fn filtered_callbacks_only_get_matching_events() {
+        let (manager, backend) =
+            PipeWireManager::mocked_with_callbacks();
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        manager.on_event_filtered(
+            |event| matches!(event, ConnectorEvent::LinkFailed(..)),
+            {
+                let failures = failures.clone();
+                move |event| {
+                    failures.lock().unwrap().push(event.clone())
+                }
+            },
+        );
+        let (all, all_received) = mpsc::channel();
+        manager
+            .on_event(move |event| all.send(event.clone()).unwrap());
+
+        let events = [
+            ConnectorEvent::LinkUpdate(1, 2),
+            ConnectorEvent::LinkFailed(1, 3),
+            ConnectorEvent::UnlinkUpdate(1, 2),
+        ];
+        for event in events.clone() {
+            backend.send(event).unwrap();
+        }
+        for (seq, kind) in (0..).zip(events) {
+            assert_eq!(
+                all_received.recv_timeout(Duration::from_secs(1)),
+                Ok(Event { seq, kind })
+            );
+        }
+        assert_eq!(
+            *failures.lock().unwrap(),
+            vec![Event {
+                seq: 1,
+                kind: ConnectorEvent::LinkFailed(1, 3)
+            }]
+        );
+    }
+
+    #[test]
+    fn callbacks_do_not_hold_back_the_manager() {
+        let (manager, backend) =
+            PipeWireManager::mocked_with_callbacks();
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let (seen, seen_received) = mpsc::channel();
+        manager.on_event(move |event| {
+            if let ConnectorEvent::LinkFailed(..) = event.kind {
+                panic!("callback failure");
+            }
+            let _result = released.lock().unwrap().recv();
+            seen.send(event.kind.clone()).unwrap();
+        });
+
+        // The manager gets the events while the callback is stuck
+        for event in [
+            ConnectorEvent::LinkFailed(1, 2),
+            ConnectorEvent::LinkUpdate(1, 2),
+            ConnectorEvent::UnlinkUpdate(1, 2),
+        ] {
+            backend.send(event.clone()).unwrap();
+            assert_eq!(
+                manager
+                    ._receiver
+                    .recv_timeout(Duration::from_secs(1))
+                    .map(|event| event.kind),
+                Ok(event)
+            );
+        }
+
+        // A panicking callback does not stop the following events
+        for event in [
+            ConnectorEvent::LinkUpdate(1, 2),
+            ConnectorEvent::UnlinkUpdate(1, 2),
+        ] {
+            release.send(()).unwrap();
+            assert_eq!(
+                seen_received.recv_timeout(Duration::from_secs(1)),
+                Ok(event)
+            );
+        }
+    }
+
+    #[test]
+    fn link_calls_from_callbacks_fail_instead_of_hanging() {
+        let (manager, backend) =
+            PipeWireManager::mocked_with_callbacks();
+        let manager = Arc::new(manager);
+        let (results, received) = mpsc::channel();
+        manager.on_event({
+            let manager = Arc::downgrade(&manager);
+            move |_| {
+                let Some(manager) = manager.upgrade() else {
+                    return;
+                };
+                let _result = results.send((
+                    manager.ensure_linked(1, 2),
+                    manager.try_unlink_nodes(1, 2),
+                    manager.wait_for_link_removed(
+                        30,
+                        Duration::from_secs(1),
+                    ),
+                ));
+            }
+        });
+        {
+            let mut objects = manager.objects.write().unwrap();
+            objects.extend([
+                stereo_node(1, "source", 10),
+                Node::from_parts(2, "sink"),
+            ]);
+            objects.links.push(Link::from_parts(
+                30,
+                (3, 31),
+                (4, 41),
+            ));
+        }
+
+        backend.send(ConnectorEvent::None).unwrap();
+        assert_eq!(
+            received.recv_timeout(Duration::from_secs(1)),
+            Ok((
+                Err(LinkError::ReentrantCall),
+                Err(LinkError::ReentrantCall),
+                Err(WaitError::ReentrantCall)
+            ))
+        );
+    }
+
+    #[test]
+    fn controls_from_callbacks_send_nothing_to_the_backend() {
+        let (mut manager, backend) =
+            PipeWireManager::mocked_with_callbacks();
+        let (logger, raised) = raised_commands();
+        manager.backend_options.logger = Some(logger.clone());
+        let manager = Arc::new(manager);
+        let (results, received) = mpsc::channel();
+        manager.on_event({
+            let manager = Arc::downgrade(&manager);
+            move |_| {
+                let Some(manager) = manager.upgrade() else {
+                    return;
+                };
+                let _scope = logging::scope(Some(logger.clone()));
+                let _result = results.send((
+                    manager.set_node_mute(1, true),
+                    manager.set_graph_quantum(256),
+                    manager.monitor_levels(1, |_| {}).err(),
+                    manager.create_virtual_node(VirtualNodeSpec {
+                        name: "easy_pw_sink".to_owned(),
+                        description: None,
+                        media_class: "Audio/Sink".to_owned(),
+                        channels: 2,
+                        channel_layout: vec![],
+                    }),
+                ));
+            }
+        });
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(stereo_node(1, "sink", 10));
+
+        backend.send(ConnectorEvent::None).unwrap();
+        assert_eq!(
+            received.recv_timeout(Duration::from_secs(1)),
+            Ok((
+                Err(ControlError::ReentrantCall),
+                Err(ControlError::ReentrantCall),
+                Some(ControlError::ReentrantCall),
+                Err(CreateNodeError::ReentrantCall),
+            ))
+        );
+        assert!(raised.lock().unwrap().is_empty());
+        assert_eq!(manager.next_monitor_id.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn blocking_calls_from_the_backend_thread_fail() {
+        let (manager, _main_sender) = PipeWireManager::mocked();
+        let manager = Arc::new(manager);
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .links
+            .push(Link::from_parts(30, (1, 10), (2, 20)));
+
+        let result = thread::Builder::new()
+            .name(BACKEND_THREAD_NAME.to_owned())
+            .spawn({
+                let manager = manager.clone();
+                move || {
+                    manager.wait_for_link_removed(
+                        30,
+                        Duration::from_secs(1),
+                    )
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(result, Err(WaitError::ReentrantCall));
+    }
+
+    #[test]
+    fn on_ready_runs_once_after_discovery() {
+        let (manager, backend) =
+            PipeWireManager::mocked_with_callbacks();
+        let (ready, ready_received) = mpsc::channel();
+        manager.on_ready({
+            let objects = manager.objects.clone();
+            move || {
+                let nodes = objects.read().unwrap().nodes.len();
+                ready.send(nodes).unwrap();
+            }
+        });
+        let (each_ready, each_ready_received) = mpsc::channel();
+        manager.on_each_ready(move || each_ready.send(()).unwrap());
+        assert!(!manager.is_ready());
+
+        // Discovery, then the server answering the initial sync
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(Node::from_parts(1, "speakers"));
+        PipeWireManager::_set_ready(&manager.objects);
+        backend.send(ConnectorEvent::Ready).unwrap();
+        assert_eq!(
+            ready_received.recv_timeout(Duration::from_secs(1)),
+            Ok(1)
+        );
+        assert!(manager.is_ready());
+        // Nothing is kept once it ran
+        assert!(manager
+            .objects
+            .read()
+            .unwrap()
+            .ready_callbacks
+            .lock()
+            .unwrap()
+            .is_empty());
+
+        // Reconnecting
+        manager.objects.write().unwrap().clear_graph();
+        PipeWireManager::_set_ready(&manager.objects);
+        backend.send(ConnectorEvent::Ready).unwrap();
+        for _ in 0..2 {
+            assert_eq!(
+                each_ready_received
+                    .recv_timeout(Duration::from_secs(1)),
+                Ok(())
+            );
+        }
+        assert!(ready_received.try_recv().is_err());
+
+        let ran = Arc::new(AtomicBool::new(false));
+        manager.on_ready({
+            let ran = ran.clone();
+            move || ran.store(true, Ordering::SeqCst)
+        });
+        assert!(ran.load(Ordering::SeqCst));
+        assert!(manager
+            .objects
+            .read()
+            .unwrap()
+            .ready_callbacks
+            .lock()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn on_default_changed_follows_the_default_sink() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        manager.objects.write().unwrap().extend([
+            stereo_node(1, "speakers", 10),
+            stereo_node(2, "headphones", 20),
+        ]);
+        let (sender, receiver) = mpsc::channel();
+        manager.on_default_changed(move |kind, node_id| {
+            sender.send((kind, node_id)).unwrap();
+        });
+
+        let set_default = |kind: DefaultKind, name: &str| {
+            metadata::apply_property(
+                &manager.objects,
+                0,
+                Some(kind.key()),
+                Some(&format!(r#"{{"name":"{name}"}}"#)),
+            );
+        };
+
+        set_default(DefaultKind::Sink, "speakers");
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![(DefaultKind::Sink, Some(1))]
+        );
+        // Every later change is reported too
+        set_default(DefaultKind::Sink, "headphones");
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![(DefaultKind::Sink, Some(2))]
+        );
+        set_default(DefaultKind::Source, "headphones");
+        set_default(DefaultKind::Sink, "speakers");
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![
+                (DefaultKind::Source, Some(2)),
+                (DefaultKind::Sink, Some(1))
+            ]
+        );
+    }
+
+    #[test]
+    fn drain_events_returns_pending_events_in_order() {
+        let (manager, backend) = PipeWireManager::mocked();
+        assert!(manager.drain_events().is_empty());
+
+        let events = vec![
+            ConnectorEvent::LinkUpdate(1, 2),
+            ConnectorEvent::LinkStateChanged(3, LinkState::Active),
+            ConnectorEvent::UnlinkUpdate(1, 2),
+        ];
+        for event in events.iter().cloned() {
+            backend.send(event).unwrap();
+        }
+        let numbered: Vec<Event> = (0..)
+            .zip(events)
+            .map(|(seq, kind)| Event { seq, kind })
+            .collect();
+        assert_eq!(manager.drain_events(), numbered);
+        assert!(manager.drain_events().is_empty());
+    }
+
+    #[test]
+    fn watched_node_properties_run_their_callbacks() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(Node::from_parts(1, "sink"));
+        let values = Arc::new(Mutex::new(vec![]));
+        let recorded = values.clone();
+        manager.subscribe_node_property(
+            1,
+            "node.latency",
+            move |v| {
+                recorded.lock().unwrap().push(v.to_owned());
+            },
+        );
+        let update = |latency: &str, rate: &str| {
+            let props = properties! {
+                "node.latency" => latency,
+                "node.rate" => rate,
+            };
+            apply_node_props(&manager.objects, 1, props.dict());
+        };
+
+        update("256/48000", "1/48000");
+        update("256/48000", "1/44100");
+        update("1024/48000", "1/44100");
+
+        assert_eq!(
+            *values.lock().unwrap(),
+            vec!["256/48000", "1024/48000"]
+        );
+    }
+
+    #[test]
+    fn driver_info_reports_the_driving_node() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        assert_eq!(manager.driver_info(), None);
+
+        let node = |id: u32, driver_id: u32, latency: &str| {
+            let props = properties! {
+                "node.name" => format!("node{id}"),
+                "object.serial" => id.to_string(),
+                "node.driver-id" => driver_id.to_string(),
+                "node.latency" => latency,
+            };
+            Node::new(&global(id, ObjectType::Node, &props)).unwrap()
+        };
+        let mut speakers = node(1, 1, "");
+        let props = properties! {
+            "clock.quantum" => "1024",
+            "node.rate" => "1/48000",
+        };
+        speakers.apply_info_props(props.dict());
+        manager.objects.write().unwrap().extend([
+            speakers,
+            node(2, 1, "256/48000"),
+            node(3, 1, ""),
+            node(4, 4, "128/44100"),
+        ]);
+
+        assert_eq!(
+            manager.driver_info(),
+            Some(DriverInfo {
+                node_id: 1,
+                quantum: Some(1024),
+                rate: Some(48000),
+            })
+        );
+        let objects = manager.objects.read().unwrap();
+        let other_driver = objects.find_node_by_exact_id(4).unwrap();
+        assert_eq!(
+            (other_driver.quantum, other_driver.rate),
+            (Some(128), Some(44100))
+        );
+    }
+
+    #[test]
+    fn link_with_mapping_reports_every_pair() {
+        let (manager, backend) = PipeWireManager::mocked();
+        manager.objects.write().unwrap().extend([
+            stereo_node(1, "player", 10),
+            stereo_node(2, "speakers", 20),
+        ]);
+        backend.send(ConnectorEvent::PortsLinked(10, 23)).unwrap();
+        backend
+            .send(ConnectorEvent::PortsLinkFailed(12, 21))
+            .unwrap();
+
+        let results = manager.link_with_mapping(
+            1,
+            2,
+            &[
+                (AudioChannel::FL, AudioChannel::FR),
+                (AudioChannel::LFE, AudioChannel::FL),
+                (AudioChannel::FR, AudioChannel::FL),
+            ],
+        );
+        assert_eq!(
+            results,
+            vec![
+                Ok(()),
+                Err(LinkError::ChannelNotFound(
+                    1,
+                    AudioChannel::LFE,
+                    PortDirection::Out
+                )),
+                Err(LinkError::LinkFailed(1, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reinit_keeps_the_manager_usable() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        manager
+            .objects
+            .write()
+            .unwrap()
+            .insert_node(Node::from_parts(9999, "before-reinit"));
+        assert!(!manager.is_running());
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let result = manager.reinit_with(
+            |_, sender, _, _, _, init_sender, running| {
+                thread::spawn(move || {
+                    let _running = running;
+                    init_sender.send(Ok(())).unwrap();
+                    // Answering the command the manager sends next
+                    sender
+                        .send(ConnectorEvent::UnlinkUpdate(1, 2))
+                        .unwrap();
+                    let _result = stopped.recv();
+                })
+            },
+        );
+        assert_eq!(result, Ok(()));
+        assert!(manager.is_running());
+        assert!(manager
+            .objects
+            .read()
+            .unwrap()
+            .find_node_by_exact_id(9999)
+            .is_none());
+
+        // The new backend answers through the same channel
+        assert_eq!(manager.try_unlink_nodes(1, 2), Ok(()));
+        stop.send(()).unwrap();
+    }
+
+    #[test]
+    fn reinit_reports_a_backend_failing_to_connect() {
+        let (manager, _backend) = PipeWireManager::mocked();
+        let result = manager.reinit_with(
+            |_, _, _, _, _, init_sender, running| {
+                thread::spawn(move || {
+                    let _running = running;
+                    let _result = init_sender.send(Err(
+                        InitError::ConnectFailed(
+                            "refused".to_owned(),
+                        ),
+                    ));
+                })
+            },
+        );
+        assert_eq!(
+            result,
+            Err(InitError::ConnectFailed("refused".to_owned()))
+        );
+    }
+
+    #[test]
+    fn is_running_follows_the_backend_thread() {
+        let (mut manager, _backend) = PipeWireManager::mocked();
+        assert!(!manager.is_running());
+
+        let running = Arc::new(AtomicBool::new(true));
+        let guard = RunningGuard(running.clone());
+        let (stop, stopped) = mpsc::channel::<()>();
+        manager.running = running;
+        manager._main_thread =
+            RwLock::new(thread::spawn(move || {
+                let _running = guard;
+                let _result = stopped.recv();
+            }));
+        assert!(manager.is_running());
+
+        drop(stop);
+        while !manager._main_thread.read().unwrap().is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!manager.is_running());
+        assert!(!manager.running.load(Ordering::SeqCst));
     }
 }
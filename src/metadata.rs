@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc};
+
+use libspa::utils::dict::DictRef;
+use pipewire::metadata::{Metadata, MetadataListener};
+use pipewire::registry::{GlobalObject, Registry};
+
+use crate::event::ConnectorEvent;
+use crate::objects::PipeWireObjects;
+use crate::sync::RwLock;
+
+/// Name of the metadata object shared by the session manager and
+/// the tools cooperating with it.
+pub const DEFAULT_METADATA_NAME: &str = "default";
+
+/// Name of the metadata object holding the settings of the server,
+/// such as its clock settings.
+pub const SETTINGS_METADATA_NAME: &str = "settings";
+
+/// The setting forcing the quantum of the graph, 0 to stop forcing
+/// it.
+pub(crate) const FORCE_QUANTUM_KEY: &str = "clock.force-quantum";
+
+/// Subject used by PipeWire to address every subject at once.
+const ANY_SUBJECT: u32 = u32::MAX;
+
+/// Which default device of the session manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefaultKind {
+    Sink,
+    Source,
+}
+
+impl DefaultKind {
+    /// The key of the metadata property holding the default device,
+    /// on the core subject.
+    pub fn key(&self) -> &'static str {
+        match self {
+            DefaultKind::Sink => "default.audio.sink",
+            DefaultKind::Source => "default.audio.source",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        [DefaultKind::Sink, DefaultKind::Source]
+            .into_iter()
+            .find(|kind| kind.key() == key)
+    }
+}
+
+/// Called with the new default node, `None` when the default was
+/// unset or names a node that is not known yet.
+pub(crate) type DefaultCallback =
+    dyn Fn(DefaultKind, Option<u32>) + Send + Sync;
+
+/// The node name of a default device property, a JSON object which
+/// looks like `{ "name": "alsa_output.pci-0000_00_1f.3.analog-stereo" }`.
+fn default_node_name(value: &str) -> Option<String> {
+    let value: serde_json::Value =
+        serde_json::from_str(value).ok()?;
+    value.get("name")?.as_str().map(str::to_owned)
+}
+
+/// Apply a property event of the metadata object, running the
+/// default device callbacks if a default device changed.
+/// The callbacks run once the objects are unlocked, so they can use
+/// the manager.
+pub(crate) fn apply_property(
+    objects: &RwLock<PipeWireObjects>,
+    subject: u32,
+    key: Option<&str>,
+    value: Option<&str>,
+) {
+    let mut objects_guard = objects.write().unwrap();
+    let kind =
+        key.filter(|_| subject == 0).and_then(DefaultKind::from_key);
+    objects_guard.metadata.update(subject, key, value);
+    let Some(kind) = kind else {
+        return;
+    };
+    // Compared by node name, the JSON may be written differently
+    let name = value.and_then(default_node_name);
+    let defaults = &mut objects_guard.metadata.defaults;
+    if defaults.get(&kind) == Some(&name) {
+        return;
+    }
+    defaults.insert(kind, name.clone());
+    let node_id = name.and_then(|name| {
+        objects_guard
+            .nodes
+            .iter()
+            .find(|node| node.name == name)
+            .map(|node| node.id)
+    });
+    let callbacks = objects_guard.default_callbacks.clone();
+    drop(objects_guard);
+    for callback in callbacks {
+        callback(kind, node_id);
+    }
+}
+
+/// Local copy of the properties of the default metadata object.
+///
+/// PipeWire metadata maps a `(subject, key)` pair to a value, the
+/// subject being the global id of the object the property is about
+/// (0 for the core itself, a node id for node properties).
+#[derive(Default, Debug)]
+pub struct MetadataStore {
+    properties: HashMap<u32, HashMap<String, String>>,
+    /// Global id of the metadata object mirrored, once bound.
+    pub(crate) global_id: Option<u32>,
+    /// Name of the default node last reported of each kind, `None`
+    /// if it was unset.
+    defaults: HashMap<DefaultKind, Option<String>>,
+}
+
+impl MetadataStore {
+    pub fn get(&self, subject: u32, key: &str) -> Option<&str> {
+        self.properties
+            .get(&subject)
+            .and_then(|properties| properties.get(key))
+            .map(String::as_str)
+    }
+
+    /// Apply a property event of the metadata object.
+    /// A missing value removes the key and a missing key removes
+    /// every property of the subject.
+    pub(crate) fn update(
+        &mut self,
+        subject: u32,
+        key: Option<&str>,
+        value: Option<&str>,
+    ) {
+        match (key, value) {
+            (None, _) if subject == ANY_SUBJECT => {
+                self.properties.clear();
+            }
+            (None, _) => {
+                self.properties.remove(&subject);
+            }
+            (Some(key), None) => {
+                if let Some(properties) =
+                    self.properties.get_mut(&subject)
+                {
+                    properties.remove(key);
+                    if properties.is_empty() {
+                        self.properties.remove(&subject);
+                    }
+                }
+            }
+            (Some(key), Some(value)) => {
+                self.properties
+                    .entry(subject)
+                    .or_default()
+                    .insert(key.to_owned(), value.to_owned());
+            }
+        }
+    }
+}
+
+/// A metadata object bound by the backend thread.
+pub(crate) struct BoundMetadata {
+    /// Global id of the metadata object.
+    pub(crate) id: u32,
+    pub(crate) metadata: Metadata,
+    _listener: MetadataListener,
+}
+
+impl BoundMetadata {
+    pub(crate) fn is_named(
+        global: &GlobalObject<&DictRef>,
+        name: &str,
+    ) -> bool {
+        global
+            .props
+            .and_then(|props| props.get("metadata.name"))
+            .is_some_and(|metadata_name| metadata_name == name)
+    }
+
+    /// Bind the metadata object, reporting every change through the
+    /// sender. Its properties are mirrored into the objects if given,
+    /// as for the default metadata object.
+    pub(crate) fn bind(
+        registry: &Registry,
+        global: &GlobalObject<&DictRef>,
+        objects: Option<Arc<RwLock<PipeWireObjects>>>,
+        sender: Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
+    ) -> Result<Self, pipewire::Error> {
+        let metadata = registry.bind::<Metadata, _>(global)?;
+        if let Some(objects) = &objects {
+            // A new metadata object starts without properties
+            objects.write().unwrap().metadata = MetadataStore {
+                global_id: Some(global.id),
+                ..MetadataStore::default()
+            };
+        }
+        let listener = metadata
+            .add_listener_local()
+            .property(move |subject, key, _type, value| {
+                if let Some(objects) = &objects {
+                    apply_property(objects, subject, key, value);
+                }
+                if let Some(key) = key {
+                    let _result = sender.read().unwrap().send(
+                        ConnectorEvent::MetadataUpdate(
+                            subject,
+                            key.to_owned(),
+                        ),
+                    );
+                }
+                0
+            })
+            .register();
+        Ok(Self {
+            id: global.id,
+            metadata,
+            _listener: listener,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{apply_property, DefaultKind, MetadataStore};
+    use crate::node::Node;
+    use crate::sync::RwLock;
+    use crate::test_utils::with_nodes;
+
+    #[test]
+    fn update_sets_and_removes_properties() {
+        let mut store = MetadataStore::default();
+        store.update(42, Some("myapp.routed"), Some("true"));
+        store.update(43, Some("myapp.routed"), Some("false"));
+        assert_eq!(store.get(42, "myapp.routed"), Some("true"));
+        assert_eq!(store.get(43, "myapp.routed"), Some("false"));
+
+        store.update(42, Some("myapp.routed"), None);
+        assert_eq!(store.get(42, "myapp.routed"), None);
+
+        store.update(43, None, None);
+        assert_eq!(store.get(43, "myapp.routed"), None);
+    }
+
+    #[test]
+    fn default_changes_run_the_callbacks() {
+        let objects = RwLock::new(with_nodes([
+            Node::from_parts(1, "speakers"),
+            Node::from_parts(2, "headphones"),
+        ]));
+        let calls = Arc::new(Mutex::new(vec![]));
+        let recorded = calls.clone();
+        objects.write().unwrap().default_callbacks.push(Arc::new(
+            move |kind, node_id| {
+                recorded.lock().unwrap().push((kind, node_id));
+            },
+        ));
+        let set_default = |value: Option<&str>| {
+            apply_property(
+                &objects,
+                0,
+                Some("default.audio.sink"),
+                value,
+            );
+        };
+
+        set_default(Some(r#"{ "name": "speakers" }"#));
+        set_default(Some(r#"{ "name": "speakers" }"#));
+        // The same node, written differently
+        set_default(Some(r#"{"name":"speakers"}"#));
+        set_default(Some(r#"{"name":"headphones"}"#));
+        set_default(Some(r#"{ "name": "unknown" }"#));
+        set_default(None);
+        set_default(Some(r#"{ "name": "speakers" }"#));
+        set_default(Some("not json"));
+        apply_property(
+            &objects,
+            42,
+            Some("target.object"),
+            Some("1"),
+        );
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                (DefaultKind::Sink, Some(1)),
+                (DefaultKind::Sink, Some(2)),
+                (DefaultKind::Sink, None),
+                (DefaultKind::Sink, None),
+                (DefaultKind::Sink, Some(1)),
+                (DefaultKind::Sink, None),
+            ]
+        );
+    }
+}
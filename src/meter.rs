@@ -0,0 +1,232 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use libspa::pod::{Object, Pod, Property, Value};
+use libspa::sys;
+use libspa::utils::{Direction, Id};
+use pipewire::core::Core;
+use pipewire::properties::properties;
+use pipewire::stream::{Stream, StreamFlags, StreamListener};
+
+use crate::event::PipeWireEvent;
+use crate::node::{Node, NodeKind};
+use crate::props::to_pod_bytes;
+
+/// Highest absolute sample of interleaved little endian 32 bit
+/// float samples, as the monitoring stream receives them, 1.0 being
+/// full scale. A trailing partial sample is ignored.
+pub(crate) fn peak_of_le_bytes(bytes: &[u8]) -> f32 {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]).abs())
+        .fold(0.0, f32::max)
+}
+
+/// Call `callback` with the peak of the chunk of a buffer holding
+/// the samples of a cycle, `size` bytes from `offset`, clamped to
+/// the buffer.
+fn report_peak(
+    bytes: &[u8],
+    offset: usize,
+    size: usize,
+    callback: &LevelCallback,
+) {
+    let end = offset.saturating_add(size).min(bytes.len());
+    let start = offset.min(end);
+    (callback.0)(peak_of_le_bytes(&bytes[start..end]));
+}
+
+/// Called with the peak level of every cycle of a monitored node.
+#[derive(Clone)]
+pub(crate) struct LevelCallback(
+    pub(crate) Arc<dyn Fn(f32) + Send + Sync>,
+);
+
+impl Debug for LevelCallback {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str("LevelCallback")
+    }
+}
+
+impl PartialEq for LevelCallback {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Keeps the levels of a node monitored, see
+/// [`crate::manager::PipeWireManager::monitor_levels`]. Monitoring
+/// stops when the handle is dropped or [`LevelMonitor::stop`] is
+/// called.
+pub struct LevelMonitor {
+    pub(crate) id: u32,
+    pub(crate) node_id: u32,
+    pub(crate) sender: pipewire::channel::Sender<PipeWireEvent>,
+}
+
+impl LevelMonitor {
+    pub fn node_id(&self) -> u32 {
+        self.node_id
+    }
+
+    /// Stop monitoring, the callback is not called anymore once the
+    /// backend handled it.
+    pub fn stop(self) {}
+}
+
+impl Drop for LevelMonitor {
+    fn drop(&mut self) {
+        if self
+            .sender
+            .send(PipeWireEvent::StopMonitorLevelsCommand(self.id))
+            .is_err()
+        {
+            log_warn!(
+                "Failed to stop monitoring the levels of node {}",
+                self.node_id
+            );
+        }
+    }
+}
+
+/// The capture stream of a level monitor. It only lives on the
+/// backend thread.
+pub(crate) struct BoundMeter {
+    _listener: StreamListener<()>,
+    _stream: Stream,
+}
+
+impl BoundMeter {
+    /// Connect a passive capture stream to the node. Sinks are
+    /// captured through their monitor ports.
+    pub(crate) fn connect(
+        core: &Core,
+        node: &Node,
+        callback: LevelCallback,
+    ) -> Result<Self, String> {
+        let capture_sink = if node.kind() == NodeKind::Sink {
+            "true"
+        } else {
+            "false"
+        };
+        let stream = Stream::new(
+            core,
+            "easy-pw-meter",
+            properties! {
+                "media.type" => "Audio",
+                "media.category" => "Capture",
+                "media.role" => "DSP",
+                "target.object" => node.object_serial.as_str(),
+                "stream.capture.sink" => capture_sink,
+                "stream.monitor" => "true",
+                "node.passive" => "true",
+                "node.dont-reconnect" => "true",
+            },
+        )
+        .map_err(|e| format!("Failed to create stream: {e}"))?;
+        let listener = stream
+            .add_local_listener_with_user_data(())
+            .process(move |stream, _| {
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    return;
+                };
+                let Some(data) = buffer.datas_mut().first_mut()
+                else {
+                    return;
+                };
+                let offset = data.chunk().offset() as usize;
+                let size = data.chunk().size() as usize;
+                if let Some(bytes) = data.data() {
+                    report_peak(bytes, offset, size, &callback);
+                }
+            })
+            .register()
+            .map_err(|e| {
+                format!("Failed to listen to stream: {e}")
+            })?;
+        let format = format_bytes()?;
+        let pod = Pod::from_bytes(&format)
+            .ok_or("Failed to build the stream format")?;
+        stream
+            .connect(
+                Direction::Input,
+                None,
+                StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS,
+                &mut [pod],
+            )
+            .map_err(|e| format!("Failed to connect stream: {e}"))?;
+        Ok(Self {
+            _listener: listener,
+            _stream: stream,
+        })
+    }
+}
+
+/// The format the monitoring stream asks for: raw 32 bit floats,
+/// leaving the rate and channels to the node.
+fn format_bytes() -> Result<Vec<u8>, String> {
+    let format = Value::Object(Object {
+        type_: sys::SPA_TYPE_OBJECT_Format,
+        id: sys::SPA_PARAM_EnumFormat,
+        properties: vec![
+            Property::new(
+                sys::SPA_FORMAT_mediaType,
+                Value::Id(Id(sys::SPA_MEDIA_TYPE_audio)),
+            ),
+            Property::new(
+                sys::SPA_FORMAT_mediaSubtype,
+                Value::Id(Id(sys::SPA_MEDIA_SUBTYPE_raw)),
+            ),
+            Property::new(
+                sys::SPA_FORMAT_AUDIO_format,
+                Value::Id(Id(sys::SPA_AUDIO_FORMAT_F32_LE)),
+            ),
+        ],
+    });
+    to_pod_bytes(&format)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{peak_of_le_bytes, report_peak, LevelCallback};
+
+    fn le_bytes(samples: &[f32]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn peak_of_silence_is_zero() {
+        assert_eq!(peak_of_le_bytes(&[]), 0.0);
+        assert_eq!(peak_of_le_bytes(&le_bytes(&[0.0; 64])), 0.0);
+    }
+
+    #[test]
+    fn peak_is_the_highest_absolute_sample() {
+        let mut bytes = le_bytes(&[0.25, -0.75, 0.5]);
+        bytes.extend([0, 0]);
+        assert_eq!(peak_of_le_bytes(&bytes), 0.75);
+    }
+
+    #[test]
+    fn callback_receives_the_peak_of_each_cycle() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let callback = LevelCallback(Arc::new({
+            let received = received.clone();
+            move |peak| received.lock().unwrap().push(peak)
+        }));
+        // A buffer of the stream, each cycle only filling a chunk
+        let buffer = le_bytes(&[0.95, 0.1, -0.2, 0.0, -0.9, 0.3]);
+        for (offset, size) in [(4, 8), (12, 4), (16, 8), (20, 100)] {
+            report_peak(&buffer, offset, size, &callback);
+        }
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![0.2, 0.0, 0.9, 0.3]
+        );
+    }
+}
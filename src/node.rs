@@ -1,22 +1,411 @@
-use std::{rc::Rc, sync::RwLock};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    rc::Rc,
+    sync::{mpsc, Arc},
+};
 
-use crate::port::PortDirection;
+use crate::event::ConnectorEvent;
+use crate::objects::PipeWireObjects;
+use crate::port::{AudioChannel, PortDirection, PortInfo};
+use crate::props::{self, NodeProps};
+use crate::retry::RetryPolicy;
+use crate::sync::RwLock;
 
 use super::{
     port::{Port, PortError},
-    utils::{val, val_opt},
+    utils::{discovery_debug, sanitize, val_opt},
 };
+use libspa::param::ParamType;
+use libspa::pod::{Pod, Value};
 use libspa::utils::dict::DictRef;
+use pipewire::node::{
+    Node as NodeProxy, NodeListener, NodeState as PwNodeState,
+};
 use pipewire::permissions::PermissionFlags;
-use pipewire::registry::GlobalObject;
+use pipewire::registry::{GlobalObject, Registry};
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+/// Called with the new value of a watched node property, see
+/// [`crate::manager::PipeWireManager::subscribe_node_property`].
+pub(crate) type PropertyCallback = dyn Fn(&str) + Send + Sync;
+
+#[derive(Error, Debug, PartialEq)]
 pub enum NodeError {
-    #[error("")]
+    #[error(transparent)]
     PortError(#[from] PortError),
     #[error("Node {0} does not have a port with direction {1:?}")]
     IncorrectTypeOfChannelDirection(String, PortDirection),
+    #[error(
+        "Node {0} has no port compatible with the ports of node {1}"
+    )]
+    IncompatibleFormats(String, String),
+    #[error(
+        "The channels of node {0} do not match those of node {1}"
+    )]
+    ChannelMismatch(String, String),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CreateNodeError {
+    #[error("A node named {0} already exists")]
+    NameTaken(String),
+    #[error("Node {0} has {1} channels but {2} were laid out")]
+    LayoutMismatch(String, u32, usize),
+    #[error("Failed to create node {0}")]
+    CreationFailed(String),
+    #[error("Node {0} was created but never announced")]
+    NotAnnounced(String),
+    #[error("The {0} property is required to create a node")]
+    MissingProperty(&'static str),
+    #[error(
+        "Blocking calls cannot be made from event callbacks or the backend thread"
+    )]
+    ReentrantCall,
+}
+
+/// The properties every node created through the manager needs: the
+/// factory behind the node, its class, and the name it is found by
+/// once announced.
+pub const REQUIRED_NODE_PROPERTIES: [&str; 3] =
+    ["factory.name", "media.class", "node.name"];
+
+/// Check that the properties of a node to create have every
+/// [`REQUIRED_NODE_PROPERTIES`], returning its name.
+pub(crate) fn required_node_name(
+    properties: &HashMap<String, String>,
+) -> Result<&str, CreateNodeError> {
+    if let Some(key) = REQUIRED_NODE_PROPERTIES
+        .into_iter()
+        .find(|key| !properties.contains_key(*key))
+    {
+        return Err(CreateNodeError::MissingProperty(key));
+    }
+    Ok(&properties["node.name"])
+}
+
+/// A virtual node to create with
+/// [`crate::manager::PipeWireManager::create_virtual_node`], e.g. a
+/// sink to route applications into or a source to record from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VirtualNodeSpec {
+    /// The `node.name`, unique among the nodes of the graph.
+    pub name: String,
+    /// The `node.description` shown by mixers.
+    pub description: Option<String>,
+    /// The `media.class`, e.g. `Audio/Sink` or `Audio/Source/Virtual`.
+    pub media_class: String,
+    pub channels: u32,
+    /// The channel of every port, in order. When empty, PipeWire
+    /// picks the usual layout for the amount of channels.
+    pub channel_layout: Vec<AudioChannel>,
+}
+
+impl VirtualNodeSpec {
+    /// Check the spec before asking PipeWire for the node.
+    pub(crate) fn validate(&self) -> Result<(), CreateNodeError> {
+        if !self.channel_layout.is_empty()
+            && self.channel_layout.len() != self.channels as usize
+        {
+            return Err(CreateNodeError::LayoutMismatch(
+                self.name.clone(),
+                self.channels,
+                self.channel_layout.len(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The properties of the null audio sink node backing the
+    /// virtual node. The node is not kept once the manager is gone.
+    pub(crate) fn properties(&self) -> HashMap<String, String> {
+        let mut properties: HashMap<String, String> = [
+            ("factory.name", "support.null-audio-sink".to_owned()),
+            ("node.name", self.name.clone()),
+            ("media.class", self.media_class.clone()),
+            ("audio.channels", self.channels.to_string()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value))
+        .collect();
+        if let Some(description) = &self.description {
+            properties.insert(
+                "node.description".to_owned(),
+                description.clone(),
+            );
+        }
+        if !self.channel_layout.is_empty() {
+            let position: Vec<&str> = self
+                .channel_layout
+                .iter()
+                .map(AudioChannel::as_str)
+                .collect();
+            properties.insert(
+                "audio.position".to_owned(),
+                position.join(","),
+            );
+        }
+        properties
+    }
+}
+
+/// How the ports of two nodes are paired up when linking them.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LinkStrategy {
+    /// Link ports of the same audio channel when both nodes have the
+    /// same amount of ports, otherwise pair them as the
+    /// [`ChannelEqualization`] says.
+    #[default]
+    ChannelMatch,
+    /// Same as [`LinkStrategy::ChannelMatch`], except that only the
+    /// front channels are linked when the target node is stereo.
+    FrontOnly,
+    /// Link the n-th output port into the n-th input port, in the
+    /// order the ports were discovered, ignoring their channels.
+    OneToOne,
+    /// Link every output port into every input port.
+    AllToAll,
+    /// Link only the first output port into the first input port.
+    FirstToFirst,
+}
+
+/// How the ports of two nodes with different channel layouts are
+/// linked, when the [`LinkStrategy`] paired none of them, e.g. a mono
+/// microphone linked into stereo speakers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ChannelEqualization {
+    /// Link the first output port into every input port. A mono
+    /// output is duplicated, but only the first channel of a stereo
+    /// output reaches a mono input.
+    FirstToAll,
+    /// Link a mono output into every input port, and every output
+    /// port into a mono input, where PipeWire mixes them. Other
+    /// mismatches are refused.
+    #[default]
+    DuplicateAndMix,
+    /// Link a mono output into every input port, refusing to mix
+    /// several outputs into a mono input and other mismatches.
+    DuplicateOnly,
+    /// Refuse to link nodes whose channels do not match.
+    Refuse,
+}
+
+/// Output and input ports to link, in order.
+type PortPairs<'a> = Vec<(&'a Port, &'a Port)>;
+
+/// Which ports linking two nodes connected, see
+/// [`Node::link_device_detailed`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkReport {
+    /// Output and input ports, in the order they were linked.
+    pub pairs_linked: Vec<(PortInfo, PortInfo)>,
+    pub strategy_used: LinkStrategy,
+    /// How the channels were paired when the strategy paired none of
+    /// them, `None` if it did.
+    pub equalization_used: Option<ChannelEqualization>,
+}
+
+impl LinkReport {
+    fn new(
+        (output_node, input_node): (u32, u32),
+        pairs: &[(&Port, &Port)],
+        strategy: LinkStrategy,
+        equalized: bool,
+        equalization: ChannelEqualization,
+    ) -> Self {
+        Self {
+            pairs_linked: pairs
+                .iter()
+                .map(|(port, other_port)| {
+                    (
+                        PortInfo::new(output_node, port),
+                        PortInfo::new(input_node, other_port),
+                    )
+                })
+                .collect(),
+            strategy_used: strategy,
+            equalization_used: equalized.then_some(equalization),
+        }
+    }
+}
+
+/// Whether a node is processing audio, as reported by its info.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum NodeState {
+    Error(String),
+    Creating,
+    /// Not processing, with its device closed.
+    Suspended,
+    /// Ready but not processing.
+    Idle,
+    /// Processing audio.
+    Running,
+}
+
+impl From<PwNodeState<'_>> for NodeState {
+    fn from(state: PwNodeState<'_>) -> Self {
+        match state {
+            PwNodeState::Error(error) => {
+                NodeState::Error(error.to_owned())
+            }
+            PwNodeState::Creating => NodeState::Creating,
+            PwNodeState::Suspended => NodeState::Suspended,
+            PwNodeState::Idle => NodeState::Idle,
+            PwNodeState::Running => NodeState::Running,
+        }
+    }
+}
+
+/// What a node does in the graph, derived from its `media.class`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum NodeKind {
+    /// Audio/Sink, e.g. speakers or headphones
+    Sink,
+    /// Audio/Source, e.g. microphones
+    Source,
+    /// Audio/Duplex
+    Duplex,
+    /// Stream/Output/Audio, an application playing audio
+    StreamOutput,
+    /// Stream/Input/Audio, an application recording audio
+    StreamInput,
+    /// Midi/Bridge and other MIDI nodes
+    Midi,
+    /// Video/Source and Stream/Output/Video, e.g. a camera or a
+    /// screen being shared
+    VideoSource,
+    /// Video/Sink and Stream/Input/Video, e.g. a screen recorder
+    VideoSink,
+    /// Anything else, including nodes without a media class
+    Other,
+}
+
+impl NodeKind {
+    pub fn from_media_class(media_class: &str) -> Self {
+        match media_class {
+            "Audio/Sink" | "Audio/Sink/Virtual" => NodeKind::Sink,
+            "Audio/Source" | "Audio/Source/Virtual" => {
+                NodeKind::Source
+            }
+            "Audio/Duplex" => NodeKind::Duplex,
+            "Stream/Output/Audio" => NodeKind::StreamOutput,
+            "Stream/Input/Audio" => NodeKind::StreamInput,
+            class if class.starts_with("Midi/") => NodeKind::Midi,
+            "Video/Source" | "Stream/Output/Video" => {
+                NodeKind::VideoSource
+            }
+            "Video/Sink" | "Stream/Input/Video" => {
+                NodeKind::VideoSink
+            }
+            _ => NodeKind::Other,
+        }
+    }
+}
+
+/// What a stream is used for, from its `media.role`, which session
+/// managers route by.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MediaRole {
+    Movie,
+    Music,
+    Camera,
+    Screen,
+    Communication,
+    Game,
+    Notification,
+    DSP,
+    Production,
+    Accessibility,
+    Test,
+    /// A role PipeWire does not define.
+    Other(String),
+}
+
+impl MediaRole {
+    pub fn from_role(role: &str) -> Self {
+        match role {
+            "Movie" => MediaRole::Movie,
+            "Music" => MediaRole::Music,
+            "Camera" => MediaRole::Camera,
+            "Screen" => MediaRole::Screen,
+            "Communication" => MediaRole::Communication,
+            "Game" => MediaRole::Game,
+            "Notification" => MediaRole::Notification,
+            "DSP" => MediaRole::DSP,
+            "Production" => MediaRole::Production,
+            "Accessibility" => MediaRole::Accessibility,
+            "Test" => MediaRole::Test,
+            other => MediaRole::Other(other.to_owned()),
+        }
+    }
+
+    /// The `media.role` value of the role.
+    pub fn as_str(&self) -> &str {
+        match self {
+            MediaRole::Movie => "Movie",
+            MediaRole::Music => "Music",
+            MediaRole::Camera => "Camera",
+            MediaRole::Screen => "Screen",
+            MediaRole::Communication => "Communication",
+            MediaRole::Game => "Game",
+            MediaRole::Notification => "Notification",
+            MediaRole::DSP => "DSP",
+            MediaRole::Production => "Production",
+            MediaRole::Accessibility => "Accessibility",
+            MediaRole::Test => "Test",
+            MediaRole::Other(role) => role,
+        }
+    }
+}
+
+/// A way to find a node, see
+/// [`crate::manager::PipeWireManager::find_node`]. Ids are the
+/// quickest to look up but are reused once the node is gone, while a
+/// [`NodeSelector::Fingerprint`] still finds the same device after a
+/// restart of the server, which makes it the one to persist.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum NodeSelector {
+    /// The global id of the node.
+    Id(u32),
+    /// The `object.serial` of the node, never reused while the
+    /// server runs.
+    Serial(u64),
+    /// The `node.name` of the node.
+    Name(String),
+    /// The `object.path` of the node.
+    ObjectPath(String),
+    /// The [`Node::fingerprint`] of the node.
+    Fingerprint(String),
+}
+
+impl NodeSelector {
+    pub fn matches(&self, node: &Node) -> bool {
+        match self {
+            NodeSelector::Id(id) => node.id == *id,
+            NodeSelector::Serial(serial) => {
+                node.object_serial == serial.to_string()
+            }
+            NodeSelector::Name(name) => node.name == *name,
+            NodeSelector::ObjectPath(path) => {
+                node.object_path.as_ref() == Some(path)
+            }
+            NodeSelector::Fingerprint(fingerprint) => {
+                node.fingerprint() == *fingerprint
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -40,20 +429,114 @@ pub struct Node {
     pub media_role: Option<String>,
     pub client_api: Option<String>,
     pub application_name: Option<String>,
-    pub ports: Vec<Port>,
+    /// Every property of the node, as announced and then updated by
+    /// its info.
+    pub properties: HashMap<String, String>,
+    /// Read with [`Node::ports`], added and removed with
+    /// [`Node::add_port`] and [`Node::remove_port`], which keep the
+    /// port counts up to date.
+    ports: Vec<Port>,
+    input_count: usize,
+    output_count: usize,
+    /// How many input and output ports the node has, as announced by
+    /// its info, while they may still be discovered one by one.
+    expected_ports: Option<(usize, usize)>,
+    ports_complete: bool,
+    /// Whether the node is muted, once its `Props` param is known.
+    pub mute: Option<bool>,
+    /// Linear volume of the node, averaged over its channels, once
+    /// its `Props` param is known. 1.0 is 100%.
+    pub volume: Option<f32>,
+    /// The node driving the graph this node is part of.
+    pub driver_id: Option<u32>,
+    /// Samples processed per cycle, as advertised by the node.
+    pub quantum: Option<u32>,
+    /// Sample rate, as advertised by the node.
+    pub rate: Option<u32>,
+    /// Whether the device behind the node is plugged in, when the
+    /// node says so, see [`Node::is_available`].
+    pub available: Option<bool>,
+    /// Whether the node is processing audio, once its info is known.
+    pub state: Option<NodeState>,
+}
+
+/// Which node drives the graph and at what pace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriverInfo {
+    pub node_id: u32,
+    /// Samples processed per cycle.
+    pub quantum: Option<u32>,
+    pub rate: Option<u32>,
+}
+
+/// An owned summary of a [`Node`], detached from the objects lock.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct NodeInfo {
+    pub id: u32,
+    pub name: String,
+    pub description: Option<String>,
+    pub nick: Option<String>,
+    /// See [`Node::short_name`].
+    pub short_name: String,
+    pub object_serial: String,
+    pub media_class: Option<String>,
+    pub kind: NodeKind,
+    pub application_name: Option<String>,
+    pub input_ports: usize,
+    pub output_ports: usize,
+    pub mute: Option<bool>,
+    pub volume: Option<f32>,
+    /// See [`Node::is_available`].
+    pub available: bool,
+    pub state: Option<NodeState>,
+}
+
+impl From<&Node> for NodeInfo {
+    fn from(node: &Node) -> Self {
+        Self {
+            id: node.id,
+            name: node.name.clone(),
+            description: node.description.clone(),
+            nick: node.nick.clone(),
+            short_name: node.short_name(),
+            object_serial: node.object_serial.clone(),
+            media_class: node.media_class.clone(),
+            kind: node.kind(),
+            application_name: node.application_name.clone(),
+            input_ports: node.input_count(),
+            output_ports: node.output_count(),
+            mute: node.mute,
+            volume: node.volume,
+            available: node.is_available(),
+            state: node.state.clone(),
+        }
+    }
+}
+
+impl NodeInfo {
+    /// See [`Node::is_running`].
+    pub fn is_running(&self) -> bool {
+        self.state == Some(NodeState::Running)
+    }
 }
 
 impl Node {
-    pub fn new(global: &GlobalObject<&DictRef>) -> Self {
-        let props = global.props.unwrap();
-        let node = Node {
+    /// The node of a registry global, `None` if it was announced
+    /// without properties or without an `object.serial`.
+    pub fn new(global: &GlobalObject<&DictRef>) -> Option<Self> {
+        let props = global.props?;
+        let mut node = Node {
             id: global.id,
-            name: val(props, "node.name"),
+            name: Self::name_from_props(global.id, props),
             description: val_opt(props, "node.description"),
             nick: val_opt(props, "node.nick"),
             permissions: global.permissions,
             version: global.version,
-            object_serial: val(props, "object.serial"),
+            object_serial: val_opt(props, "object.serial")?,
             factory_id: val_opt(props, "factory.id"),
             object_path: val_opt(props, "object.path"),
             client_id: val_opt(props, "client.id"),
@@ -64,13 +547,332 @@ impl Node {
             media_role: val_opt(props, "media.role"),
             client_api: val_opt(props, "client.api"),
             application_name: val_opt(props, "application.name"),
+            properties: HashMap::new(),
             ports: vec![],
+            input_count: 0,
+            output_count: 0,
+            expected_ports: None,
+            ports_complete: false,
+            mute: None,
+            volume: None,
+            driver_id: None,
+            quantum: None,
+            rate: None,
+            available: None,
+            state: None,
         };
-        log::debug!(
+        node.apply_info_props(props);
+        discovery_debug!(
             "Creating new Node from global object: {:?}",
             node.name
         );
-        node
+        Some(node)
+    }
+
+    /// The `node.name` of the node, or else its `node.description`,
+    /// its `object.path` and at last `node-{id}`, as some nodes are
+    /// announced without a name.
+    fn name_from_props(id: u32, props: &DictRef) -> String {
+        ["node.name", "node.description", "object.path"]
+            .into_iter()
+            .filter_map(|key| props.get(key))
+            .find(|name| !name.is_empty())
+            .map_or_else(|| format!("node-{id}"), str::to_owned)
+    }
+
+    /// Update the clock related properties and the availability of
+    /// the node from the properties of its info, and keep them in
+    /// [`Node::properties`].
+    /// The quantum and the rate come from `clock.quantum` and
+    /// `clock.rate`, or else from the `node.latency` (e.g.
+    /// "256/48000") and the `node.rate` (e.g. "1/48000") of the node.
+    /// Returns the properties whose value changed.
+    pub(crate) fn apply_info_props(
+        &mut self,
+        props: &DictRef,
+    ) -> Vec<String> {
+        let mut changed = vec![];
+        for (key, value) in props.iter() {
+            if self.properties.get(key).map(String::as_str)
+                != Some(value)
+            {
+                self.properties
+                    .insert(key.to_owned(), value.to_owned());
+                changed.push(key.to_owned());
+            }
+        }
+        let number =
+            |key| props.get(key).and_then(|v| v.parse().ok());
+        let fraction = |key| {
+            let (numerator, denominator) =
+                props.get(key)?.split_once('/')?;
+            Some((numerator.parse().ok()?, denominator.parse().ok()?))
+        };
+        if let Some(driver_id) = number("node.driver-id") {
+            self.driver_id = Some(driver_id);
+        }
+        let latency: Option<(u32, u32)> = fraction("node.latency");
+        let rate: Option<(u32, u32)> = fraction("node.rate");
+        if let Some(quantum) = number("clock.quantum")
+            .or(latency.map(|(quantum, _)| quantum))
+        {
+            self.quantum = Some(quantum);
+        }
+        if let Some(rate) = number("clock.rate")
+            .or(rate.map(|(_, rate)| rate))
+            .or(latency.map(|(_, rate)| rate))
+        {
+            self.rate = Some(rate);
+        }
+        if let Some(description) = props.get("node.description") {
+            self.description = Some(description.to_owned());
+        }
+        match props.get("node.available") {
+            Some("true") => self.available = Some(true),
+            Some("false") => self.available = Some(false),
+            _ => {}
+        }
+        if let (Some(inputs), Some(outputs)) = (
+            number("node.n-input-ports"),
+            number("node.n-output-ports"),
+        ) {
+            self.set_expected_ports(
+                inputs as usize,
+                outputs as usize,
+            );
+        }
+        if changed.iter().any(|key| key == "audio.position") {
+            // Ports may be discovered before the info of their node
+            let channels = self.position_channels();
+            for port in &mut self.ports {
+                Self::assign_position_channel(port, &channels);
+            }
+        }
+        changed
+    }
+
+    /// Set how many input and output ports the node has, as its info
+    /// reports them.
+    pub(crate) fn set_expected_ports(
+        &mut self,
+        inputs: usize,
+        outputs: usize,
+    ) {
+        self.expected_ports = Some((inputs, outputs));
+    }
+
+    /// How many ports the node has once they are all discovered, when
+    /// its info said so.
+    pub fn expected_port_count(&self) -> Option<usize> {
+        self.expected_ports
+            .map(|(inputs, outputs)| inputs + outputs)
+    }
+
+    /// Whether every expected port of the node was discovered, see
+    /// [`ConnectorEvent::NodePortsComplete`].
+    pub fn ports_complete(&self) -> bool {
+        self.ports_complete
+    }
+
+    /// Mark the ports of the node complete once both its inputs and
+    /// its outputs reach the expected count, returning true only the
+    /// first time.
+    pub(crate) fn check_ports_complete(&mut self) -> bool {
+        let complete = !self.ports_complete
+            && self.expected_ports.is_some_and(
+                |(inputs, outputs)| {
+                    self.input_ports().count() >= inputs
+                        && self.output_ports().count() >= outputs
+                },
+            );
+        if complete {
+            self.ports_complete = true;
+        }
+        complete
+    }
+
+    /// Whether the node can currently play or record.
+    ///
+    /// Sinks and sources of devices that come and go (a Bluetooth
+    /// headset out of range, an HDMI screen turned off) can outlive
+    /// the device. This follows the `node.available` property the
+    /// node advertises, and considers nodes that do not advertise it
+    /// available: availability the session manager only tracks on
+    /// the routes of the device is not reflected.
+    pub fn is_available(&self) -> bool {
+        self.available.unwrap_or(true)
+    }
+
+    /// Whether the node is processing audio. False until its info
+    /// is known.
+    pub fn is_running(&self) -> bool {
+        self.state == Some(NodeState::Running)
+    }
+
+    /// Whether the device of the node was closed for being idle,
+    /// e.g. to grey it out.
+    pub fn is_suspended(&self) -> bool {
+        self.state == Some(NodeState::Suspended)
+    }
+
+    /// Whether the node reported an error, in which case it should
+    /// not be linked.
+    pub fn has_error(&self) -> bool {
+        matches!(self.state, Some(NodeState::Error(_)))
+    }
+
+    /// Whether the node carries video, from its media class or its
+    /// ports.
+    pub fn is_video(&self) -> bool {
+        matches!(
+            self.kind(),
+            NodeKind::VideoSource | NodeKind::VideoSink
+        ) || self.ports.iter().any(|port| port.format.is_video())
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.media_class
+            .as_deref()
+            .map_or(NodeKind::Other, NodeKind::from_media_class)
+    }
+
+    /// The properties identifying the node across restarts of the
+    /// server, joined: its `media.class`, `node.name` and
+    /// `object.path`, e.g.
+    /// `Audio/Sink|alsa_output.usb-headset|alsa:pcm:1:front:1:playback`.
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "{}|{}|{}",
+            self.media_class.as_deref().unwrap_or_default(),
+            self.name,
+            self.object_path.as_deref().unwrap_or_default()
+        )
+    }
+
+    /// The `media.role` of the node, usually only set on streams.
+    pub fn role(&self) -> Option<MediaRole> {
+        self.media_role.as_deref().map(MediaRole::from_role)
+    }
+
+    /// The most human friendly label of the node: its `node.nick`,
+    /// then its `node.description`, and at last a cleaned up
+    /// `node.name` (e.g. "Analog Stereo" for
+    /// "alsa_output.pci-0000_00_1f.3.analog-stereo").
+    ///
+    /// Control characters are removed, see [`Node::description`].
+    pub fn short_name(&self) -> String {
+        [&self.nick, &self.description]
+            .into_iter()
+            .flatten()
+            .map(|label| sanitize(label))
+            .find(|label| !label.is_empty())
+            .unwrap_or_else(|| {
+                sanitize(&Self::clean_name(&self.name))
+            })
+    }
+
+    /// The `node.description` of the node, safe to display: control
+    /// characters are replaced and whitespace is collapsed. The
+    /// [`Node::description`] field keeps the raw value.
+    pub fn description(&self) -> Option<String> {
+        self.description
+            .as_deref()
+            .map(sanitize)
+            .filter(|description| !description.is_empty())
+    }
+
+    fn clean_name(name: &str) -> String {
+        const PREFIXES: [&str; 6] = [
+            "alsa_output.",
+            "alsa_input.",
+            "bluez_output.",
+            "bluez_input.",
+            "v4l2_input.",
+            "libcamera_input.",
+        ];
+        let name = PREFIXES
+            .iter()
+            .find_map(|prefix| name.strip_prefix(prefix))
+            .unwrap_or(name);
+        let label = name
+            .rsplit('.')
+            .find(|part| {
+                !part.is_empty()
+                    && !part.chars().all(|c| c.is_ascii_digit())
+            })
+            .unwrap_or(name);
+
+        let words: Vec<String> = label
+            .split(['-', '_', ' '])
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                let mut chars = word.chars();
+                chars.next().map_or_else(String::new, |first| {
+                    first.to_uppercase().chain(chars).collect()
+                })
+            })
+            .collect();
+        if words.is_empty() {
+            return name.to_owned();
+        }
+        words.join(" ")
+    }
+
+    /// Update the node with the values of its `Props` param,
+    /// returning an event for every value that changed.
+    pub(crate) fn apply_props(
+        &mut self,
+        props: &NodeProps,
+    ) -> Vec<ConnectorEvent> {
+        let mut events = vec![];
+        if let Some(muted) = props.mute {
+            if self.mute != Some(muted) {
+                self.mute = Some(muted);
+                events.push(ConnectorEvent::MuteChanged {
+                    node_id: self.id,
+                    muted,
+                });
+            }
+        }
+        let volume = props
+            .channel_volumes
+            .as_ref()
+            .filter(|volumes| !volumes.is_empty())
+            .map(|volumes| {
+                volumes.iter().sum::<f32>() / volumes.len() as f32
+            });
+        if let Some(volume) = volume {
+            if self.volume != Some(volume) {
+                self.volume = Some(volume);
+                events.push(ConnectorEvent::VolumeChanged {
+                    node_id: self.id,
+                    volume,
+                });
+            }
+        }
+        events
+    }
+
+    /// Update the state of the node from its info, returning an
+    /// event if it changed.
+    pub(crate) fn apply_state(
+        &mut self,
+        state: NodeState,
+    ) -> Option<ConnectorEvent> {
+        if self.state.as_ref() == Some(&state) {
+            return None;
+        }
+        log_debug!("Node {} is now {state:?}", self.id);
+        self.state = Some(state.clone());
+        Some(ConnectorEvent::NodeStateChanged {
+            node_id: self.id,
+            state,
+        })
+    }
+
+    /// The ports of the node, in the order they were discovered.
+    pub fn ports(&self) -> &[Port] {
+        &self.ports
     }
 
     pub fn get_port_names(&self) -> Vec<String> {
@@ -82,10 +884,73 @@ impl Node {
         self.ports.iter().find(|port| port.id == port_id)
     }
 
-    pub fn add_port(&mut self, port: Port) {
+    pub fn get_port_by_name(&self, name: &str) -> Option<&Port> {
+        self.ports.iter().find(|port| port.name == name)
+    }
+
+    pub fn add_port(&mut self, mut port: Port) {
+        *self.port_count_mut(&port.direction) += 1;
+        let channels = self.position_channels();
+        Self::assign_position_channel(&mut port, &channels);
         self.ports.push(port);
     }
 
+    /// The channels of the `audio.position` of the node, in order.
+    fn position_channels(&self) -> Vec<AudioChannel> {
+        self.properties
+            .get("audio.position")
+            .map(|positions| {
+                AudioChannel::from_position_map(positions)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Give a port without an `audio.channel` the channel at its
+    /// index in the position map of its node.
+    fn assign_position_channel(
+        port: &mut Port,
+        channels: &[AudioChannel],
+    ) {
+        if port.audio_channel != AudioChannel::Unknown {
+            return;
+        }
+        if let Some(channel) =
+            port.index.and_then(|index| channels.get(index as usize))
+        {
+            port.audio_channel = channel.clone();
+        }
+    }
+
+    pub fn remove_port(&mut self, port_id: u32) -> Option<Port> {
+        let position =
+            self.ports.iter().position(|port| port.id == port_id)?;
+        let port = self.ports.remove(position);
+        *self.port_count_mut(&port.direction) -= 1;
+        Some(port)
+    }
+
+    fn port_count_mut(
+        &mut self,
+        direction: &PortDirection,
+    ) -> &mut usize {
+        match direction {
+            PortDirection::In => &mut self.input_count,
+            PortDirection::Out => &mut self.output_count,
+        }
+    }
+
+    /// How many input ports the node has, without going through
+    /// them, e.g. for UIs polling it every frame.
+    pub fn input_count(&self) -> usize {
+        self.input_count
+    }
+
+    /// How many output ports the node has, see
+    /// [`Node::input_count`].
+    pub fn output_count(&self) -> usize {
+        self.output_count
+    }
+
     pub fn has_port(&self, port: &Port) -> bool {
         self.has_port_of_id(port.id)
     }
@@ -94,24 +959,19 @@ impl Node {
         self.ports.iter().any(|p| p.id == port_id)
     }
 
-    pub fn link_device(
-        &mut self,
-        core: Rc<RwLock<pipewire::core::Core>>,
-        input_device: &mut Self,
+    /// Verify that this node could be linked into `input_device`,
+    /// without creating anything.
+    pub fn check_link_target(
+        &self,
+        input_device: &Self,
     ) -> Result<(), NodeError> {
-        log::debug!(
-            "Linking device \"{}\" to \"{}\"",
-            self.name,
-            input_device.name
-        );
-
         // First we verify if self contains output ports
         if !self
             .ports
             .iter()
             .any(|port| port.direction == PortDirection::Out)
         {
-            log::error!(
+            log_debug!(
                 "Node \"{}\" does not have any output ports",
                 self.name
             );
@@ -127,61 +987,1271 @@ impl Node {
             .iter()
             .any(|port| port.direction == PortDirection::In)
         {
-            log::error!("Node \"{}\" does not have any input ports | Available Ports: {:#?}", input_device.name, input_device.ports);
+            log_debug!("Node \"{}\" does not have any input ports | Available Ports: {:#?}", input_device.name, input_device.ports);
             return Err(NodeError::IncorrectTypeOfChannelDirection(
                 input_device.name.clone(),
                 PortDirection::In,
             ));
         }
 
-        let mut were_matching_ports_found = false;
+        // At last, at least one pair of ports must carry the same format
+        let has_compatible_ports = self
+            .ports
+            .iter()
+            .filter(|port| port.direction == PortDirection::Out)
+            .any(|port| {
+                input_device.ports.iter().any(|other| {
+                    other.direction == PortDirection::In
+                        && port.is_format_compatible(other)
+                })
+            });
+        if !has_compatible_ports {
+            log_debug!(
+                "Node \"{}\" and node \"{}\" do not have ports of compatible formats",
+                self.name,
+                input_device.name
+            );
+            return Err(NodeError::IncompatibleFormats(
+                self.name.clone(),
+                input_device.name.clone(),
+            ));
+        }
+        Ok(())
+    }
 
-        // First we check if the two nodes have the same ammount
-        // of channels and the same audio channels
-        if self.ports.len() == input_device.ports.len() {
-            for port in self.ports.iter() {
-                if port.direction == PortDirection::In {
-                    continue;
-                }
-                let matching_port = input_device
-                    .ports
-                    .iter()
-                    .find(|p| p.audio_channel == port.audio_channel);
-                if matching_port.is_none() {
-                    continue;
+    pub fn output_ports(&self) -> impl Iterator<Item = &Port> {
+        self.ports
+            .iter()
+            .filter(|port| port.direction == PortDirection::Out)
+    }
+
+    pub fn input_ports(&self) -> impl Iterator<Item = &Port> {
+        self.ports
+            .iter()
+            .filter(|port| port.direction == PortDirection::In)
+    }
+
+    /// The ports of the hardware behind the node, see
+    /// [`Port::physical`].
+    pub fn physical_ports(&self) -> impl Iterator<Item = &Port> {
+        self.ports.iter().filter(|port| port.physical)
+    }
+
+    /// The ports that are not [`Node::physical_ports`], e.g. the
+    /// ports of application streams and virtual devices.
+    pub fn virtual_ports(&self) -> impl Iterator<Item = &Port> {
+        self.ports.iter().filter(|port| !port.physical)
+    }
+
+    /// The port of the node carrying `channel` in `direction`, e.g.
+    /// its FR input port.
+    /// Ports of an unknown channel cannot be told apart, so asking
+    /// for [`AudioChannel::Unknown`] always returns `None`.
+    pub fn port(
+        &self,
+        direction: PortDirection,
+        channel: &AudioChannel,
+    ) -> Option<&Port> {
+        if *channel == AudioChannel::Unknown {
+            return None;
+        }
+        self.ports.iter().find(|port| {
+            port.direction == direction
+                && port.audio_channel == *channel
+        })
+    }
+
+    /// Whether the input ports of this node are exactly FL and FR.
+    pub fn is_stereo_input(&self) -> bool {
+        let channels: Vec<&AudioChannel> = self
+            .input_ports()
+            .map(|port| &port.audio_channel)
+            .collect();
+        channels.len() == 2
+            && channels.contains(&&AudioChannel::FL)
+            && channels.contains(&&AudioChannel::FR)
+    }
+
+    pub fn link_device(
+        &mut self,
+        core: Rc<RwLock<pipewire::core::Core>>,
+        input_device: &mut Self,
+    ) -> Result<(), NodeError> {
+        self.link_device_with_strategy(
+            core,
+            input_device,
+            LinkStrategy::default(),
+            &RetryPolicy::default(),
+        )
+    }
+
+    /// Link the ports paired by `strategy`, creating each link again
+    /// as described by `retry_policy` on transient failures.
+    pub fn link_device_with_strategy(
+        &mut self,
+        core: Rc<RwLock<pipewire::core::Core>>,
+        input_device: &mut Self,
+        strategy: LinkStrategy,
+        retry_policy: &RetryPolicy,
+    ) -> Result<(), NodeError> {
+        self.link_device_with_equalization(
+            core,
+            input_device,
+            strategy,
+            ChannelEqualization::default(),
+            retry_policy,
+        )
+    }
+
+    /// Same as [`Self::link_device_with_strategy`], linking
+    /// mismatched channel layouts as described by `equalization`.
+    pub fn link_device_with_equalization(
+        &mut self,
+        core: Rc<RwLock<pipewire::core::Core>>,
+        input_device: &mut Self,
+        strategy: LinkStrategy,
+        equalization: ChannelEqualization,
+        retry_policy: &RetryPolicy,
+    ) -> Result<(), NodeError> {
+        self.link_device_detailed(
+            core,
+            input_device,
+            strategy,
+            equalization,
+            retry_policy,
+        )
+        .map(|_| ())
+    }
+
+    /// Same as [`Self::link_device_with_equalization`], reporting
+    /// which ports were linked and why, e.g. to find out why FL got
+    /// linked into FR.
+    pub fn link_device_detailed(
+        &mut self,
+        core: Rc<RwLock<pipewire::core::Core>>,
+        input_device: &mut Self,
+        strategy: LinkStrategy,
+        equalization: ChannelEqualization,
+        retry_policy: &RetryPolicy,
+    ) -> Result<LinkReport, NodeError> {
+        self.link_device_with(
+            input_device,
+            strategy,
+            equalization,
+            |port, other_port| {
+                port.link_port_with_retry(
+                    core.clone(),
+                    other_port,
+                    retry_policy,
+                )
+            },
+        )
+    }
+
+    /// Same as [`Self::link_device_detailed`], creating each planned
+    /// link through `link_ports`.
+    pub(crate) fn link_device_with(
+        &self,
+        input_device: &Self,
+        strategy: LinkStrategy,
+        equalization: ChannelEqualization,
+        mut link_ports: impl FnMut(&Port, &Port) -> Result<(), PortError>,
+    ) -> Result<LinkReport, NodeError> {
+        log_debug!(
+            "Linking device \"{}\" to \"{}\" ({strategy:?}, {equalization:?})",
+            self.name,
+            input_device.name
+        );
+
+        let (pairs, equalized) =
+            self.plan(input_device, strategy, equalization)?;
+        for (port, other_port) in &pairs {
+            link_ports(port, other_port)?;
+        }
+        Ok(LinkReport::new(
+            (self.id, input_device.id),
+            &pairs,
+            strategy,
+            equalized,
+            equalization,
+        ))
+    }
+
+    /// The report [`Self::link_device_detailed`] would give, without
+    /// linking anything.
+    pub fn plan_report(
+        &self,
+        input_device: &Self,
+        strategy: LinkStrategy,
+        equalization: ChannelEqualization,
+    ) -> Result<LinkReport, NodeError> {
+        let (pairs, equalized) =
+            self.plan(input_device, strategy, equalization)?;
+        Ok(LinkReport::new(
+            (self.id, input_device.id),
+            &pairs,
+            strategy,
+            equalized,
+            equalization,
+        ))
+    }
+
+    /// Decide which output port of this node gets linked into which
+    /// input port of `input_device`, without linking anything.
+    pub fn plan_links<'a>(
+        &'a self,
+        input_device: &'a Self,
+        strategy: LinkStrategy,
+    ) -> Result<Vec<(&'a Port, &'a Port)>, NodeError> {
+        self.plan_links_with_equalization(
+            input_device,
+            strategy,
+            ChannelEqualization::default(),
+        )
+    }
+
+    /// Same as [`Self::plan_links`], pairing the ports of mismatched
+    /// channel layouts as described by `equalization`.
+    pub fn plan_links_with_equalization<'a>(
+        &'a self,
+        input_device: &'a Self,
+        strategy: LinkStrategy,
+        equalization: ChannelEqualization,
+    ) -> Result<Vec<(&'a Port, &'a Port)>, NodeError> {
+        self.plan(input_device, strategy, equalization)
+            .map(|(pairs, _)| pairs)
+    }
+
+    /// The planned pairs of ports, along with whether the channels
+    /// had to be equalized.
+    fn plan<'a>(
+        &'a self,
+        input_device: &'a Self,
+        strategy: LinkStrategy,
+        equalization: ChannelEqualization,
+    ) -> Result<(PortPairs<'a>, bool), NodeError> {
+        self.check_link_target(input_device)?;
+
+        if self.is_video() || input_device.is_video() {
+            // Video ports carry whole frames, there are no channels
+            // to match
+            let pairs = self
+                .output_ports()
+                .zip(input_device.input_ports())
+                .collect();
+            return Ok((pairs, false));
+        }
+
+        let mut pairs = vec![];
+        match strategy {
+            LinkStrategy::FrontOnly
+                if input_device.is_stereo_input() =>
+            {
+                // Surround channels have nowhere to go on a stereo
+                // target, so only the front ones get linked
+                pairs = self.channel_pairs(
+                    input_device,
+                    AudioChannel::is_front,
+                );
+            }
+            LinkStrategy::ChannelMatch | LinkStrategy::FrontOnly => {
+                // First we check if the two nodes have the same ammount
+                // of channels and the same audio channels
+                if self.ports.len() == input_device.ports.len() {
+                    pairs =
+                        self.channel_pairs(input_device, |_| true);
                 }
-                port.link_port(core.clone(), matching_port.unwrap())?;
-                were_matching_ports_found = true;
+            }
+            LinkStrategy::OneToOne => {
+                pairs = self
+                    .output_ports()
+                    .zip(input_device.input_ports())
+                    .collect();
+            }
+            LinkStrategy::AllToAll => {
+                pairs = self
+                    .output_ports()
+                    .flat_map(|port| {
+                        input_device
+                            .input_ports()
+                            .map(move |other_port| (port, other_port))
+                    })
+                    .collect();
+            }
+            LinkStrategy::FirstToFirst => {
+                pairs = self
+                    .output_ports()
+                    .zip(input_device.input_ports())
+                    .take(1)
+                    .collect();
             }
         }
-        if were_matching_ports_found {
-            return Ok(());
+        if !pairs.is_empty() {
+            return Ok((pairs, false));
         }
+        let pairs =
+            self.equalize_channels(input_device, equalization)?;
+        Ok((pairs, true))
+    }
 
-        // If no matching ports were found, we try to link the first output port in the node to the first input port in the input device
-        let first_port = self
-            .ports
-            .iter()
-            .find(|p| p.direction == PortDirection::Out);
-        if first_port.is_none() {
-            log::warn!("No output port found in node {}", self.name);
-            return Err(NodeError::IncorrectTypeOfChannelDirection(
+    /// Pair the ports of nodes whose channel layouts do not match, as
+    /// described by `equalization`.
+    pub fn equalize_channels<'a>(
+        &'a self,
+        input_device: &'a Self,
+        equalization: ChannelEqualization,
+    ) -> Result<Vec<(&'a Port, &'a Port)>, NodeError> {
+        let outputs: Vec<&Port> = self.output_ports().collect();
+        let inputs: Vec<&Port> = input_device.input_ports().collect();
+        let mismatch = || {
+            NodeError::ChannelMismatch(
+                self.name.clone(),
                 input_device.name.clone(),
-                PortDirection::In,
-            ));
-        }
-        let first_port = first_port.unwrap();
-        for other_port in input_device.ports.iter() {
-            if other_port.direction != PortDirection::In {
-                continue;
+            )
+        };
+        let pairs = match (equalization, outputs.as_slice()) {
+            (_, []) => {
+                log_warn!(
+                    "No output port found in node {}",
+                    self.name
+                );
+                return Err(
+                    NodeError::IncorrectTypeOfChannelDirection(
+                        input_device.name.clone(),
+                        PortDirection::In,
+                    ),
+                );
             }
-            first_port.link_port(core.clone(), other_port)?;
-        }
-        Ok(())
+            (ChannelEqualization::Refuse, _) => {
+                return Err(mismatch())
+            }
+            (ChannelEqualization::FirstToAll, [first, ..])
+            | (_, [first]) => inputs
+                .iter()
+                .map(|other_port| (*first, *other_port))
+                .collect(),
+            (ChannelEqualization::DuplicateAndMix, _)
+                if inputs.len() == 1 =>
+            {
+                outputs
+                    .iter()
+                    .map(|port| (*port, inputs[0]))
+                    .collect()
+            }
+            _ => return Err(mismatch()),
+        };
+        Ok(pairs)
+    }
+
+    /// Pair every output port whose channel passes `filter` with the
+    /// input port of the same channel in `input_device`.
+    fn channel_pairs<'a>(
+        &'a self,
+        input_device: &'a Self,
+        filter: impl Fn(&AudioChannel) -> bool,
+    ) -> Vec<(&'a Port, &'a Port)> {
+        self.output_ports()
+            .filter(|port| filter(&port.audio_channel))
+            .filter_map(|port| {
+                input_device
+                    .input_ports()
+                    .find(|p| p.audio_channel == port.audio_channel)
+                    .map(|matching_port| (port, matching_port))
+            })
+            .collect()
     }
 }
 impl Drop for Node {
     fn drop(&mut self) {
-        log::debug!("Node {}({}) was removed", self.name, self.id);
+        discovery_debug!(
+            "Node {}({}) was removed",
+            self.name,
+            self.id
+        );
+    }
+}
+
+/// A node bound by the backend thread to follow and control its
+/// params.
+pub(crate) struct BoundNode {
+    proxy: NodeProxy,
+    _listener: NodeListener,
+    /// Channels of the node according to its last `Props` param.
+    channels: Rc<Cell<usize>>,
+}
+
+/// Update the properties of a node from its info, running the
+/// callbacks watching the ones that changed once the objects are
+/// unlocked.
+pub(crate) fn apply_node_props(
+    objects: &RwLock<PipeWireObjects>,
+    node_id: u32,
+    props: &DictRef,
+) {
+    let mut objects_guard = objects.write().unwrap();
+    let Some(node) = objects_guard.find_node_by_exact_id_mut(node_id)
+    else {
+        return;
+    };
+    let changed = node.apply_info_props(props);
+    let mut calls = vec![];
+    for key in changed {
+        let Some(callbacks) = objects_guard
+            .property_callbacks
+            .get(&(node_id, key.clone()))
+        else {
+            continue;
+        };
+        let value = props.get(&key).unwrap_or_default().to_owned();
+        calls.extend(
+            callbacks
+                .iter()
+                .map(|callback| (callback.clone(), value.clone())),
+        );
+    }
+    drop(objects_guard);
+    for (callback, value) in calls {
+        callback(&value);
+    }
+}
+
+impl BoundNode {
+    /// Bind the node, mirroring its `Props` param into the objects
+    /// and reporting every change through the sender.
+    pub(crate) fn bind(
+        registry: &Registry,
+        global: &GlobalObject<&DictRef>,
+        objects: Arc<RwLock<PipeWireObjects>>,
+        sender: Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
+    ) -> Result<Self, pipewire::Error> {
+        let node_id = global.id;
+        let proxy = registry.bind::<NodeProxy, _>(global)?;
+        let channels = Rc::new(Cell::new(0));
+        let listener_channels = channels.clone();
+        let info_objects = objects.clone();
+        let info_sender = sender.clone();
+        let listener = proxy
+            .add_listener_local()
+            .info(move |info| {
+                if let Some(props) = info.props() {
+                    apply_node_props(&info_objects, node_id, props);
+                }
+                let mut objects = info_objects.write().unwrap();
+                let Some(node) =
+                    objects.find_node_by_exact_id_mut(node_id)
+                else {
+                    return;
+                };
+                node.set_expected_ports(
+                    info.n_input_ports() as usize,
+                    info.n_output_ports() as usize,
+                );
+                let sender = info_sender.read().unwrap();
+                if node.check_ports_complete() {
+                    let _result = sender.send(
+                        ConnectorEvent::NodePortsComplete(node_id),
+                    );
+                }
+                if let Some(event) =
+                    node.apply_state(NodeState::from(info.state()))
+                {
+                    let _result = sender.send(event);
+                }
+            })
+            .param(move |_seq, id, _index, _next, param| {
+                if id != ParamType::Props {
+                    return;
+                }
+                let Some(props) = param.and_then(NodeProps::parse)
+                else {
+                    return;
+                };
+                if let Some(volumes) = &props.channel_volumes {
+                    listener_channels.set(volumes.len());
+                }
+                let mut objects = objects.write().unwrap();
+                let Some(node) =
+                    objects.find_node_by_exact_id_mut(node_id)
+                else {
+                    return;
+                };
+                let sender = sender.read().unwrap();
+                for event in node.apply_props(&props) {
+                    let _result = sender.send(event);
+                }
+            })
+            .register();
+        proxy.subscribe_params(&[ParamType::Props]);
+        Ok(Self {
+            proxy,
+            _listener: listener,
+            channels,
+        })
+    }
+
+    pub(crate) fn set_mute(&self, mute: bool) -> Result<(), String> {
+        self.set_props(&props::mute_props(mute))
+    }
+
+    pub(crate) fn set_volume(
+        &self,
+        volume: f32,
+    ) -> Result<(), String> {
+        self.set_props(&props::volume_props(
+            volume,
+            self.channels.get(),
+        ))
+    }
+
+    pub(crate) fn set_description(
+        &self,
+        description: &str,
+    ) -> Result<(), String> {
+        self.set_props(&props::description_props(description))
+    }
+
+    fn set_props(&self, props: &Value) -> Result<(), String> {
+        let bytes = props::to_pod_bytes(props)?;
+        let pod = Pod::from_bytes(&bytes)
+            .ok_or("Failed to build the Props param")?;
+        self.proxy.set_param(ParamType::Props, 0, pod);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pipewire::permissions::PermissionFlags;
+    use pipewire::properties::{properties, Properties};
+    use pipewire::registry::GlobalObject;
+    use pipewire::types::ObjectType;
+
+    use super::{
+        ChannelEqualization, CreateNodeError, LinkStrategy, Node,
+        NodeError, NodeInfo, NodeKind, NodeState, VirtualNodeSpec,
+    };
+    use crate::event::ConnectorEvent;
+    use crate::port::{
+        AudioChannel, Port, PortDirection, PortFormat, VideoFormat,
+    };
+    use crate::props::NodeProps;
+    use crate::test_utils::{global, stereo_node};
+
+    fn planned_links(
+        source: &Node,
+        sink: &Node,
+        strategy: LinkStrategy,
+    ) -> Vec<(u32, u32)> {
+        source
+            .plan_links(sink, strategy)
+            .unwrap()
+            .iter()
+            .map(|(port, other_port)| (port.id, other_port.id))
+            .collect()
+    }
+
+    fn equalized_links(
+        source: &Node,
+        sink: &Node,
+        equalization: ChannelEqualization,
+    ) -> Result<Vec<(u32, u32)>, NodeError> {
+        Ok(source
+            .plan_links_with_equalization(
+                sink,
+                LinkStrategy::ChannelMatch,
+                equalization,
+            )?
+            .iter()
+            .map(|(port, other_port)| (port.id, other_port.id))
+            .collect())
+    }
+
+    /// A stereo sink whose ports were discovered right channel first.
+    fn swapped_sink() -> Node {
+        let mut sink = Node::from_parts(2, "sink");
+        sink.add_port(Port::from_parts(
+            21,
+            2,
+            "playback_FR",
+            PortDirection::In,
+            "FR",
+        ));
+        sink.add_port(Port::from_parts(
+            23,
+            2,
+            "playback_FL",
+            PortDirection::In,
+            "FL",
+        ));
+        sink.add_port(Port::from_parts(
+            20,
+            2,
+            "monitor_FR",
+            PortDirection::Out,
+            "FR",
+        ));
+        sink.add_port(Port::from_parts(
+            22,
+            2,
+            "monitor_FL",
+            PortDirection::Out,
+            "FL",
+        ));
+        sink
+    }
+
+    #[test]
+    fn link_strategies_pair_ports_differently() {
+        let source = stereo_node(1, "source", 10);
+        let sink = swapped_sink();
+
+        assert_eq!(
+            planned_links(&source, &sink, LinkStrategy::ChannelMatch),
+            vec![(10, 23), (12, 21)]
+        );
+        assert_eq!(
+            planned_links(&source, &sink, LinkStrategy::OneToOne),
+            vec![(10, 21), (12, 23)]
+        );
+        assert_eq!(
+            planned_links(&source, &sink, LinkStrategy::AllToAll),
+            vec![(10, 21), (10, 23), (12, 21), (12, 23)]
+        );
+        assert_eq!(
+            planned_links(&source, &sink, LinkStrategy::FirstToFirst),
+            vec![(10, 21)]
+        );
+    }
+
+    #[test]
+    fn front_only_strategy_drops_surround_channels() {
+        let mut source = Node::from_parts(1, "surround");
+        for (id, channel) in [
+            (10, "FL"),
+            (11, "FR"),
+            (12, "FC"),
+            (13, "LFE"),
+            (14, "SL"),
+            (15, "SR"),
+        ] {
+            source.add_port(Port::from_parts(
+                id,
+                1,
+                &format!("output_{channel}"),
+                PortDirection::Out,
+                channel,
+            ));
+        }
+        let sink = swapped_sink();
+
+        assert_eq!(
+            planned_links(&source, &sink, LinkStrategy::FrontOnly),
+            vec![(10, 23), (11, 21)]
+        );
+        // The port counts differ, so the channels are equalized
+        assert!(matches!(
+            source.plan_links(&sink, LinkStrategy::ChannelMatch),
+            Err(NodeError::ChannelMismatch(..))
+        ));
+        assert_eq!(
+            equalized_links(
+                &source,
+                &sink,
+                ChannelEqualization::FirstToAll
+            ),
+            Ok(vec![(10, 21), (10, 23)])
+        );
+    }
+
+    #[test]
+    fn mono_outputs_are_duplicated_into_every_channel() {
+        let mut microphone = Node::from_parts(1, "microphone");
+        microphone.add_port(Port::from_parts(
+            10,
+            1,
+            "capture_MONO",
+            PortDirection::Out,
+            "MONO",
+        ));
+        let speakers = stereo_node(2, "speakers", 20);
+
+        for equalization in [
+            ChannelEqualization::FirstToAll,
+            ChannelEqualization::DuplicateAndMix,
+            ChannelEqualization::DuplicateOnly,
+        ] {
+            assert_eq!(
+                equalized_links(&microphone, &speakers, equalization),
+                Ok(vec![(10, 21), (10, 23)])
+            );
+        }
+        assert_eq!(
+            equalized_links(
+                &microphone,
+                &speakers,
+                ChannelEqualization::Refuse
+            ),
+            Err(NodeError::ChannelMismatch(
+                "microphone".into(),
+                "speakers".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn stereo_outputs_are_mixed_or_refused_into_mono() {
+        let source = stereo_node(1, "source", 10);
+        let mut recorder = Node::from_parts(2, "recorder");
+        recorder.add_port(Port::from_parts(
+            20,
+            2,
+            "input_MONO",
+            PortDirection::In,
+            "MONO",
+        ));
+
+        assert_eq!(
+            equalized_links(
+                &source,
+                &recorder,
+                ChannelEqualization::DuplicateAndMix
+            ),
+            Ok(vec![(10, 20), (12, 20)])
+        );
+        assert_eq!(
+            equalized_links(
+                &source,
+                &recorder,
+                ChannelEqualization::FirstToAll
+            ),
+            Ok(vec![(10, 20)])
+        );
+        assert!(matches!(
+            equalized_links(
+                &source,
+                &recorder,
+                ChannelEqualization::DuplicateOnly
+            ),
+            Err(NodeError::ChannelMismatch(..))
+        ));
+    }
+
+    #[test]
+    fn link_reports_show_the_paired_channels() {
+        let source = stereo_node(1, "source", 10);
+        let sink = swapped_sink();
+        let report = source
+            .plan_report(
+                &sink,
+                LinkStrategy::ChannelMatch,
+                ChannelEqualization::default(),
+            )
+            .unwrap();
+
+        let pairs: Vec<_> = report
+            .pairs_linked
+            .iter()
+            .map(|(output, input)| {
+                (
+                    (output.id, output.audio_channel.clone()),
+                    (input.id, input.audio_channel.clone()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ((10, AudioChannel::FL), (23, AudioChannel::FL)),
+                ((12, AudioChannel::FR), (21, AudioChannel::FR)),
+            ]
+        );
+        assert_eq!(report.strategy_used, LinkStrategy::ChannelMatch);
+        assert_eq!(report.equalization_used, None);
+        assert_eq!(report.pairs_linked[0].1.node_id, 2);
+
+        let mut microphone = Node::from_parts(3, "microphone");
+        microphone.add_port(Port::from_parts(
+            30,
+            3,
+            "capture_MONO",
+            PortDirection::Out,
+            "MONO",
+        ));
+        let report = microphone
+            .plan_report(
+                &sink,
+                LinkStrategy::ChannelMatch,
+                ChannelEqualization::DuplicateOnly,
+            )
+            .unwrap();
+        assert_eq!(
+            report.equalization_used,
+            Some(ChannelEqualization::DuplicateOnly)
+        );
+        assert_eq!(report.pairs_linked.len(), 2);
+    }
+
+    #[test]
+    fn unlabeled_ports_take_the_channels_of_their_node() {
+        let unlabeled = |id: u32, index: &str| {
+            let props = properties! {
+                "port.name" => format!("playback_{index}"),
+                "port.direction" => "in",
+                "port.alias" => format!("sink:playback_{index}"),
+                "port.group" => "stream.0",
+                "port.id" => index,
+                "object.serial" => id.to_string(),
+                "object.path" => format!("sink:playback_{index}"),
+                "node.id" => "1",
+            };
+            Port::new(&global(id, ObjectType::Port, &props))
+        };
+        let props = properties! {
+            "node.name" => "sink",
+            "object.serial" => "1",
+            "audio.position" => "[ FL, FR ]",
+        };
+        let mut sink =
+            Node::new(&global(1, ObjectType::Node, &props)).unwrap();
+        sink.add_port(unlabeled(10, "0"));
+        sink.add_port(unlabeled(11, "1"));
+        sink.add_port(unlabeled(12, "2"));
+        let channels = |node: &Node| -> Vec<AudioChannel> {
+            node.ports()
+                .iter()
+                .map(|port| port.audio_channel.clone())
+                .collect()
+        };
+        assert_eq!(
+            channels(&sink),
+            vec![
+                AudioChannel::FL,
+                AudioChannel::FR,
+                AudioChannel::Unknown
+            ]
+        );
+
+        // The ports are discovered before the info of the node
+        let mut sink = Node::from_parts(2, "sink");
+        sink.add_port(unlabeled(20, "0"));
+        sink.add_port(unlabeled(21, "1"));
+        assert_eq!(
+            channels(&sink),
+            vec![AudioChannel::Unknown, AudioChannel::Unknown]
+        );
+        sink.apply_info_props(
+            properties! { "audio.position" => "FR,FL" }.dict(),
+        );
+        assert_eq!(
+            channels(&sink),
+            vec![AudioChannel::FR, AudioChannel::FL]
+        );
+    }
+
+    #[test]
+    fn port_finds_the_port_of_a_direction_and_channel() {
+        let mut node = stereo_node(1, "sink", 10);
+        let port_id = |direction, channel| {
+            node.port(direction, &channel).map(|port| port.id)
+        };
+        assert_eq!(
+            port_id(PortDirection::Out, AudioChannel::FL),
+            Some(10)
+        );
+        assert_eq!(
+            port_id(PortDirection::In, AudioChannel::FR),
+            Some(13)
+        );
+        assert_eq!(
+            port_id(PortDirection::In, AudioChannel::FC),
+            None
+        );
+
+        node.add_port(Port::from_parts(
+            20,
+            1,
+            "capture_AUX0",
+            PortDirection::In,
+            "AUX0",
+        ));
+        assert!(node
+            .port(PortDirection::In, &AudioChannel::Unknown)
+            .is_none());
+    }
+
+    #[test]
+    fn short_name_prefers_nick_then_description() {
+        let mut sink = Node::from_parts(
+            1,
+            "alsa_output.pci-0000_00_1f.3.analog-stereo",
+        );
+        assert_eq!(sink.short_name(), "Analog Stereo");
+
+        sink.description =
+            Some("Built-in Audio Analog Stereo".into());
+        assert_eq!(sink.short_name(), "Built-in Audio Analog Stereo");
+
+        sink.nick = Some("ALC257 Analog".into());
+        assert_eq!(sink.short_name(), "ALC257 Analog");
+
+        sink.nick = Some(String::new());
+        assert_eq!(sink.short_name(), "Built-in Audio Analog Stereo");
+    }
+
+    #[test]
+    fn display_labels_drop_control_characters() {
+        let mut sink =
+            Node::from_parts(1, "alsa_output.usb\u{1b}[31m-headset");
+        sink.description = Some(
+            "USB\u{7}\tHeadset\r\n\u{1b}[2J \u{9b}Pro ".to_owned(),
+        );
+        assert_eq!(
+            sink.description().unwrap(),
+            "USB Headset [2J Pro"
+        );
+        assert_eq!(sink.short_name(), "USB Headset [2J Pro");
+        // The raw value is left as it is
+        assert!(sink.description.as_ref().unwrap().contains('\u{7}'));
+
+        sink.description = Some("\u{0}\u{1}".to_owned());
+        assert_eq!(sink.description(), None);
+        assert!(!sink.short_name().chars().any(char::is_control));
+    }
+
+    #[test]
+    fn nodes_without_properties_or_a_serial_are_ignored() {
+        let bare = GlobalObject {
+            id: 1,
+            permissions: PermissionFlags::all(),
+            type_: ObjectType::Node,
+            version: 3,
+            props: None,
+        };
+        assert!(Node::new(&bare).is_none());
+        let props = properties! { "node.name" => "sink" };
+        assert!(
+            Node::new(&global(1, ObjectType::Node, &props)).is_none()
+        );
+    }
+
+    #[test]
+    fn nodes_without_a_name_fall_back_on_other_properties() {
+        let named = |id: u32, props: &Properties| {
+            Node::new(&global(id, ObjectType::Node, props))
+                .unwrap()
+                .name
+                .clone()
+        };
+        assert_eq!(
+            named(
+                1,
+                &properties! {
+                    "object.serial" => "1",
+                    "node.description" => "HDMI Output",
+                    "object.path" => "alsa:pcm:1:hdmi:1:playback",
+                }
+            ),
+            "HDMI Output"
+        );
+        assert_eq!(
+            named(
+                2,
+                &properties! {
+                    "object.serial" => "2",
+                    "node.name" => "",
+                    "object.path" => "alsa:pcm:1:hdmi:1:playback",
+                }
+            ),
+            "alsa:pcm:1:hdmi:1:playback"
+        );
+        assert_eq!(
+            named(3, &properties! { "object.serial" => "3" }),
+            "node-3"
+        );
+    }
+
+    #[test]
+    fn short_name_cleans_up_node_names() {
+        assert_eq!(
+            Node::from_parts(1, "Firefox").short_name(),
+            "Firefox"
+        );
+        assert_eq!(
+            Node::from_parts(2, "speech-dispatcher-dummy")
+                .short_name(),
+            "Speech Dispatcher Dummy"
+        );
+        assert_eq!(
+            Node::from_parts(3, "bluez_output.AA_BB_CC_DD_EE_FF.1")
+                .short_name(),
+            "AA BB CC DD EE FF"
+        );
+    }
+
+    #[test]
+    fn apply_state_reports_state_changes() {
+        let mut stream = stereo_node(1, "stream", 10);
+        assert_eq!(stream.state, None);
+        assert!(!stream.is_running());
+
+        assert_eq!(
+            stream.apply_state(NodeState::Idle),
+            Some(ConnectorEvent::NodeStateChanged {
+                node_id: 1,
+                state: NodeState::Idle,
+            })
+        );
+        assert_eq!(
+            stream.apply_state(NodeState::Running),
+            Some(ConnectorEvent::NodeStateChanged {
+                node_id: 1,
+                state: NodeState::Running,
+            })
+        );
+        assert_eq!(stream.state, Some(NodeState::Running));
+        assert!(stream.is_running());
+        assert!(NodeInfo::from(&stream).is_running());
+        assert_eq!(stream.apply_state(NodeState::Running), None);
+
+        stream.apply_state(NodeState::Suspended);
+        assert!(stream.is_suspended() && !stream.is_running());
+        stream.apply_state(NodeState::Error("busy".to_owned()));
+        assert!(stream.has_error());
+    }
+
+    #[test]
+    fn apply_props_reports_volume_and_mute_changes() {
+        let mut sink = stereo_node(1, "sink", 10);
+        let props = NodeProps {
+            mute: Some(false),
+            channel_volumes: Some(vec![0.5, 0.25]),
+        };
+        assert_eq!(
+            sink.apply_props(&props),
+            vec![
+                ConnectorEvent::MuteChanged {
+                    node_id: 1,
+                    muted: false
+                },
+                ConnectorEvent::VolumeChanged {
+                    node_id: 1,
+                    volume: 0.375
+                },
+            ]
+        );
+        assert_eq!(sink.mute, Some(false));
+        assert_eq!(sink.volume, Some(0.375));
+
+        // Unchanged values are not reported again
+        assert!(sink.apply_props(&props).is_empty());
+        let props = NodeProps {
+            mute: Some(true),
+            channel_volumes: None,
+        };
+        assert_eq!(
+            sink.apply_props(&props),
+            vec![ConnectorEvent::MuteChanged {
+                node_id: 1,
+                muted: true
+            }]
+        );
+        assert_eq!(sink.volume, Some(0.375));
+    }
+
+    #[test]
+    fn port_counts_follow_added_and_removed_ports() {
+        let recount = |node: &Node| {
+            (node.input_ports().count(), node.output_ports().count())
+        };
+        let mut sink = stereo_node(1, "sink", 10);
+        assert_eq!((sink.input_count(), sink.output_count()), (2, 2));
+
+        sink.add_port(Port::from_parts(
+            14,
+            1,
+            "playback_LFE",
+            PortDirection::In,
+            "LFE",
+        ));
+        assert_eq!((sink.input_count(), sink.output_count()), (3, 2));
+        assert_eq!(
+            (sink.input_count(), sink.output_count()),
+            recount(&sink)
+        );
+
+        assert_eq!(
+            sink.remove_port(10).map(|port| port.id),
+            Some(10)
+        );
+        assert!(sink.remove_port(10).is_none());
+        assert_eq!((sink.input_count(), sink.output_count()), (3, 1));
+        assert_eq!(
+            (sink.input_count(), sink.output_count()),
+            recount(&sink)
+        );
+    }
+
+    #[test]
+    fn ports_are_read_in_discovery_order() {
+        let mut sink = stereo_node(1, "sink", 10);
+        let ids = |node: &Node| {
+            node.ports()
+                .iter()
+                .map(|port| port.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&sink), vec![10, 11, 12, 13]);
+
+        sink.remove_port(11);
+        sink.add_port(Port::from_parts(
+            14,
+            1,
+            "playback_LFE",
+            PortDirection::In,
+            "LFE",
+        ));
+        assert_eq!(ids(&sink), vec![10, 12, 13, 14]);
+        assert_eq!(sink.ports()[3].audio_channel, AudioChannel::LFE);
+    }
+
+    #[test]
+    fn ports_are_classified_as_physical_or_virtual() {
+        let physical = |id: u32, name: &str, direction: &str| {
+            let props = properties! {
+                "port.name" => name,
+                "port.direction" => direction,
+                "port.alias" => name,
+                "port.group" => "stream.0",
+                "object.serial" => id.to_string(),
+                "object.path" => format!("alsa:pcm:0:{name}"),
+                "node.id" => "1",
+                "audio.channel" => "FL",
+                "port.physical" => "true",
+                "port.terminal" => "true",
+            };
+            Port::new(&global(id, ObjectType::Port, &props))
+        };
+        let mut card = Node::from_parts(1, "alsa_output.pci");
+        card.add_port(physical(10, "playback_FL", "in"));
+        card.add_port(Port::from_parts(
+            11,
+            1,
+            "monitor_FL",
+            PortDirection::Out,
+            "FL",
+        ));
+        card.add_port(physical(12, "capture_FL", "out"));
+
+        let ids = |ports: Vec<&Port>| -> Vec<u32> {
+            ports.iter().map(|port| port.id).collect()
+        };
+        assert_eq!(
+            ids(card.physical_ports().collect()),
+            vec![10, 12]
+        );
+        assert_eq!(ids(card.virtual_ports().collect()), vec![11]);
+    }
+
+    fn video_port(id: u32, node_id: u32, direction: &str) -> Port {
+        let props = properties! {
+            "port.name" => format!("video_{direction}"),
+            "port.direction" => direction,
+            "port.alias" => "video",
+            "port.group" => "stream.0",
+            "object.serial" => id.to_string(),
+            "object.path" => format!("test:{node_id}:video"),
+            "node.id" => node_id.to_string(),
+            "format.dsp" => "32 bit float RGBA video",
+            "video.size" => "1280x720",
+            "video.framerate" => "30/1",
+        };
+        Port::new(&global(id, ObjectType::Port, &props))
+    }
+
+    #[test]
+    fn video_ports_link_one_to_one() {
+        let mut camera = Node::from_parts(1, "camera");
+        camera.media_class = Some("Video/Source".to_owned());
+        camera.add_port(video_port(10, 1, "out"));
+        let mut recorder = Node::from_parts(2, "recorder");
+        recorder.media_class = Some("Stream/Input/Video".to_owned());
+        recorder.add_port(video_port(20, 2, "in"));
+
+        assert_eq!(camera.kind(), NodeKind::VideoSource);
+        assert_eq!(recorder.kind(), NodeKind::VideoSink);
+        assert_eq!(
+            camera.ports[0].format,
+            PortFormat::Video(VideoFormat {
+                format: None,
+                size: Some((1280, 720)),
+                framerate: Some((30, 1)),
+            })
+        );
+        assert_eq!(
+            planned_links(&camera, &recorder, LinkStrategy::AllToAll),
+            vec![(10, 20)]
+        );
+
+        let speakers = stereo_node(3, "speakers", 30);
+        assert!(camera.check_link_target(&speakers).is_err());
+    }
+
+    fn virtual_source() -> VirtualNodeSpec {
+        VirtualNodeSpec {
+            name: "easy_pw_mic".to_owned(),
+            description: Some("Mixed Microphone".to_owned()),
+            media_class: "Audio/Source/Virtual".to_owned(),
+            channels: 3,
+            channel_layout: vec![
+                AudioChannel::FL,
+                AudioChannel::FR,
+                AudioChannel::LFE,
+            ],
+        }
+    }
+
+    #[test]
+    fn virtual_node_spec_maps_to_properties() {
+        let properties = virtual_source().properties();
+        for (key, value) in [
+            ("factory.name", "support.null-audio-sink"),
+            ("node.name", "easy_pw_mic"),
+            ("node.description", "Mixed Microphone"),
+            ("media.class", "Audio/Source/Virtual"),
+            ("audio.channels", "3"),
+            ("audio.position", "FL,FR,LFE"),
+        ] {
+            assert_eq!(
+                properties.get(key).map(String::as_str),
+                Some(value),
+                "{key}"
+            );
+        }
+
+        let spec = VirtualNodeSpec {
+            description: None,
+            channel_layout: Vec::new(),
+            ..virtual_source()
+        };
+        let properties = spec.properties();
+        assert_eq!(properties.get("node.description"), None);
+        assert_eq!(properties.get("audio.position"), None);
+    }
+
+    #[test]
+    fn virtual_node_layout_must_match_the_channels() {
+        let spec = VirtualNodeSpec {
+            channels: 2,
+            ..virtual_source()
+        };
+        assert_eq!(
+            spec.validate(),
+            Err(CreateNodeError::LayoutMismatch(
+                "easy_pw_mic".to_owned(),
+                2,
+                3
+            ))
+        );
+        assert_eq!(virtual_source().validate(), Ok(()));
     }
 }
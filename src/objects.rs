@@ -1,32 +1,161 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::rc::Rc;
-use std::sync::{mpsc, Arc, RwLock};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Instant;
 
-use pipewire::registry::Registry;
+use libspa::utils::dict::DictRef;
+use pipewire::registry::{GlobalObject, Registry};
+use pipewire::types::ObjectType;
 
+use crate::changes::{ChangeLog, DiffError, GraphChange};
 use crate::event::ConnectorEvent;
+use crate::utils::discovery_debug;
 
-use super::link::Link;
-use super::node::Node;
-use super::port::Port;
+use super::link::{
+    Link, LinkError, LinkInfo, LogicalLink, Route,
+    PENDING_LINK_TIMEOUT,
+};
+use super::metadata::{DefaultCallback, MetadataStore};
+use super::node::{
+    ChannelEqualization, DriverInfo, LinkStrategy, Node, NodeKind,
+    NodeSelector, PropertyCallback,
+};
+use super::port::{AudioChannel, Port, PortDirection};
+use super::rules::{LinkRule, LinkRuleId};
+use super::server::ServerInfo;
+use super::sync::RwLock;
 #[derive(Default)]
 pub struct PipeWireObjects {
+    /// Add and remove nodes with [`Self::insert_node`] and
+    /// [`Self::remove_node`], which keep them indexed.
     pub nodes: Vec<Node>,
     pub links: Vec<Link>,
     pub(super) _ports_to_be_added: Vec<Port>,
+    pub metadata: MetadataStore,
+    pub(crate) routes: Vec<Route>,
+    pub(crate) server_info: Option<ServerInfo>,
+    /// Whether the objects of the current connection were all
+    /// announced, see [`ConnectorEvent::Ready`].
+    pub(crate) ready: bool,
+    /// Nodes left out by the node filter of the manager.
+    pub(crate) filtered_nodes: HashSet<u32>,
+    /// Called when a default device changes.
+    pub(crate) default_callbacks: Vec<Arc<DefaultCallback>>,
+    /// Position of every node in `nodes` by id, rebuilt whenever
+    /// nodes are added or removed through the objects.
+    pub(crate) node_index: HashMap<u32, usize>,
+    /// Node of every port by port id, kept up to date as ports are
+    /// attached to and detached from nodes through the objects.
+    pub(crate) port_index: HashMap<u32, u32>,
+    /// Keep `nodes` sorted by id and find them by binary search
+    /// instead of through `node_index`, see
+    /// [`PipeWireObjects::keep_nodes_sorted`].
+    pub(crate) sorted_nodes: bool,
+    /// Globals of the types the manager does not handle, only kept
+    /// when the manager is built with
+    /// [`crate::manager::PipeWireManagerBuilder::record_unknown_objects`].
+    pub other_objects: Vec<OtherObject>,
+    /// Rules linking the nodes that appear, kept across reconnections.
+    pub(crate) link_rules: Vec<(LinkRuleId, LinkRule)>,
+    pub(crate) next_link_rule_id: u64,
+    /// Nodes and links added or removed through the objects.
+    pub(crate) changes: ChangeLog,
+    /// How nodes with different channel layouts are linked by the
+    /// link commands.
+    pub(crate) channel_equalization: ChannelEqualization,
+    /// Called when a property of a node changes, by node id and
+    /// property.
+    pub(crate) property_callbacks:
+        HashMap<(u32, String), Vec<Arc<PropertyCallback>>>,
+    /// Pending links unlinked before the server announced them, by
+    /// output and input port, to destroy them once it does.
+    pub(crate) cancelled_links: Vec<((u32, u32), Instant)>,
+    /// Called once, the next time the objects are all announced.
+    /// Behind a lock of its own as the callbacks need not be `Sync`.
+    pub(crate) ready_callbacks: Mutex<Vec<Box<ReadyCallback>>>,
+}
+
+pub(crate) type ReadyCallback = dyn FnOnce() + Send;
+
+/// A broken invariant of the objects, see
+/// [`PipeWireObjects::validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inconsistency {
+    /// Several nodes have the same id.
+    DuplicateNode(u32),
+    /// A port is listed under a node other than its `node_id`.
+    PortOfOtherNode {
+        port_id: u32,
+        node_id: u32,
+        port_node_id: Option<u32>,
+    },
+    /// A port is listed under two nodes.
+    PortInTwoNodes {
+        port_id: u32,
+        first_node: u32,
+        second_node: u32,
+    },
+    /// A link goes from or into a node that is not known.
+    LinkToMissingNode { link_id: u32, node_id: u32 },
+    /// A link goes from or into a port its node does not have, or
+    /// from an input port or into an output port.
+    LinkToInvalidPort {
+        link_id: u32,
+        node_id: u32,
+        port_id: u32,
+    },
+}
+
+/// A global the manager does not handle, as it was announced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OtherObject {
+    pub id: u32,
+    pub type_: ObjectType,
+    pub props: HashMap<String, String>,
+}
+
+impl OtherObject {
+    pub fn new(global: &GlobalObject<&DictRef>) -> Self {
+        Self {
+            id: global.id,
+            type_: global.type_.clone(),
+            props: global
+                .props
+                .map(|props| {
+                    props
+                        .iter()
+                        .map(|(key, value)| {
+                            (key.to_owned(), value.to_owned())
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Adds the nodes with [`PipeWireObjects::insert_node`].
+impl Extend<Node> for PipeWireObjects {
+    fn extend<T: IntoIterator<Item = Node>>(&mut self, nodes: T) {
+        for node in nodes {
+            self.insert_node(node);
+        }
+    }
 }
 
 impl PipeWireObjects {
-    pub fn update_nodes(&mut self) {
+    /// Attach the pending ports to their nodes, returning the nodes
+    /// whose expected ports are now all discovered.
+    pub fn update_nodes(&mut self) -> Vec<u32> {
         let mut nodes: HashMap<u32, (&mut Node, bool)> =
             HashMap::new();
         // Fill nodes
         if self.nodes.is_empty() || self._ports_to_be_added.is_empty()
         {
-            return;
+            return vec![];
         }
-        log::debug!("Nodes Quantity: {:?}", self.nodes.len());
-        log::debug!(
+        discovery_debug!("Nodes Quantity: {:?}", self.nodes.len());
+        discovery_debug!(
             "Ports that need to be added: {:?}",
             self._ports_to_be_added.len()
         );
@@ -35,21 +164,32 @@ impl PipeWireObjects {
         }
 
         let mut ports_not_found: Vec<Port> = vec![];
-        while let Some(port) = self._ports_to_be_added.pop() {
+        // In the order they were announced, which is the order of
+        // the channels of the node
+        for port in std::mem::take(&mut self._ports_to_be_added) {
             let port_id = port.id;
-            let node_id = port.node_id;
+            let Some(node_id) = port.node_id else {
+                // Kept until the server removes it, like the ports
+                // of nodes not announced yet
+                log_error!(
+                    "Port {port_id} has an invalid node.id, keeping it pending"
+                );
+                ports_not_found.push(port);
+                continue;
+            };
 
             if let Some(node) = nodes.get_mut(&node_id) {
                 if node.0.has_port(&port) {
                     continue;
                 }
-                log::debug!(
+                discovery_debug!(
                     "Adding port {port_id} to node {node_id}"
                 );
                 node.0.add_port(port);
+                self.port_index.insert(port_id, node_id);
                 node.1 = true;
             } else {
-                log::error!("Port {port_id} has no node");
+                log_error!("Port {port_id} has no node");
                 ports_not_found.push(port);
             }
         }
@@ -58,16 +198,20 @@ impl PipeWireObjects {
         // That makes sure that it will not be deleted at this time
         self._ports_to_be_added.extend(ports_not_found);
 
-        for (_, (node, updated)) in nodes.iter() {
-            if !updated {
+        let mut complete = vec![];
+        for (_, (node, updated)) in nodes.iter_mut() {
+            if !*updated {
                 continue;
             }
-            log::debug!(
+            discovery_debug!(
                 "Node {}({}) was updated | Ports: {:#?}",
                 node.name,
                 node.id,
                 node.get_port_names()
             );
+            if node.check_ports_complete() {
+                complete.push(node.id);
+            }
         }
 
         // DEBUG ALL NODES:
@@ -76,12 +220,72 @@ impl PipeWireObjects {
         // node.id,
         // node.get_port_names())).collect();
         // log::debug!("{:#?}", str_nodes);
+        complete.sort_unstable();
+        complete
     }
 
-    pub fn find_node_by_id(&self, id: u32) -> Option<&Node> {
-        self.nodes
+    /// Keep the nodes sorted by id, finding them by binary search
+    /// rather than through a hash index. Adding a node costs O(n) as
+    /// the following ones move, which pays off once the graph is
+    /// enumerated and lookups outnumber new nodes.
+    ///
+    /// As always, nodes must be added with [`Self::insert_node`]:
+    /// nodes pushed into `nodes` directly are not found.
+    pub fn keep_nodes_sorted(&mut self, sorted: bool) {
+        self.sorted_nodes = sorted;
+        if sorted {
+            self.nodes.sort_by_key(|node| node.id);
+            self.node_index.clear();
+        } else {
+            self.reindex_nodes();
+        }
+    }
+
+    /// Add a node, keeping the nodes sorted or indexed.
+    pub fn insert_node(&mut self, node: Node) {
+        self.changes.record(GraphChange::NodeAdded(node.id));
+        for port in node.ports() {
+            self.port_index.insert(port.id, node.id);
+        }
+        if self.sorted_nodes {
+            let position = self
+                .nodes
+                .partition_point(|other| other.id < node.id);
+            self.nodes.insert(position, node);
+        } else {
+            self.node_index.insert(node.id, self.nodes.len());
+            self.nodes.push(node);
+        }
+    }
+
+    fn reindex_nodes(&mut self) {
+        if self.sorted_nodes {
+            return;
+        }
+        self.node_index = self
+            .nodes
             .iter()
-            .find(|node| node.id == id || node.has_port_of_id(id))
+            .enumerate()
+            .map(|(position, node)| (node.id, position))
+            .collect();
+    }
+
+    /// Position of the node in `nodes`, by binary search or through
+    /// the index.
+    fn node_position(&self, id: u32) -> Option<usize> {
+        if self.sorted_nodes {
+            return self
+                .nodes
+                .binary_search_by_key(&id, |node| node.id)
+                .ok();
+        }
+        self.node_index.get(&id).copied()
+    }
+
+    pub fn find_node_by_id(&self, id: u32) -> Option<&Node> {
+        self.find_node_by_exact_id(id).or_else(|| {
+            self.find_node_by_exact_id(self.node_id_of_port(id)?)
+        })
     }
 
     #[allow(dead_code)]
@@ -89,9 +293,114 @@ impl PipeWireObjects {
         &mut self,
         id: u32,
     ) -> Option<&mut Node> {
-        self.nodes
-            .iter_mut()
-            .find(|node| node.id == id || node.has_port_of_id(id))
+        let position = self.node_position(id).or_else(|| {
+            self.node_position(self.node_id_of_port(id)?)
+        })?;
+        self.nodes.get_mut(position)
+    }
+
+    /// The node the port belongs to, through the index.
+    pub fn node_id_of_port(&self, port_id: u32) -> Option<u32> {
+        self.port_index.get(&port_id).copied()
+    }
+
+    /// Attach the port to its node, returning whether the node is
+    /// known. See [`Self::update_nodes`] for ports announced before
+    /// their node.
+    pub fn add_port(&mut self, port: Port) -> bool {
+        let Some(node_id) = port.node_id else {
+            return false;
+        };
+        let port_id = port.id;
+        let Some(node) = self.find_node_by_exact_id_mut(node_id)
+        else {
+            return false;
+        };
+        node.add_port(port);
+        self.port_index.insert(port_id, node_id);
+        true
+    }
+
+    /// Detach the port from its node, e.g. once the server removed
+    /// it.
+    pub fn remove_port(&mut self, port_id: u32) -> Option<Port> {
+        let node_id = self.node_id_of_port(port_id)?;
+        self.port_index.remove(&port_id);
+        self.find_node_by_exact_id_mut(node_id)?
+            .remove_port(port_id)
+    }
+
+    /// Unlike [`Self::find_node_by_id`], only matches node ids.
+    pub(crate) fn find_node_by_exact_id(
+        &self,
+        id: u32,
+    ) -> Option<&Node> {
+        self.nodes.get(self.node_position(id)?)
+    }
+
+    pub(crate) fn find_node_by_exact_id_mut(
+        &mut self,
+        id: u32,
+    ) -> Option<&mut Node> {
+        let position = self.node_position(id)?;
+        self.nodes.get_mut(position)
+    }
+
+    /// Find a port by its name within the node of the given id.
+    pub fn find_port_by_name(
+        &self,
+        node_id: u32,
+        port_name: &str,
+    ) -> Result<&Port, LinkError> {
+        let node = self
+            .find_node_by_exact_id(node_id)
+            .ok_or(LinkError::NodeNotFound(node_id))?;
+        node.get_port_by_name(port_name).ok_or_else(|| {
+            LinkError::PortNotFound(node_id, port_name.to_owned())
+        })
+    }
+
+    /// The `(output port, input port)` pair linking every
+    /// `(output channel, input channel)` pair of the mapping.
+    pub fn resolve_channel_mapping(
+        &self,
+        out_node: u32,
+        in_node: u32,
+        mapping: &[(AudioChannel, AudioChannel)],
+    ) -> Vec<Result<(u32, u32), LinkError>> {
+        let port_id = |node_id, direction: PortDirection, channel| {
+            self.find_node_by_exact_id(node_id)
+                .ok_or(LinkError::NodeNotFound(node_id))?
+                .port(direction.clone(), channel)
+                .map(|port| port.id)
+                .ok_or_else(|| {
+                    LinkError::ChannelNotFound(
+                        node_id,
+                        channel.clone(),
+                        direction,
+                    )
+                })
+        };
+        mapping
+            .iter()
+            .map(|(out_channel, in_channel)| {
+                Ok((
+                    port_id(
+                        out_node,
+                        PortDirection::Out,
+                        out_channel,
+                    )?,
+                    port_id(in_node, PortDirection::In, in_channel)?,
+                ))
+            })
+            .collect()
+    }
+
+    pub fn find_port_by_id(&self, port_id: u32) -> Option<&Port> {
+        self.find_node_by_exact_id(self.node_id_of_port(port_id)?)?
+            .ports()
+            .iter()
+            .find(|port| port.id == port_id)
     }
 
     pub fn find_two_nodes_by_id_mut(
@@ -99,20 +408,26 @@ impl PipeWireObjects {
         first_id: u32,
         second_id: u32,
     ) -> (Option<&mut Node>, Option<&mut Node>) {
-        let mut first: Option<&mut Node> = None;
-        let mut second: Option<&mut Node> = None;
-
-        for node in &mut self.nodes {
-            if node.id == first_id {
-                first = Some(node);
-            } else if node.id == second_id {
-                second = Some(node)
+        let first = self.node_position(first_id);
+        let second = self
+            .node_position(second_id)
+            .filter(|_| first_id != second_id);
+        match (first, second) {
+            (Some(first), Some(second)) => {
+                let [first, second] = self
+                    .nodes
+                    .get_disjoint_mut([first, second])
+                    .expect("Nodes have distinct positions");
+                (Some(first), Some(second))
+            }
+            (Some(first), None) => (self.nodes.get_mut(first), None),
+            (None, Some(second)) => {
+                (None, self.nodes.get_mut(second))
             }
+            (None, None) => (None, None),
         }
-        (first, second)
     }
 
-    #[allow(dead_code)]
     pub fn find_links_by_id(&self, id: u32) -> Option<&Link> {
         self.links.iter().find(|link| link.id == id)
     }
@@ -125,32 +440,803 @@ impl PipeWireObjects {
         link.map(|link| (link.output_node, link.input_node))
     }
 
+    /// Ids of every link going from the output node into the input
+    /// node. Pending links have no id yet and are left out, unlike in
+    /// [`Self::link_count_between`] and [`Self::is_linked`]; see
+    /// [`Self::pending_link_count_between`].
+    pub fn find_link_ids_between(
+        &self,
+        output_node: u32,
+        input_node: u32,
+    ) -> Vec<u32> {
+        self.links
+            .iter()
+            .filter(|link| {
+                link.output_node == output_node
+                    && link.input_node == input_node
+                    && !link.is_pending()
+            })
+            .map(|link| link.id)
+            .collect()
+    }
+
+    /// The links of a port, whether it is their output or their
+    /// input port.
+    pub fn port_links(&self, port_id: u32) -> Vec<LinkInfo> {
+        self.links
+            .iter()
+            .filter(|link| {
+                (link.output_port == port_id
+                    || link.input_port == port_id)
+                    && !link.is_expired()
+            })
+            .map(LinkInfo::from)
+            .collect()
+    }
+
+    /// Whether at least one link starts or ends at the port.
+    pub fn is_port_linked(&self, port_id: u32) -> bool {
+        self.links.iter().any(|link| {
+            (link.output_port == port_id
+                || link.input_port == port_id)
+                && !link.is_expired()
+        })
+    }
+
+    /// How many links go from the output node into the input node,
+    /// e.g. 2 for a stereo pair linked on both channels and 1 for a
+    /// partial link. Pending links are counted.
+    pub fn link_count_between(
+        &self,
+        output_node: u32,
+        input_node: u32,
+    ) -> usize {
+        self.links
+            .iter()
+            .filter(|link| {
+                link.output_node == output_node
+                    && link.input_node == input_node
+                    && !link.is_expired()
+            })
+            .count()
+    }
+
+    /// How many of the links from the output node into the input
+    /// node were created by the manager but not announced yet, see
+    /// [`Link::is_pending`].
+    pub fn pending_link_count_between(
+        &self,
+        output_node: u32,
+        input_node: u32,
+    ) -> usize {
+        self.links
+            .iter()
+            .filter(|link| {
+                link.output_node == output_node
+                    && link.input_node == input_node
+                    && link.is_pending()
+                    && !link.is_expired()
+            })
+            .count()
+    }
+
+    /// Whether at least one link goes from the output node into the
+    /// input node.
+    pub fn is_linked(
+        &self,
+        output_node: u32,
+        input_node: u32,
+    ) -> bool {
+        self.links.iter().any(|link| {
+            link.output_node == output_node
+                && link.input_node == input_node
+                && !link.is_expired()
+        })
+    }
+
+    /// The `(output port, input port)` pairs linking the output node
+    /// into the input node would create, with the default
+    /// [`LinkStrategy`] and the configured [`ChannelEqualization`],
+    /// leaving out the pairs already linked.
+    pub fn missing_links(
+        &self,
+        output_node: u32,
+        input_node: u32,
+    ) -> Result<Vec<(u32, u32)>, LinkError> {
+        let output = self
+            .find_node_by_exact_id(output_node)
+            .ok_or(LinkError::NodeNotFound(output_node))?;
+        let input = self
+            .find_node_by_exact_id(input_node)
+            .ok_or(LinkError::NodeNotFound(input_node))?;
+        let pairs = output.plan_links_with_equalization(
+            input,
+            LinkStrategy::default(),
+            self.channel_equalization,
+        )?;
+        Ok(pairs
+            .into_iter()
+            .map(|(output, input)| (output.id, input.id))
+            .filter(|&(output_port, input_port)| {
+                !self.links.iter().any(|link| {
+                    link.output_port == output_port
+                        && link.input_port == input_port
+                        && !link.is_expired()
+                })
+            })
+            .collect())
+    }
+
+    /// Check whether the output node could be linked into the input
+    /// node, performing the same validation as an actual link
+    /// without creating anything.
+    pub fn can_link(
+        &self,
+        output_node: u32,
+        input_node: u32,
+    ) -> Result<(), LinkError> {
+        if output_node == input_node {
+            return Err(LinkError::SameNode(output_node));
+        }
+        let output = self
+            .find_node_by_exact_id(output_node)
+            .ok_or(LinkError::NodeNotFound(output_node))?;
+        let input = self
+            .find_node_by_exact_id(input_node)
+            .ok_or(LinkError::NodeNotFound(input_node))?;
+
+        if self.is_linked(output_node, input_node) {
+            return Err(LinkError::AlreadyLinked(
+                output_node,
+                input_node,
+            ));
+        }
+        self.check_channels(output, input)?;
+
+        if self.is_reachable(input_node, output_node) {
+            return Err(LinkError::WouldCreateCycle(
+                output_node,
+                input_node,
+            ));
+        }
+        Ok(())
+    }
+
+    /// The nodes [`Self::can_link`] accepts linking the output node
+    /// into, sorted by id, e.g. to highlight the valid drop targets
+    /// while dragging a source. The links are only walked once for
+    /// all the candidates.
+    pub fn valid_targets_for(&self, output_node: u32) -> Vec<u32> {
+        let Some(output) = self.find_node_by_exact_id(output_node)
+        else {
+            return vec![];
+        };
+        let linked: HashSet<u32> = self
+            .links
+            .iter()
+            .filter(|link| link.output_node == output_node)
+            .map(|link| link.input_node)
+            .collect();
+        // Linking into a node audio flows from would close a cycle
+        let upstream = self.upstream_of(output_node);
+        let mut targets: Vec<u32> = self
+            .nodes
+            .iter()
+            .filter(|input| {
+                !upstream.contains(&input.id)
+                    && !linked.contains(&input.id)
+                    && self.check_channels(output, input).is_ok()
+            })
+            .map(|input| input.id)
+            .collect();
+        targets.sort_unstable();
+        targets
+    }
+
+    /// Check the ports of the output node can be paired with those of
+    /// the input node with the default [`LinkStrategy`] and the
+    /// configured [`ChannelEqualization`], as linking them would.
+    fn check_channels(
+        &self,
+        output: &Node,
+        input: &Node,
+    ) -> Result<(), LinkError> {
+        output.plan_links_with_equalization(
+            input,
+            LinkStrategy::default(),
+            self.channel_equalization,
+        )?;
+        Ok(())
+    }
+
+    /// The nodes audio reaches `node` from through the existing
+    /// links, including `node` itself.
+    fn upstream_of(&self, node: u32) -> HashSet<u32> {
+        let mut upstream = HashSet::from([node]);
+        let mut pending = vec![node];
+        while let Some(node) = pending.pop() {
+            for link in &self.links {
+                if link.input_node == node
+                    && upstream.insert(link.output_node)
+                {
+                    pending.push(link.output_node);
+                }
+            }
+        }
+        upstream
+    }
+
+    /// The links as `(output_node, input_node)` pairs, with the links
+    /// of every channel between two nodes collapsed into one pair.
+    /// Pairs are ordered by output node, then input node.
+    pub fn node_adjacency(&self) -> Vec<(u32, u32)> {
+        let mut adjacency: Vec<(u32, u32)> = self
+            .links
+            .iter()
+            .map(|link| (link.output_node, link.input_node))
+            .collect();
+        adjacency.sort_unstable();
+        adjacency.dedup();
+        adjacency
+    }
+
+    /// The links grouped by the nodes they link, ordered by output
+    /// node, then input node. Pending links are left out, see
+    /// [`Link::is_pending`].
+    pub fn logical_links(&self) -> Vec<LogicalLink> {
+        let mut links: Vec<&Link> = self
+            .links
+            .iter()
+            .filter(|link| !link.is_pending())
+            .collect();
+        links.sort_unstable_by_key(|link| {
+            (link.output_node, link.input_node, link.id)
+        });
+        let mut logical: Vec<LogicalLink> = vec![];
+        for link in links {
+            match logical.last_mut() {
+                Some(last)
+                    if (last.output_node, last.input_node)
+                        == (link.output_node, link.input_node) =>
+                {
+                    last.link_ids.push(link.id);
+                }
+                _ => logical.push(LogicalLink {
+                    output_node: link.output_node,
+                    input_node: link.input_node,
+                    link_ids: vec![link.id],
+                }),
+            }
+        }
+        logical
+    }
+
+    /// Whether audio leaving node `from` can reach node `to` by
+    /// following the existing links.
+    fn is_reachable(&self, from: u32, to: u32) -> bool {
+        self.path_between(from, to).is_some()
+    }
+
+    /// The shortest chain of nodes audio leaving node `from` goes
+    /// through to reach node `to`, both included, e.g. a source, a
+    /// virtual sink and the speakers. `None` if `to` is unreachable.
+    pub fn path_between(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Option<Vec<u32>> {
+        let adjacency = self.node_adjacency();
+        // Node each visited node was reached from
+        let mut previous: HashMap<u32, u32> = HashMap::new();
+        let mut pending = VecDeque::from([from]);
+        while let Some(node) = pending.pop_front() {
+            if node == to {
+                let mut path = vec![to];
+                while let Some(&node) = previous.get(path.last()?) {
+                    path.push(node);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &(_, input_node) in adjacency
+                .iter()
+                .filter(|(output_node, _)| *output_node == node)
+            {
+                if input_node != from
+                    && !previous.contains_key(&input_node)
+                {
+                    previous.insert(input_node, node);
+                    pending.push_back(input_node);
+                }
+            }
+        }
+        None
+    }
+
+    /// The node driving the most nodes, with its clock. `None` if no
+    /// node reports its driver.
+    pub fn driver_info(&self) -> Option<DriverInfo> {
+        let mut followers: HashMap<u32, usize> = HashMap::new();
+        for node in self.nodes.iter() {
+            if let Some(driver_id) = node.driver_id {
+                *followers.entry(driver_id).or_insert(0) += 1;
+            }
+        }
+        let driver = followers
+            .into_iter()
+            .filter_map(|(driver_id, count)| {
+                Some((self.find_node_by_exact_id(driver_id)?, count))
+            })
+            // Most followers first, then lowest id
+            .max_by_key(|(driver, count)| {
+                (*count, u32::MAX - driver.id)
+            })
+            .map(|(driver, _)| driver)?;
+        Some(DriverInfo {
+            node_id: driver.id,
+            quantum: driver.quantum,
+            rate: driver.rate,
+        })
+    }
+
+    /// The nodes of the given kind, leaving out the unavailable ones
+    /// unless `include_unavailable` is set, see
+    /// [`Node::is_available`].
+    pub fn nodes_of_kind(
+        &self,
+        kind: NodeKind,
+        include_unavailable: bool,
+    ) -> impl Iterator<Item = &Node> {
+        self.nodes.iter().filter(move |node| {
+            node.kind() == kind
+                && (include_unavailable || node.is_available())
+        })
+    }
+
+    /// How many nodes of each kind currently exist.
+    /// Kinds without any node are left out.
+    pub fn counts_by_kind(&self) -> HashMap<NodeKind, usize> {
+        let mut counts = HashMap::new();
+        for node in self.nodes.iter() {
+            *counts.entry(node.kind()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Check the invariants the objects are expected to keep, e.g.
+    /// while debugging or at the end of a test, returning every
+    /// problem found. Nothing is reported for consistent objects.
+    pub fn validate(&self) -> Vec<Inconsistency> {
+        let mut problems = Vec::new();
+        let mut nodes: HashMap<u32, &Node> = HashMap::new();
+        let mut port_nodes: HashMap<u32, u32> = HashMap::new();
+        for node in self.nodes.iter() {
+            if nodes.insert(node.id, node).is_some() {
+                problems.push(Inconsistency::DuplicateNode(node.id));
+                continue;
+            }
+            for port in node.ports() {
+                if port.node_id != Some(node.id) {
+                    problems.push(Inconsistency::PortOfOtherNode {
+                        port_id: port.id,
+                        node_id: node.id,
+                        port_node_id: port.node_id,
+                    });
+                }
+                if let Some(first_node) =
+                    port_nodes.insert(port.id, node.id)
+                {
+                    problems.push(Inconsistency::PortInTwoNodes {
+                        port_id: port.id,
+                        first_node,
+                        second_node: node.id,
+                    });
+                }
+            }
+        }
+        for link in self.links.iter() {
+            for (node_id, port_id, direction) in [
+                (
+                    link.output_node,
+                    link.output_port,
+                    PortDirection::Out,
+                ),
+                (link.input_node, link.input_port, PortDirection::In),
+            ] {
+                let Some(node) = nodes.get(&node_id) else {
+                    problems.push(Inconsistency::LinkToMissingNode {
+                        link_id: link.id,
+                        node_id,
+                    });
+                    continue;
+                };
+                if !node.ports().iter().any(|port| {
+                    port.id == port_id && port.direction == direction
+                }) {
+                    problems.push(Inconsistency::LinkToInvalidPort {
+                        link_id: link.id,
+                        node_id,
+                        port_id,
+                    });
+                }
+            }
+        }
+        problems
+    }
+
+    /// Forget every node, port and link, e.g. before enumerating the
+    /// graph again. Everything else, such as routes and metadata, is
+    /// kept.
+    pub fn clear(&mut self) {
+        log_debug!(
+            "Clearing {} nodes and {} links",
+            self.nodes.len(),
+            self.links.len()
+        );
+        self.nodes.clear();
+        self.links.clear();
+        self.cancelled_links.clear();
+        self._ports_to_be_added.clear();
+        self.node_index.clear();
+        self.port_index.clear();
+        self.changes.reset();
+    }
+
+    /// Mark the objects of the current connection as all announced,
+    /// returning the callbacks waiting for it.
+    pub(crate) fn set_ready(&mut self) -> Vec<Box<ReadyCallback>> {
+        self.ready = true;
+        std::mem::take(self.ready_callbacks.get_mut().unwrap())
+    }
+
+    /// Forget every node, port, link, metadata and the server info,
+    /// as when the connection to the server is lost. Routes are kept.
+    pub(crate) fn clear_graph(&mut self) {
+        self.clear();
+        self.metadata = MetadataStore::default();
+        self.server_info = None;
+        self.ready = false;
+        self.filtered_nodes.clear();
+        self.other_objects.clear();
+    }
+
+    /// Remember that the nodes were linked through the manager.
+    pub(crate) fn record_route(
+        &mut self,
+        output_node: u32,
+        input_node: u32,
+        strategy: LinkStrategy,
+    ) {
+        let (Some(output), Some(input)) = (
+            self.find_node_by_exact_id(output_node),
+            self.find_node_by_exact_id(input_node),
+        ) else {
+            return;
+        };
+        let route = Route {
+            output_node: output.fingerprint(),
+            input_node: input.fingerprint(),
+            strategy,
+            ports: Vec::new(),
+        };
+        self.routes.retain(|other| {
+            other.output_node != route.output_node
+                || other.input_node != route.input_node
+        });
+        self.routes.push(route);
+    }
+
+    /// Remember that the ports were linked through the manager,
+    /// unless their nodes are already linked as a whole.
+    pub(crate) fn record_port_route(
+        &mut self,
+        output_port: u32,
+        input_port: u32,
+    ) {
+        let (Some(output), Some(input)) = (
+            self.find_port_by_id(output_port),
+            self.find_port_by_id(input_port),
+        ) else {
+            return;
+        };
+        let node =
+            |port: &Port| self.find_node_by_exact_id(port.node_id?);
+        let (Some(output_node), Some(input_node)) =
+            (node(output), node(input))
+        else {
+            return;
+        };
+        let ports = (output.name.clone(), input.name.clone());
+        let (output_node, input_node) =
+            (output_node.fingerprint(), input_node.fingerprint());
+        let route = self.routes.iter_mut().find(|route| {
+            route.output_node == output_node
+                && route.input_node == input_node
+        });
+        match route {
+            Some(route) if route.ports.is_empty() => {}
+            Some(route) => {
+                if !route.ports.contains(&ports) {
+                    route.ports.push(ports);
+                }
+            }
+            None => self.routes.push(Route {
+                output_node,
+                input_node,
+                strategy: LinkStrategy::default(),
+                ports: vec![ports],
+            }),
+        }
+    }
+
+    /// Whether the nodes were linked through the manager.
+    pub(crate) fn is_route(
+        &self,
+        output_node: u32,
+        input_node: u32,
+    ) -> bool {
+        let (Some(output), Some(input)) = (
+            self.find_node_by_exact_id(output_node),
+            self.find_node_by_exact_id(input_node),
+        ) else {
+            return false;
+        };
+        let (output, input) =
+            (output.fingerprint(), input.fingerprint());
+        self.routes.iter().any(|route| {
+            route.output_node == output && route.input_node == input
+        })
+    }
+
+    /// Forget the route between the nodes, once they are unlinked.
+    pub(crate) fn forget_route(
+        &mut self,
+        output_node: u32,
+        input_node: u32,
+    ) {
+        let (Some(output), Some(input)) = (
+            self.find_node_by_exact_id(output_node),
+            self.find_node_by_exact_id(input_node),
+        ) else {
+            return;
+        };
+        let (output, input) =
+            (output.fingerprint(), input.fingerprint());
+        self.routes.retain(|route| {
+            route.output_node != output || route.input_node != input
+        });
+    }
+
+    /// The routes whose nodes currently exist and can be linked, as
+    /// `(output_node, input_node, strategy)`. Routes of single ports
+    /// are left to [`Self::port_routes_to_restore`].
+    pub(crate) fn routes_to_restore(
+        &self,
+    ) -> Vec<(u32, u32, LinkStrategy)> {
+        self.routes
+            .iter()
+            .filter(|route| route.ports.is_empty())
+            .filter_map(|route| {
+                let (output_node, input_node) =
+                    self.route_nodes(route)?;
+                Some((output_node.id, input_node.id, route.strategy))
+            })
+            .filter(|(output_node, input_node, _)| {
+                self.can_link(*output_node, *input_node).is_ok()
+            })
+            .collect()
+    }
+
+    /// The ports of the routes linked port by port that currently
+    /// exist and are not linked, as `((output_node, output_port),
+    /// (input_node, input_port))`.
+    pub(crate) fn port_routes_to_restore(
+        &self,
+    ) -> Vec<((u32, u32), (u32, u32))> {
+        let mut pairs = Vec::new();
+        for route in &self.routes {
+            let Some((output_node, input_node)) =
+                self.route_nodes(route)
+            else {
+                continue;
+            };
+            for (output_port, input_port) in &route.ports {
+                let (Some(output), Some(input)) = (
+                    output_node.get_port_by_name(output_port),
+                    input_node.get_port_by_name(input_port),
+                ) else {
+                    continue;
+                };
+                let linked = self.links.iter().any(|link| {
+                    link.output_port == output.id
+                        && link.input_port == input.id
+                });
+                if !linked {
+                    pairs.push((
+                        (output_node.id, output.id),
+                        (input_node.id, input.id),
+                    ));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// The nodes of the route, found by their fingerprint.
+    fn route_nodes(&self, route: &Route) -> Option<(&Node, &Node)> {
+        let find = |fingerprint: &String| {
+            self.find_node_by(&NodeSelector::Fingerprint(
+                fingerprint.clone(),
+            ))
+        };
+        Some((find(&route.output_node)?, find(&route.input_node)?))
+    }
+
     pub fn find_node_id_by_name(&self, name: &str) -> Option<u32> {
-        let node = self.nodes.iter().find(|node| node.name == name);
-        node.map(|node| node.id)
+        self.find_node_by(&NodeSelector::Name(name.to_owned()))
+            .map(|node| node.id)
+    }
+
+    /// The node `selector` designates, the first one if several do.
+    pub fn find_node_by(
+        &self,
+        selector: &NodeSelector,
+    ) -> Option<&Node> {
+        match selector {
+            NodeSelector::Id(id) => self.find_node_by_exact_id(*id),
+            _ => {
+                self.nodes.iter().find(|node| selector.matches(node))
+            }
+        }
     }
 
     pub fn remove_node(&mut self, id: u32) {
-        if let Some(index) =
-            self.nodes.iter().position(|n| n.id == id)
-        {
-            self.nodes.remove(index);
+        if let Some(index) = self.node_position(id) {
+            let node = self.nodes.remove(index);
+            for port in node.ports() {
+                self.port_index.remove(&port.id);
+            }
+            self.changes.record(GraphChange::NodeRemoved(id));
+            self.property_callbacks
+                .retain(|(node, _), _| *node != id);
+            // Links of the node will never be announced anymore
+            self.links.retain(|link| {
+                !link.is_pending()
+                    || (link.output_node != id
+                        && link.input_node != id)
+            });
+            // Every following node moved back by one
+            self.reindex_nodes();
+        }
+    }
+
+    /// Add a link announced by the server, replacing the pending
+    /// link between the same ports if the manager created it. Returns
+    /// whether it did.
+    pub fn insert_link(&mut self, link: Link) -> bool {
+        self.expire_pending_links();
+        let pending = self.links.iter().position(|pending| {
+            pending.is_pending()
+                && pending.output_port == link.output_port
+                && pending.input_port == link.input_port
+        });
+        if let Some(position) = pending {
+            self.links.remove(position);
         }
+        self.changes.record(GraphChange::LinkAdded(link.id));
+        self.links.push(link);
+        pending.is_some()
     }
+
+    /// Add a link the manager just created, until the server
+    /// announces it. See [`Link::is_pending`].
+    pub(crate) fn insert_pending_link(&mut self, link: Link) {
+        self.expire_pending_links();
+        self.links.push(link);
+    }
+
+    /// Forget the pending links from the output node into the input
+    /// node, returning how many there were. The links are destroyed
+    /// once the server announces them, see
+    /// [`Self::take_cancelled_link`].
+    pub(crate) fn cancel_pending_links(
+        &mut self,
+        output_node: u32,
+        input_node: u32,
+    ) -> usize {
+        self.expire_pending_links();
+        let now = Instant::now();
+        let before = self.cancelled_links.len();
+        self.links.retain(|link| {
+            let cancelled = link.is_pending()
+                && link.output_node == output_node
+                && link.input_node == input_node;
+            if cancelled {
+                self.cancelled_links
+                    .push(((link.output_port, link.input_port), now));
+            }
+            !cancelled
+        });
+        self.cancelled_links.len() - before
+    }
+
+    /// Whether the announced link was unlinked while it was pending,
+    /// in which case it has to be destroyed instead of added.
+    pub(crate) fn take_cancelled_link(
+        &mut self,
+        link: &Link,
+    ) -> bool {
+        self.expire_pending_links();
+        let position =
+            self.cancelled_links.iter().position(|(ports, _)| {
+                *ports == (link.output_port, link.input_port)
+            });
+        if let Some(position) = position {
+            self.cancelled_links.remove(position);
+        }
+        position.is_some()
+    }
+
+    /// Drop the pending and cancelled links the server did not
+    /// announce within [`PENDING_LINK_TIMEOUT`].
+    fn expire_pending_links(&mut self) {
+        self.links.retain(|link| {
+            if link.is_expired() {
+                log_warn!(
+                    "The link from port {} into port {} was never announced, forgetting it",
+                    link.output_port,
+                    link.input_port
+                );
+            }
+            !link.is_expired()
+        });
+        self.cancelled_links.retain(|(_, since)| {
+            since.elapsed() < PENDING_LINK_TIMEOUT
+        });
+    }
+
+    /// How many nodes and links were added or removed so far, to
+    /// later ask for the [`Self::changes_since`] that version.
+    pub fn graph_version(&self) -> u64 {
+        self.changes.version()
+    }
+
+    /// The nodes and links added or removed after `version`, oldest
+    /// first. Only the latest changes are kept (see
+    /// [`Self::set_change_log_capacity`]) and clearing the graph
+    /// drops them all: [`DiffError::TooOld`] then means a full
+    /// snapshot is needed instead.
+    pub fn changes_since(
+        &self,
+        version: u64,
+    ) -> Result<Vec<GraphChange>, DiffError> {
+        self.changes.since(version)
+    }
+
+    /// How many changes are kept for [`Self::changes_since`],
+    /// [`crate::changes::DEFAULT_CHANGE_LOG_CAPACITY`] by default.
+    pub fn set_change_log_capacity(&mut self, capacity: usize) {
+        self.changes.set_capacity(capacity);
+    }
+
     #[allow(dead_code)]
     pub fn print_nodes(&self) {
         self.nodes.iter().for_each(|node| {
-            log::info!("=======================\nNode ID: {}, \nNode Name: {} \nNode Description {:?} \nPorts: {:?}", node.id, node.name, node.description, node.get_port_names());
+            log_info!("=======================\nNode ID: {}, \nNode Name: {} \nNode Description {:?} \nPorts: {:?}", node.id, node.name, node.description, node.get_port_names());
         });
     }
     /// Removes a link from the list of links and optionally from the registry.
     /// If registry is None, then it will not remove the link from the registry.
+    ///
+    /// Returns the linked nodes, or `None` when one of them is
+    /// already gone: the link is then only forgotten, the server
+    /// removing it along with its node.
     pub async fn remove_link(
         &mut self,
         id: u32,
         registry: Option<Rc<RwLock<Registry>>>,
         sender: Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
-    ) -> Result<(u32, u32), String> {
+    ) -> Result<Option<(u32, u32)>, String> {
         let link = self.find_linked_nodes_by_link_id_mut(id);
         if link.is_none() {
             return Err(format!("Failed to find link with id {id}"));
@@ -164,17 +1250,18 @@ impl PipeWireObjects {
             self.find_two_nodes_by_id_mut(input_node, output_node);
 
         // In case this fails, it means that one of the nodes were deleted earlier.
-        if first_node.is_some() && second_node.is_some() {
-            let first_node = first_node.unwrap();
-            let second_node = second_node.unwrap();
-            log::debug!(
+        let nodes_exist =
+            first_node.is_some() && second_node.is_some();
+        if let (Some(first_node), Some(second_node)) =
+            (first_node, second_node)
+        {
+            log_debug!(
                 "Removing the link between node {} and node {}",
                 first_node.name,
                 second_node.name
             );
 
-            if registry.is_some() {
-                let registry = registry.unwrap();
+            if let Some(registry) = registry {
                 Link::remove_link(id, registry).await;
             }
         }
@@ -182,10 +1269,1019 @@ impl PipeWireObjects {
         let index =
             self.links.iter().position(|link| link.id == id).unwrap();
         self.links.remove(index);
-        let _result = sender
+        self.changes.record(GraphChange::LinkRemoved(id));
+        let sender = sender
             .read()
-            .map_err(|_| "Remove Link Sender is Poisoned")?
-            .send(ConnectorEvent::UnlinkUpdate(link.0, link.1));
-        Ok(link)
+            .map_err(|_| "Remove Link Sender is Poisoned")?;
+        let _result = sender.send(ConnectorEvent::LinkRemoved(id));
+        let _result =
+            sender.send(ConnectorEvent::UnlinkUpdate(link.0, link.1));
+        Ok(nodes_exist.then_some(link))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pipewire::properties::properties;
+    use pipewire::types::ObjectType;
+
+    use std::sync::{mpsc, Arc};
+    use std::time::Instant;
+
+    use futures::executor::block_on;
+
+    use super::{Inconsistency, PipeWireObjects};
+    use crate::changes::{DiffError, GraphChange};
+    use crate::event::ConnectorEvent;
+    use crate::link::{
+        Link, LinkError, LinkInfo, LinkState, PENDING_LINK_ID,
+        PENDING_LINK_TIMEOUT,
+    };
+    use crate::node::{
+        ChannelEqualization, LinkStrategy, Node, NodeError, NodeKind,
+    };
+    use crate::port::{AudioChannel, Port, PortDirection};
+    use crate::sync::RwLock;
+    use crate::test_utils::{global, stereo_node, with_nodes};
+
+    #[test]
+    fn ports_resolve_to_their_node() {
+        let mut objects = PipeWireObjects::default();
+        objects.insert_node(stereo_node(1, "source", 10));
+        objects.insert_node(Node::from_parts(2, "sink"));
+        objects._ports_to_be_added.extend([
+            Port::from_parts(
+                20,
+                2,
+                "playback_FL",
+                PortDirection::In,
+                "FL",
+            ),
+            Port::from_parts(
+                21,
+                2,
+                "playback_FR",
+                PortDirection::In,
+                "FR",
+            ),
+        ]);
+        objects.update_nodes();
+
+        assert_eq!(objects.node_id_of_port(12), Some(1));
+        assert_eq!(objects.node_id_of_port(21), Some(2));
+        assert_eq!(objects.port_index.len(), 6);
+        assert_eq!(objects.node_id_of_port(99), None);
+
+        assert_eq!(
+            objects.remove_port(21).map(|port| port.id),
+            Some(21)
+        );
+        assert_eq!(objects.node_id_of_port(21), None);
+        assert!(objects.find_port_by_id(21).is_none());
+        assert_eq!(
+            objects.find_port_by_id(20).unwrap().node_id,
+            Some(2)
+        );
+        assert_eq!(objects.nodes[1].input_count(), 1);
+
+        assert!(objects.add_port(Port::from_parts(
+            22,
+            2,
+            "playback_FC",
+            PortDirection::In,
+            "FC"
+        )));
+        assert!(!objects.add_port(Port::from_parts(
+            30,
+            3,
+            "playback_FL",
+            PortDirection::In,
+            "FL"
+        )));
+        assert_eq!(objects.node_id_of_port(22), Some(2));
+        assert_eq!(objects.node_id_of_port(30), None);
+
+        objects.remove_node(1);
+        assert_eq!(objects.node_id_of_port(10), None);
+        assert_eq!(objects.port_index.len(), 2);
+    }
+
+    #[test]
+    fn ports_complete_once_the_last_expected_port_attaches() {
+        let mut objects = PipeWireObjects::default();
+        objects.insert_node(
+            Node::new(&global(
+                1,
+                ObjectType::Node,
+                &properties! {
+                    "object.serial" => "1",
+                    "node.name" => "speakers",
+                    "node.n-input-ports" => "2",
+                    "node.n-output-ports" => "1",
+                },
+            ))
+            .unwrap(),
+        );
+        assert_eq!(objects.nodes[0].expected_port_count(), Some(3));
+
+        let mut completions = vec![];
+        // More outputs than expected do not make up for a missing
+        // input
+        for port in [
+            Port::from_parts(
+                12,
+                1,
+                "monitor_FL",
+                PortDirection::Out,
+                "FL",
+            ),
+            Port::from_parts(
+                13,
+                1,
+                "monitor_FR",
+                PortDirection::Out,
+                "FR",
+            ),
+            Port::from_parts(
+                10,
+                1,
+                "playback_FL",
+                PortDirection::In,
+                "FL",
+            ),
+            Port::from_parts(
+                11,
+                1,
+                "playback_FR",
+                PortDirection::In,
+                "FR",
+            ),
+        ] {
+            objects._ports_to_be_added.push(port);
+            completions.push(objects.update_nodes());
+        }
+
+        assert_eq!(
+            completions,
+            vec![vec![], vec![], vec![], vec![1]]
+        );
+        assert!(objects.nodes[0].ports_complete());
+    }
+
+    #[test]
+    fn node_lookups_stay_consistent_after_removals() {
+        let mut objects = with_nodes(
+            (1..=5).map(|id| Node::from_parts(id, "node")),
+        );
+        objects.remove_node(2);
+        objects.remove_node(4);
+        objects.insert_node(stereo_node(6, "sink", 60));
+
+        for id in [1, 3, 5, 6] {
+            assert_eq!(
+                objects.find_node_by_exact_id(id).unwrap().id,
+                id
+            );
+        }
+        for id in [2, 4, 7] {
+            assert!(objects.find_node_by_exact_id(id).is_none());
+        }
+        assert_eq!(objects.find_node_by_id(61).unwrap().id, 6);
+        let (first, second) = objects.find_two_nodes_by_id_mut(5, 1);
+        assert_eq!((first.unwrap().id, second.unwrap().id), (5, 1));
+        let (first, second) = objects.find_two_nodes_by_id_mut(3, 3);
+        assert_eq!(first.unwrap().id, 3);
+        assert!(second.is_none());
+    }
+
+    #[test]
+    fn resolve_channel_mapping_swaps_channels() {
+        let objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ]);
+        let swap = [
+            (AudioChannel::FL, AudioChannel::FR),
+            (AudioChannel::FR, AudioChannel::FL),
+            (AudioChannel::FC, AudioChannel::FL),
+        ];
+        assert_eq!(
+            objects.resolve_channel_mapping(1, 2, &swap),
+            vec![
+                Ok((10, 23)),
+                Ok((12, 21)),
+                Err(LinkError::ChannelNotFound(
+                    1,
+                    AudioChannel::FC,
+                    PortDirection::Out
+                )),
+            ]
+        );
+        assert_eq!(
+            objects.resolve_channel_mapping(1, 3, &swap[..1]),
+            vec![Err(LinkError::NodeNotFound(3))]
+        );
+    }
+
+    #[test]
+    fn find_port_by_name_resolves_named_ports() {
+        let objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ]);
+
+        let port =
+            objects.find_port_by_name(1, "monitor_FR").unwrap();
+        assert_eq!(port.id, 12);
+        assert_eq!(port.node_id, Some(1));
+
+        let port =
+            objects.find_port_by_name(2, "playback_FL").unwrap();
+        assert_eq!(port.id, 21);
+        assert_eq!(port.node_id, Some(2));
+    }
+
+    #[test]
+    fn update_nodes_keeps_ports_with_invalid_node_ids() {
+        let props = properties! {
+            "node.id" => "not-a-number",
+            "port.name" => "playback_FL",
+            "port.direction" => "in",
+            "port.alias" => "alias:playback_FL",
+            "port.group" => "stream.0",
+            "object.serial" => "also-not-a-number",
+            "object.path" => "test:playback_FL",
+        };
+        let port = Port::new(&global(10, ObjectType::Port, &props));
+        assert_eq!(port.node_id, None);
+        assert_eq!(port.object_serial, None);
+
+        let mut objects = PipeWireObjects {
+            _ports_to_be_added: vec![port],
+            ..with_nodes([
+                Node::from_parts(1, "sink"),
+                Node::from_parts(u32::MAX, "sentinel"),
+            ])
+        };
+        objects.update_nodes();
+        assert!(objects
+            .nodes
+            .iter()
+            .all(|node| node.ports().is_empty()));
+        assert_eq!(
+            objects
+                ._ports_to_be_added
+                .iter()
+                .map(|port| port.id)
+                .collect::<Vec<_>>(),
+            vec![10]
+        );
+    }
+
+    #[test]
+    fn update_nodes_attaches_ports_in_discovery_order() {
+        let mut objects = PipeWireObjects {
+            _ports_to_be_added: ["FL", "FR", "RL"]
+                .iter()
+                .zip(10..)
+                .map(|(channel, id)| {
+                    Port::from_parts(
+                        id,
+                        1,
+                        &format!("playback_{channel}"),
+                        PortDirection::In,
+                        channel,
+                    )
+                })
+                .collect(),
+            ..with_nodes([Node::from_parts(1, "sink")])
+        };
+        objects.update_nodes();
+        let ids: Vec<u32> =
+            objects.nodes[0].ports().iter().map(|p| p.id).collect();
+        assert_eq!(ids, vec![10, 11, 12]);
+        assert_eq!(objects.node_id_of_port(11), Some(1));
+    }
+
+    #[test]
+    fn orphan_ports_wait_for_their_node() {
+        let orphan = Port::from_parts(
+            20,
+            2,
+            "capture_MONO",
+            PortDirection::Out,
+            "MONO",
+        );
+        let mut objects = PipeWireObjects {
+            _ports_to_be_added: vec![orphan],
+            ..with_nodes([Node::from_parts(1, "sink")])
+        };
+        objects.update_nodes();
+        assert!(objects.nodes[0].ports().is_empty());
+        assert_eq!(objects._ports_to_be_added.len(), 1);
+
+        objects.insert_node(Node::from_parts(2, "microphone"));
+        objects.update_nodes();
+        assert!(objects._ports_to_be_added.is_empty());
+        assert_eq!(objects.node_id_of_port(20), Some(2));
+    }
+
+    #[test]
+    fn remove_link_reports_the_unlinked_nodes() {
+        let mut objects = PipeWireObjects {
+            links: vec![
+                Link::from_parts(30, (1, 10), (2, 21)),
+                Link::from_parts(31, (1, 12), (2, 23)),
+            ],
+            ..with_nodes([
+                stereo_node(1, "source", 10),
+                stereo_node(2, "sink", 20),
+            ])
+        };
+        let (sender, receiver) = mpsc::channel();
+        let sender = Arc::new(RwLock::new(sender));
+
+        assert_eq!(
+            block_on(objects.remove_link(30, None, sender.clone())),
+            Ok(Some((1, 2)))
+        );
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![
+                ConnectorEvent::LinkRemoved(30),
+                ConnectorEvent::UnlinkUpdate(1, 2)
+            ]
+        );
+        assert_eq!(objects.find_link_ids_between(1, 2), vec![31]);
+        assert!(block_on(objects.remove_link(
+            30,
+            None,
+            sender.clone()
+        ))
+        .is_err());
+
+        // The sink went away first, the link is only forgotten
+        objects.remove_node(2);
+        assert_eq!(
+            block_on(objects.remove_link(31, None, sender)),
+            Ok(None)
+        );
+        assert!(objects.links.is_empty());
+    }
+
+    #[test]
+    fn find_port_by_name_rejects_unknown_names() {
+        let objects = with_nodes([stereo_node(1, "source", 10)]);
+
+        assert_eq!(
+            objects.find_port_by_name(1, "capture_AUX0").unwrap_err(),
+            LinkError::PortNotFound(1, "capture_AUX0".to_owned())
+        );
+        assert_eq!(
+            objects.find_port_by_name(5, "monitor_FL").unwrap_err(),
+            LinkError::NodeNotFound(5)
+        );
+    }
+
+    #[test]
+    fn find_link_ids_between_without_links_is_empty() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ]);
+        assert!(objects.find_link_ids_between(1, 2).is_empty());
+
+        objects.links.push(Link::from_parts(30, (1, 10), (2, 21)));
+        objects.links.push(Link::from_parts(31, (1, 12), (2, 23)));
+        assert_eq!(objects.find_link_ids_between(1, 2), vec![30, 31]);
+        assert!(objects.find_link_ids_between(2, 1).is_empty());
+    }
+
+    #[test]
+    fn is_linked_follows_the_link_direction() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+            stereo_node(3, "recorder", 30),
+        ]);
+        objects.links.push(Link::from_parts(40, (1, 10), (2, 21)));
+
+        assert!(objects.is_linked(1, 2));
+        assert!(!objects.is_linked(2, 1));
+        assert!(!objects.is_linked(1, 3));
+        assert!(!objects.is_linked(4, 5));
+    }
+
+    #[test]
+    fn port_links_lists_the_links_of_one_port() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+            stereo_node(3, "recorder", 30),
+        ]);
+        objects.links.push(Link::from_parts(40, (1, 10), (2, 21)));
+        objects.links.push(Link::from_parts(41, (1, 10), (3, 31)));
+        // Never announced by the server
+        let mut expired = Link::pending((1, 12), (2, 23));
+        expired.pending_since =
+            Instant::now().checked_sub(PENDING_LINK_TIMEOUT);
+        objects.links.push(expired);
+
+        let ids = |port_id| {
+            objects
+                .port_links(port_id)
+                .iter()
+                .map(|link| link.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(10), vec![40, 41]);
+        assert_eq!(
+            objects.port_links(21),
+            vec![LinkInfo {
+                id: 40,
+                output_node: 1,
+                output_port: 10,
+                input_node: 2,
+                input_port: 21,
+                state: LinkState::Init,
+            }]
+        );
+        assert!(objects.is_port_linked(10));
+        assert!(objects.is_port_linked(31));
+        // The FL input of the source is not linked
+        assert!(ids(11).is_empty());
+        assert!(!objects.is_port_linked(11));
+        assert!(ids(12).is_empty());
+        assert!(!objects.is_port_linked(23));
+    }
+
+    #[test]
+    fn link_count_between_counts_channel_links() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+            stereo_node(3, "recorder", 30),
+        ]);
+        objects.links.push(Link::from_parts(40, (1, 10), (2, 21)));
+        objects.links.push(Link::from_parts(41, (1, 12), (2, 23)));
+        objects.links.push(Link::from_parts(42, (1, 10), (3, 31)));
+
+        assert_eq!(objects.link_count_between(1, 2), 2);
+        assert_eq!(objects.link_count_between(1, 3), 1);
+        assert_eq!(objects.link_count_between(2, 1), 0);
+        assert_eq!(objects.link_count_between(2, 3), 0);
+    }
+
+    #[test]
+    fn created_links_are_pending_until_announced() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+            stereo_node(3, "recorder", 30),
+        ]);
+        let version = objects.graph_version();
+        objects.insert_pending_link(Link::pending((1, 10), (2, 21)));
+        objects.insert_pending_link(Link::pending((1, 12), (2, 23)));
+        objects.insert_pending_link(Link::pending((1, 10), (3, 31)));
+
+        // Visible right away, but without an id to act on
+        assert!(objects.is_linked(1, 2));
+        assert_eq!(objects.link_count_between(1, 2), 2);
+        assert!(objects.find_link_ids_between(1, 2).is_empty());
+        assert_eq!(objects.changes_since(version), Ok(vec![]));
+
+        assert!(objects.insert_link(Link::from_parts(
+            40,
+            (1, 10),
+            (2, 21)
+        )));
+        assert!(!objects.insert_link(Link::from_parts(
+            41,
+            (2, 20),
+            (1, 11)
+        )));
+        assert_eq!(objects.link_count_between(1, 2), 2);
+        assert_eq!(objects.find_link_ids_between(1, 2), vec![40]);
+        assert_eq!(
+            objects.changes_since(version),
+            Ok(vec![
+                GraphChange::LinkAdded(40),
+                GraphChange::LinkAdded(41)
+            ])
+        );
+
+        objects.remove_node(3);
+        assert_eq!(
+            objects
+                .links
+                .iter()
+                .map(|link| link.id())
+                .collect::<Vec<_>>(),
+            vec![PENDING_LINK_ID, 40, 41]
+        );
+    }
+
+    #[test]
+    fn node_adjacency_collapses_channel_links() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "filter", 20),
+            stereo_node(3, "sink", 30),
+        ]);
+        assert!(objects.node_adjacency().is_empty());
+
+        objects.links.push(Link::from_parts(41, (2, 20), (3, 31)));
+        objects.links.push(Link::from_parts(40, (1, 10), (2, 21)));
+        objects.links.push(Link::from_parts(42, (1, 12), (2, 23)));
+        assert_eq!(objects.node_adjacency(), vec![(1, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn path_between_follows_links_through_virtual_nodes() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "virtual_sink", 20),
+            stereo_node(3, "filter", 30),
+            stereo_node(4, "speakers", 40),
+            stereo_node(5, "microphone", 50),
+        ]);
+        objects.links.push(Link::from_parts(60, (1, 10), (2, 21)));
+        objects.links.push(Link::from_parts(61, (2, 20), (3, 31)));
+        objects.links.push(Link::from_parts(62, (3, 30), (4, 41)));
+
+        assert_eq!(
+            objects.path_between(1, 4),
+            Some(vec![1, 2, 3, 4])
+        );
+        assert_eq!(objects.path_between(2, 4), Some(vec![2, 3, 4]));
+        assert_eq!(objects.path_between(1, 1), Some(vec![1]));
+        assert_eq!(objects.path_between(4, 1), None);
+        assert_eq!(objects.path_between(5, 4), None);
+    }
+
+    #[test]
+    fn counts_by_kind_tallies_mixed_graphs() {
+        let mut objects = PipeWireObjects::default();
+        assert!(objects.counts_by_kind().is_empty());
+
+        for (id, media_class) in [
+            (1, Some("Audio/Sink")),
+            (2, Some("Audio/Sink")),
+            (3, Some("Audio/Source")),
+            (4, Some("Stream/Output/Audio")),
+            (5, Some("Stream/Output/Audio")),
+            (6, Some("Stream/Output/Audio")),
+            (7, Some("Video/Source")),
+            (8, None),
+        ] {
+            let mut node = Node::from_parts(id, "node");
+            node.media_class = media_class.map(str::to_owned);
+            objects.insert_node(node);
+        }
+
+        assert_eq!(
+            objects.counts_by_kind(),
+            HashMap::from([
+                (NodeKind::Sink, 2),
+                (NodeKind::Source, 1),
+                (NodeKind::StreamOutput, 3),
+                (NodeKind::VideoSource, 1),
+                (NodeKind::Other, 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn clear_empties_the_graph() {
+        let mut objects = PipeWireObjects {
+            links: vec![Link::from_parts(30, (1, 10), (2, 21))],
+            ..with_nodes([
+                stereo_node(1, "source", 10),
+                stereo_node(2, "sink", 20),
+            ])
+        };
+        objects._ports_to_be_added.push(Port::from_parts(
+            40,
+            3,
+            "FL",
+            PortDirection::Out,
+            "FL",
+        ));
+        objects.update_nodes();
+        objects.record_route(1, 2, LinkStrategy::default());
+
+        objects.clear();
+        assert!(objects.nodes.is_empty());
+        assert!(objects.links.is_empty());
+        assert!(objects._ports_to_be_added.is_empty());
+        assert!(objects.find_node_by_id(1).is_none());
+        assert_eq!(objects.routes.len(), 1);
+
+        // Still usable once cleared
+        objects.insert_node(stereo_node(1, "source", 10));
+        objects.update_nodes();
+        assert!(objects.find_node_by_id(1).is_some());
+    }
+
+    #[test]
+    fn routes_are_restored_after_reconnecting() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+            stereo_node(3, "recorder", 30),
+        ]);
+        objects.record_route(1, 2, LinkStrategy::FrontOnly);
+        objects.record_route(1, 3, LinkStrategy::default());
+        objects.forget_route(1, 3);
+
+        // The daemon restarts, nodes come back with new ids
+        objects.clear_graph();
+        assert!(objects.nodes.is_empty());
+        assert!(objects.routes_to_restore().is_empty());
+        objects.extend([
+            stereo_node(4, "sink", 40),
+            stereo_node(5, "source", 50),
+            stereo_node(6, "recorder", 60),
+        ]);
+
+        assert_eq!(
+            objects.routes_to_restore(),
+            vec![(5, 4, LinkStrategy::FrontOnly)]
+        );
+        objects.links.push(Link::from_parts(70, (5, 50), (4, 41)));
+        assert!(objects.routes_to_restore().is_empty());
+    }
+
+    #[test]
+    fn port_routes_are_restored_port_by_port() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ]);
+        objects.record_port_route(10, 23);
+        objects.record_port_route(12, 21);
+        objects.record_port_route(10, 23);
+        assert!(objects.is_route(1, 2));
+        assert_eq!(objects.routes.len(), 1);
+
+        objects.clear_graph();
+        objects.extend([
+            stereo_node(3, "sink", 30),
+            stereo_node(4, "source", 40),
+        ]);
+        assert!(objects.routes_to_restore().is_empty());
+        assert_eq!(
+            objects.port_routes_to_restore(),
+            vec![((4, 40), (3, 33)), ((4, 42), (3, 31))]
+        );
+        objects.links.push(Link::from_parts(50, (4, 40), (3, 33)));
+        assert_eq!(
+            objects.port_routes_to_restore(),
+            vec![((4, 42), (3, 31))]
+        );
+
+        // Linking the nodes as a whole supersedes the ports
+        objects.record_route(4, 3, LinkStrategy::default());
+        assert!(objects.port_routes_to_restore().is_empty());
+    }
+
+    #[test]
+    fn routes_tell_apart_nodes_of_the_same_name() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "headset", 20),
+            stereo_node(3, "headset", 30),
+        ]);
+        objects.nodes[1].object_path = Some("alsa:pcm:1".to_owned());
+        objects.nodes[2].object_path = Some("alsa:pcm:2".to_owned());
+        objects.record_route(1, 2, LinkStrategy::default());
+
+        assert!(objects.is_route(1, 2));
+        assert!(!objects.is_route(1, 3));
+        objects.forget_route(1, 3);
+        assert!(objects.is_route(1, 2));
+
+        objects.clear_graph();
+        let mut second = stereo_node(4, "headset", 40);
+        second.object_path = Some("alsa:pcm:2".to_owned());
+        let mut first = stereo_node(5, "headset", 50);
+        first.object_path = Some("alsa:pcm:1".to_owned());
+        objects.extend([stereo_node(6, "source", 60), second, first]);
+
+        assert_eq!(
+            objects.routes_to_restore(),
+            vec![(6, 5, LinkStrategy::default())]
+        );
+    }
+
+    #[test]
+    fn can_link_accepts_valid_pairs() {
+        let objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+        ]);
+        assert_eq!(objects.can_link(1, 2), Ok(()));
+        assert_eq!(objects.can_link(2, 1), Ok(()));
+    }
+
+    #[test]
+    fn can_link_rejects_missing_and_same_nodes() {
+        let objects = with_nodes([stereo_node(1, "source", 10)]);
+        assert_eq!(
+            objects.can_link(1, 1),
+            Err(LinkError::SameNode(1))
+        );
+        assert_eq!(
+            objects.can_link(1, 2),
+            Err(LinkError::NodeNotFound(2))
+        );
+        assert_eq!(
+            objects.can_link(3, 1),
+            Err(LinkError::NodeNotFound(3))
+        );
+    }
+
+    #[test]
+    fn can_link_rejects_wrong_directions() {
+        let mut playback = Node::from_parts(1, "playback");
+        playback.add_port(Port::from_parts(
+            10,
+            1,
+            "output_FL",
+            PortDirection::Out,
+            "FL",
+        ));
+        let mut capture = Node::from_parts(2, "capture");
+        capture.add_port(Port::from_parts(
+            20,
+            2,
+            "input_FL",
+            PortDirection::In,
+            "FL",
+        ));
+        let mut objects = with_nodes([playback, capture]);
+
+        assert_eq!(
+            objects.can_link(2, 1),
+            Err(LinkError::Node(
+                NodeError::IncorrectTypeOfChannelDirection(
+                    "capture".to_owned(),
+                    PortDirection::Out
+                )
+            ))
+        );
+        objects.remove_port(20);
+        objects.add_port(Port::from_parts(
+            21,
+            2,
+            "output_FL",
+            PortDirection::Out,
+            "FL",
+        ));
+        assert_eq!(
+            objects.can_link(1, 2),
+            Err(LinkError::Node(
+                NodeError::IncorrectTypeOfChannelDirection(
+                    "capture".to_owned(),
+                    PortDirection::In
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn can_link_rejects_incompatible_formats() {
+        let mut midi = Node::from_parts(1, "midi");
+        midi.add_port(Port::from_parts_with_format(
+            10,
+            1,
+            "midi_out",
+            PortDirection::Out,
+            "MONO",
+            "8 bit raw midi",
+        ));
+        let objects = with_nodes([midi, stereo_node(2, "sink", 20)]);
+        assert_eq!(
+            objects.can_link(1, 2),
+            Err(LinkError::Node(NodeError::IncompatibleFormats(
+                "midi".to_owned(),
+                "sink".to_owned()
+            )))
+        );
+    }
+
+    #[test]
+    fn can_link_follows_the_channel_equalization() {
+        let mut recorder = Node::from_parts(2, "recorder");
+        recorder.add_port(Port::from_parts(
+            20,
+            2,
+            "input_MONO",
+            PortDirection::In,
+            "MONO",
+        ));
+        let mut objects =
+            with_nodes([stereo_node(1, "source", 10), recorder]);
+        assert_eq!(objects.can_link(1, 2), Ok(()));
+        assert_eq!(objects.valid_targets_for(1), vec![2]);
+
+        objects.channel_equalization =
+            ChannelEqualization::DuplicateOnly;
+        assert_eq!(
+            objects.can_link(1, 2),
+            Err(LinkError::Node(NodeError::ChannelMismatch(
+                "source".to_owned(),
+                "recorder".to_owned()
+            )))
+        );
+        assert!(objects.valid_targets_for(1).is_empty());
+    }
+
+    #[test]
+    fn can_link_rejects_existing_links_and_cycles() {
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "filter", 20),
+            stereo_node(3, "sink", 30),
+        ]);
+        objects.links.push(Link::from_parts(40, (1, 10), (2, 21)));
+        objects.links.push(Link::from_parts(41, (2, 20), (3, 31)));
+
+        assert_eq!(
+            objects.can_link(1, 2),
+            Err(LinkError::AlreadyLinked(1, 2))
+        );
+        assert_eq!(
+            objects.can_link(3, 1),
+            Err(LinkError::WouldCreateCycle(3, 1))
+        );
+        assert_eq!(objects.can_link(1, 3), Ok(()));
+    }
+
+    #[test]
+    fn valid_targets_match_can_link() {
+        let mut midi = Node::from_parts(4, "midi");
+        midi.add_port(Port::from_parts_with_format(
+            40,
+            4,
+            "midi_in",
+            PortDirection::In,
+            "MONO",
+            "8 bit raw midi",
+        ));
+        let mut objects = with_nodes([
+            stereo_node(1, "source", 10),
+            stereo_node(2, "sink", 20),
+            stereo_node(3, "linked", 30),
+            midi,
+            stereo_node(5, "feedback", 50),
+            stereo_node(6, "feedback source", 60),
+            stereo_node(7, "other sink", 70),
+        ]);
+        objects.links.push(Link::from_parts(80, (1, 10), (3, 31)));
+        objects.links.push(Link::from_parts(81, (5, 50), (1, 11)));
+        objects.links.push(Link::from_parts(82, (6, 60), (5, 51)));
+
+        let targets = objects.valid_targets_for(1);
+        assert_eq!(targets, vec![2, 7]);
+        for candidate in [1, 2, 3, 4, 5, 6, 7] {
+            assert_eq!(
+                objects.can_link(1, candidate).is_ok(),
+                targets.contains(&candidate),
+                "node {candidate}"
+            );
+        }
+        assert!(objects.valid_targets_for(99).is_empty());
+    }
+
+    #[test]
+    fn mutations_are_recorded_as_changes() {
+        let mut objects = PipeWireObjects::default();
+        objects.insert_node(stereo_node(1, "source", 10));
+        let synced = objects.graph_version();
+        objects.insert_node(stereo_node(2, "sink", 20));
+        objects.insert_link(Link::from_parts(30, (1, 10), (2, 21)));
+        let (sender, _receiver) = mpsc::channel();
+        block_on(objects.remove_link(
+            30,
+            None,
+            Arc::new(RwLock::new(sender)),
+        ))
+        .unwrap();
+        objects.remove_node(1);
+        objects.remove_node(99);
+
+        assert_eq!(
+            objects.changes_since(synced),
+            Ok(vec![
+                GraphChange::NodeAdded(2),
+                GraphChange::LinkAdded(30),
+                GraphChange::LinkRemoved(30),
+                GraphChange::NodeRemoved(1),
+            ])
+        );
+        objects.clear();
+        assert_eq!(
+            objects.changes_since(synced),
+            Err(DiffError::TooOld)
+        );
+    }
+
+    #[test]
+    fn sorted_nodes_stay_sorted_through_mutations() {
+        let mut objects = PipeWireObjects::default();
+        objects.insert_node(stereo_node(5, "first", 50));
+        objects.keep_nodes_sorted(true);
+        for id in [3, 9, 1, 7] {
+            objects.insert_node(stereo_node(id, "node", id * 10));
+        }
+        objects.remove_node(7);
+
+        let ids: Vec<u32> =
+            objects.nodes.iter().map(|node| node.id).collect();
+        assert_eq!(ids, vec![1, 3, 5, 9]);
+        for id in ids {
+            assert_eq!(
+                objects.find_node_by_exact_id(id).map(|n| n.id),
+                Some(id)
+            );
+        }
+        assert!(objects.find_node_by_exact_id(7).is_none());
+        // Port ids still resolve to their node.
+        assert_eq!(
+            objects.find_node_by_id(31).map(|n| n.id),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn validate_accepts_consistent_objects() {
+        let objects = PipeWireObjects {
+            links: vec![Link::from_parts(30, (1, 10), (2, 21))],
+            ..with_nodes([
+                stereo_node(1, "source", 10),
+                stereo_node(2, "sink", 20),
+            ])
+        };
+        assert_eq!(objects.validate(), Vec::new());
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let mut objects = PipeWireObjects {
+            links: vec![
+                // Output and input swapped.
+                Link::from_parts(30, (2, 21), (1, 10)),
+                Link::from_parts(31, (1, 10), (5, 50)),
+            ],
+            ..with_nodes([
+                stereo_node(1, "source", 10),
+                stereo_node(2, "sink", 20),
+                stereo_node(2, "sink copy", 40),
+            ])
+        };
+        let mut moved = objects.nodes[0].remove_port(11).unwrap();
+        moved.node_id = Some(2);
+        objects.nodes[0].add_port(moved);
+        objects.nodes[1].add_port(Port::from_parts(
+            11,
+            2,
+            "extra",
+            PortDirection::In,
+            "FL",
+        ));
+
+        assert_eq!(
+            objects.validate(),
+            vec![
+                Inconsistency::PortOfOtherNode {
+                    port_id: 11,
+                    node_id: 1,
+                    port_node_id: Some(2),
+                },
+                Inconsistency::PortInTwoNodes {
+                    port_id: 11,
+                    first_node: 1,
+                    second_node: 2,
+                },
+                Inconsistency::DuplicateNode(2),
+                Inconsistency::LinkToInvalidPort {
+                    link_id: 30,
+                    node_id: 2,
+                    port_id: 21,
+                },
+                Inconsistency::LinkToInvalidPort {
+                    link_id: 30,
+                    node_id: 1,
+                    port_id: 10,
+                },
+                Inconsistency::LinkToMissingNode {
+                    link_id: 31,
+                    node_id: 5,
+                },
+            ]
+        );
     }
 }
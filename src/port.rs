@@ -1,11 +1,16 @@
-use std::{rc::Rc, sync::RwLock};
+use std::rc::Rc;
 
-use super::utils::{val, val_or, UNKNOWN_STR};
+use super::link::LinkOptions;
+use super::retry::{self, RetryPolicy};
+use super::sync::RwLock;
+use super::utils::{discovery_debug, val, val_opt, UNKNOWN_STR};
+use libspa::sys;
 use libspa::utils::dict::DictRef;
+use pipewire::properties::Properties;
 use pipewire::registry::GlobalObject;
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, PartialEq)]
 pub enum PortError {
     #[error(
         "Port {0} could not be linked into Port {1}. Reason: {2}"
@@ -13,7 +18,71 @@ pub enum PortError {
     LinkError(String, String, String),
 }
 
+/// Format of a video port, as far as the port advertises it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VideoFormat {
+    /// Pixel format from `video.format`, e.g. "RGBA".
+    pub format: Option<String>,
+    /// Width and height from `video.size`, e.g. "1920x1080".
+    pub size: Option<(u32, u32)>,
+    /// Frames per second as a fraction from `video.framerate`, e.g.
+    /// "30/1".
+    pub framerate: Option<(u32, u32)>,
+}
+
+/// The kind of data a port carries.
 #[derive(Debug, Clone, PartialEq)]
+pub enum PortFormat {
+    Audio,
+    Midi,
+    Video(VideoFormat),
+    /// The port does not say what it carries.
+    Unknown,
+}
+
+impl PortFormat {
+    /// Read from the `format.dsp` of the port (e.g. "32 bit float
+    /// RGBA video"), or from its video properties when it has none.
+    fn from_props(props: &DictRef) -> Self {
+        let format_dsp = props.get("format.dsp").unwrap_or_default();
+        let has_video_props = ["video.format", "video.size"]
+            .iter()
+            .any(|key| props.get(key).is_some());
+        if format_dsp.ends_with("audio") {
+            PortFormat::Audio
+        } else if format_dsp.ends_with("midi") {
+            PortFormat::Midi
+        } else if format_dsp.ends_with("video") || has_video_props {
+            PortFormat::Video(VideoFormat {
+                format: val_opt(props, "video.format"),
+                size: props
+                    .get("video.size")
+                    .and_then(|size| parse_pair(size, 'x')),
+                framerate: props
+                    .get("video.framerate")
+                    .and_then(|rate| parse_pair(rate, '/')),
+            })
+        } else {
+            PortFormat::Unknown
+        }
+    }
+
+    pub fn is_video(&self) -> bool {
+        matches!(self, PortFormat::Video(_))
+    }
+}
+
+/// Parse two numbers separated by `separator`, e.g. "1920x1080".
+fn parse_pair(value: &str, separator: char) -> Option<(u32, u32)> {
+    let (first, second) = value.split_once(separator)?;
+    Some((first.trim().parse().ok()?, second.trim().parse().ok()?))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum AudioChannel {
     MONO,
@@ -56,14 +125,106 @@ impl AudioChannel {
             "TFR" => AudioChannel::TFR,
             UNKNOWN_STR => AudioChannel::Unknown,
             _ => {
-                log::warn!("An audio channel of type {s} has been found. That was totally not supposed to happen");
+                log_warn!("An audio channel of type {s} has been found. That was totally not supposed to happen");
                 AudioChannel::Unknown
             }
         }
     }
+
+    /// The channel of a SPA channel position (`spa_audio_channel`),
+    /// as found in the position arrays of audio formats.
+    pub fn from_position(position: u32) -> Self {
+        match position {
+            sys::SPA_AUDIO_CHANNEL_MONO => AudioChannel::MONO,
+            sys::SPA_AUDIO_CHANNEL_FL => AudioChannel::FL,
+            sys::SPA_AUDIO_CHANNEL_FR => AudioChannel::FR,
+            sys::SPA_AUDIO_CHANNEL_FC => AudioChannel::FC,
+            sys::SPA_AUDIO_CHANNEL_LFE => AudioChannel::LFE,
+            sys::SPA_AUDIO_CHANNEL_SL => AudioChannel::SL,
+            sys::SPA_AUDIO_CHANNEL_SR => AudioChannel::SR,
+            sys::SPA_AUDIO_CHANNEL_RL => AudioChannel::RL,
+            sys::SPA_AUDIO_CHANNEL_RR => AudioChannel::RR,
+            sys::SPA_AUDIO_CHANNEL_TFL => AudioChannel::TFL,
+            sys::SPA_AUDIO_CHANNEL_TFR => AudioChannel::TFR,
+            _ => AudioChannel::Unknown,
+        }
+    }
+
+    /// The channels of a node's `audio.position`, in order, e.g.
+    /// "[ FL, FR ]". Positions may also be SPA channel positions.
+    pub(crate) fn from_position_map(positions: &str) -> Vec<Self> {
+        positions
+            .split(|c: char| {
+                c == ',' || c == '[' || c == ']' || c.is_whitespace()
+            })
+            .filter(|position| !position.is_empty())
+            .map(|position| match position.parse() {
+                Ok(position) => Self::from_position(position),
+                Err(_) => Self::from_str(position),
+            })
+            .collect()
+    }
+
+    /// The channel of a port from its `audio.channel`. Ports without
+    /// one get theirs from their node, see [`Port::index`].
+    fn from_props(props: &DictRef) -> Self {
+        props
+            .get("audio.channel")
+            .map_or(AudioChannel::Unknown, Self::from_str)
+    }
+
+    /// The name PipeWire gives the channel, e.g. in `audio.position`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AudioChannel::MONO => "MONO",
+            AudioChannel::FL => "FL",
+            AudioChannel::FR => "FR",
+            AudioChannel::FC => "FC",
+            AudioChannel::LFE => "LFE",
+            AudioChannel::SL => "SL",
+            AudioChannel::SR => "SR",
+            AudioChannel::RL => "RL",
+            AudioChannel::RR => "RR",
+            AudioChannel::TFL => "TFL",
+            AudioChannel::TFR => "TFR",
+            AudioChannel::Unknown => UNKNOWN_STR,
+        }
+    }
+
+    /// Front Left, Front Right and Front Center
+    pub fn is_front(&self) -> bool {
+        matches!(
+            self,
+            AudioChannel::FL | AudioChannel::FR | AudioChannel::FC
+        )
+    }
+
+    /// Side and Rear channels
+    pub fn is_surround(&self) -> bool {
+        matches!(
+            self,
+            AudioChannel::SL
+                | AudioChannel::SR
+                | AudioChannel::RL
+                | AudioChannel::RR
+        )
+    }
+
+    pub fn is_lfe(&self) -> bool {
+        *self == AudioChannel::LFE
+    }
+
+    /// Top (Atmos) channels
+    pub fn is_height(&self) -> bool {
+        matches!(self, AudioChannel::TFL | AudioChannel::TFR)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub enum PortDirection {
     In,
     Out,
@@ -80,6 +241,10 @@ impl PortDirection {
     }
 }
 
+/// An owned summary of a [`Port`], detached from the objects lock,
+/// the same as the ports of a [`crate::snapshot::GraphSnapshot`].
+pub type PortInfo = crate::snapshot::PortSnapshot;
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct Port {
@@ -88,22 +253,31 @@ pub struct Port {
     pub direction: PortDirection,
     pub alias: String,
     pub group: String,
-    pub object_serial: u32,
+    /// `None` if the `object.serial` property is not a number.
+    pub object_serial: Option<u32>,
     pub object_path: String,
-    // pub format_dsp: String,
-    /// The node this port belongs to
-    pub node_id: u32,
+    /// e.g. "32 bit float mono audio" or "8 bit raw midi"
+    pub format_dsp: Option<String>,
+    pub format: PortFormat,
+    /// The node this port belongs to, `None` if the `node.id`
+    /// property is not a number.
+    pub node_id: Option<u32>,
     pub audio_channel: AudioChannel,
+    /// Position of the port among the ports of its node going the
+    /// same way, from its `port.id`. A port without an
+    /// `audio.channel` gets the channel at this position of the
+    /// `audio.position` of its node.
+    pub index: Option<u32>,
+    /// Whether the port is a port of the hardware, e.g. of a sound
+    /// card, rather than of an application or a virtual device.
+    pub physical: bool,
     // // Optional fields (only present in some entries)
     // pub port_monitor: Option<String>,
-    // pub port_physical: Option<String>,
     // pub port_terminal: Option<String>,
 }
 impl Port {
     pub fn new(port_dict: &GlobalObject<&DictRef>) -> Self {
         let props = port_dict.props.unwrap();
-        let audio_channel =
-            val_or(props, "audio.channel", UNKNOWN_STR);
         let port = Port {
             id: port_dict.id,
             name: val(props, "port.name"),
@@ -113,16 +287,18 @@ impl Port {
             )),
             alias: val(props, "port.alias"),
             group: val(props, "port.group"),
-            object_serial: val(props, "object.serial")
-                .parse()
-                .unwrap_or(u32::MAX),
+            object_serial: val(props, "object.serial").parse().ok(),
             object_path: val(props, "object.path"),
-            node_id: val(props, "node.id")
-                .parse()
-                .unwrap_or(u32::MAX),
-            audio_channel: AudioChannel::from_str(&audio_channel),
+            format_dsp: val_opt(props, "format.dsp"),
+            format: PortFormat::from_props(props),
+            node_id: val(props, "node.id").parse().ok(),
+            audio_channel: AudioChannel::from_props(props),
+            index: props
+                .get("port.id")
+                .and_then(|id| id.parse().ok()),
+            physical: props.get("port.physical") == Some("true"),
         };
-        log::debug!(
+        discovery_debug!(
             "Creating new Port from global object: {:?}({:?} | N_ID: {:?})",
             port.name,
             port.id,
@@ -131,11 +307,91 @@ impl Port {
         port
     }
 
+    /// Whether both ports carry the same kind of data.
+    /// Ports that do not advertise their format are assumed to be compatible.
+    /// Video ports link with any other video port, PipeWire
+    /// negotiating a pixel format and size both support.
+    pub fn is_format_compatible(&self, other: &Self) -> bool {
+        match (&self.format, &other.format) {
+            (PortFormat::Video(_), PortFormat::Video(_))
+            | (PortFormat::Video(_), PortFormat::Unknown)
+            | (PortFormat::Unknown, PortFormat::Video(_)) => {
+                return true
+            }
+            (PortFormat::Video(_), _) | (_, PortFormat::Video(_)) => {
+                return false
+            }
+            _ => {}
+        }
+        match (&self.format_dsp, &other.format_dsp) {
+            (Some(format), Some(other_format)) => {
+                format == other_format
+            }
+            _ => true,
+        }
+    }
+
     /// Connect the current port into another, assuming that the other port is an input port.
     pub fn link_port(
         &self,
         core: Rc<RwLock<pipewire::core::Core>>,
         target_port: &Self,
+    ) -> Result<(), PortError> {
+        self.link_port_with_retry(
+            core,
+            target_port,
+            &RetryPolicy::default(),
+        )
+    }
+
+    /// Same as [`Self::link_port`], creating the link again as
+    /// described by `retry_policy` when PipeWire fails to do so for
+    /// transient reasons.
+    pub fn link_port_with_retry(
+        &self,
+        core: Rc<RwLock<pipewire::core::Core>>,
+        target_port: &Self,
+        retry_policy: &RetryPolicy,
+    ) -> Result<(), PortError> {
+        self.link_port_with_options(
+            core,
+            target_port,
+            retry_policy,
+            &LinkOptions::default(),
+        )
+    }
+
+    /// Same as [`Self::link_port_with_retry`], making the link as
+    /// described by `options`.
+    pub fn link_port_with_options(
+        &self,
+        core: Rc<RwLock<pipewire::core::Core>>,
+        target_port: &Self,
+        retry_policy: &RetryPolicy,
+        options: &LinkOptions,
+    ) -> Result<(), PortError> {
+        let core = core.read().expect("Failed to lock core");
+        self.link_port_through(
+            target_port,
+            retry_policy,
+            options,
+            |properties| {
+                core.create_object::<pipewire::link::Link>(
+                    "link-factory",
+                    properties,
+                )
+            },
+        )
+    }
+
+    /// Same as [`Self::link_port_with_options`], creating the link
+    /// from its properties with `create`.
+    pub(crate) fn link_port_through<L>(
+        &self,
+        target_port: &Self,
+        retry_policy: &RetryPolicy,
+        options: &LinkOptions,
+        mut create: impl FnMut(&Properties) -> Result<L, pipewire::Error>,
     ) -> Result<(), PortError> {
         if self.direction != PortDirection::Out {
             return Err(PortError::LinkError(
@@ -151,22 +407,38 @@ impl Port {
                 format!("{} is not an input port", self.name),
             ));
         }
-        let core = core.read().expect("Failed to lock core");
-
-        if let Err(e) = core.create_object::<pipewire::link::Link>(
-            "link-factory",
-            &pipewire::properties::properties! {
-                "link.output.node" => self.node_id.to_string(),
-                "link.output.port" => self.id.to_string(),
-                "link.input.node" => target_port.node_id.to_string(),
-                "link.input.port" => target_port.id.to_string(),
-                "object.linger" => "1"
+        let (Some(output_node), Some(input_node)) =
+            (self.node_id, target_port.node_id)
+        else {
+            return Err(PortError::LinkError(
+                self.name.clone(),
+                target_port.name.clone(),
+                "The node of a port is unknown".to_owned(),
+            ));
+        };
+        let properties = options.properties(
+            (output_node, self.id),
+            (input_node, target_port.id),
+        );
+        let result = retry_policy.retry(
+            |e| {
+                let transient = retry::is_transient(e);
+                if transient {
+                    log_warn!("Failed to create link, retrying: {e}");
+                }
+                transient
             },
-        ) {
-            log::warn!("Failed to create link: {e}");
+            || create(&properties),
+        );
+        if let Err(e) = result {
+            return Err(PortError::LinkError(
+                self.name.clone(),
+                target_port.name.clone(),
+                format!("Failed to create link: {e}"),
+            ));
         }
 
-        log::debug!(
+        log_debug!(
             "Port {}({}) linked to port {}({})",
             self.name,
             self.id,
@@ -180,11 +452,92 @@ impl Port {
 
 impl Drop for Port {
     fn drop(&mut self) {
-        log::debug!(
-            "Port {}({} | N_ID: {}) was removed",
+        discovery_debug!(
+            "Port {}({} | N_ID: {:?}) was removed",
             self.name,
             self.id,
             self.node_id
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pipewire::properties::{properties, Properties};
+
+    use super::AudioChannel;
+
+    #[test]
+    fn channels_fall_back_on_spa_positions() {
+        assert_eq!(AudioChannel::from_position(3), AudioChannel::FL);
+        assert_eq!(AudioChannel::from_position(4), AudioChannel::FR);
+        assert_eq!(
+            AudioChannel::from_position(2),
+            AudioChannel::MONO
+        );
+        assert_eq!(
+            AudioChannel::from_position(0),
+            AudioChannel::Unknown
+        );
+
+        assert_eq!(
+            AudioChannel::from_position_map("[ FL, FR ]"),
+            vec![AudioChannel::FL, AudioChannel::FR]
+        );
+        assert_eq!(
+            AudioChannel::from_position_map("FL,FR,LFE"),
+            vec![
+                AudioChannel::FL,
+                AudioChannel::FR,
+                AudioChannel::LFE
+            ]
+        );
+        assert_eq!(
+            AudioChannel::from_position_map("3 4"),
+            vec![AudioChannel::FL, AudioChannel::FR]
+        );
+        assert!(AudioChannel::from_position_map("").is_empty());
+
+        let channel = |props: &Properties| {
+            AudioChannel::from_props(props.dict())
+        };
+        assert_eq!(
+            channel(&properties! { "audio.channel" => "FL" }),
+            AudioChannel::FL
+        );
+        assert_eq!(
+            channel(&Properties::new()),
+            AudioChannel::Unknown
+        );
+    }
+
+    #[test]
+    fn audio_channel_classification() {
+        use AudioChannel::*;
+        let cases = [
+            // (channel, front, surround, lfe, height)
+            (MONO, false, false, false, false),
+            (FL, true, false, false, false),
+            (FR, true, false, false, false),
+            (FC, true, false, false, false),
+            (LFE, false, false, true, false),
+            (SL, false, true, false, false),
+            (SR, false, true, false, false),
+            (RL, false, true, false, false),
+            (RR, false, true, false, false),
+            (TFL, false, false, false, true),
+            (TFR, false, false, false, true),
+            (Unknown, false, false, false, false),
+        ];
+        for (channel, front, surround, lfe, height) in cases {
+            assert_eq!(channel.is_front(), front, "{channel:?}");
+            assert_eq!(
+                channel.is_surround(),
+                surround,
+                "{channel:?}"
+            );
+            assert_eq!(channel.is_lfe(), lfe, "{channel:?}");
+            assert_eq!(channel.is_height(), height, "{channel:?}");
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::io::Cursor;
+
+use libspa::pod::deserialize::PodDeserializer;
+use libspa::pod::serialize::PodSerializer;
+use libspa::pod::{Object, Pod, Property, Value, ValueArray};
+use libspa::sys;
+
+/// The values of a node's `Props` param followed by the crate.
+/// Values missing from the param are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeProps {
+    pub mute: Option<bool>,
+    /// Linear volume of every channel, 1.0 being 100%.
+    pub channel_volumes: Option<Vec<f32>>,
+}
+
+impl NodeProps {
+    pub(crate) fn parse(pod: &Pod) -> Option<Self> {
+        let (_, value) =
+            PodDeserializer::deserialize_any_from(pod.as_bytes())
+                .ok()?;
+        Self::from_value(&value)
+    }
+
+    pub(crate) fn from_value(value: &Value) -> Option<Self> {
+        let Value::Object(object) = value else {
+            return None;
+        };
+        if object.type_ != sys::SPA_TYPE_OBJECT_Props {
+            return None;
+        }
+
+        let mut props = Self::default();
+        for property in object.properties.iter() {
+            match (property.key, &property.value) {
+                (sys::SPA_PROP_mute, Value::Bool(mute)) => {
+                    props.mute = Some(*mute);
+                }
+                (
+                    sys::SPA_PROP_channelVolumes,
+                    Value::ValueArray(ValueArray::Float(volumes)),
+                ) => {
+                    props.channel_volumes = Some(volumes.clone());
+                }
+                _ => {}
+            }
+        }
+        Some(props)
+    }
+}
+
+/// A `Props` param muting or unmuting a node.
+pub(crate) fn mute_props(mute: bool) -> Value {
+    props_object(vec![Property::new(
+        sys::SPA_PROP_mute,
+        Value::Bool(mute),
+    )])
+}
+
+/// A `Props` param setting the volume of every channel of a node.
+/// Without any known channel, the node wide volume is set instead.
+pub(crate) fn volume_props(volume: f32, channels: usize) -> Value {
+    let property = if channels == 0 {
+        Property::new(sys::SPA_PROP_volume, Value::Float(volume))
+    } else {
+        Property::new(
+            sys::SPA_PROP_channelVolumes,
+            Value::ValueArray(ValueArray::Float(vec![
+                volume;
+                channels
+            ])),
+        )
+    };
+    props_object(vec![property])
+}
+
+/// A `Props` param overriding the `node.description` of a node,
+/// through its free form `params`.
+pub(crate) fn description_props(description: &str) -> Value {
+    props_object(vec![Property::new(
+        sys::SPA_PROP_params,
+        Value::Struct(vec![
+            Value::String("node.description".to_owned()),
+            Value::String(description.to_owned()),
+        ]),
+    )])
+}
+
+fn props_object(properties: Vec<Property>) -> Value {
+    Value::Object(Object {
+        type_: sys::SPA_TYPE_OBJECT_Props,
+        id: sys::SPA_PARAM_Props,
+        properties,
+    })
+}
+
+/// Serialize a param so it can be handed to PipeWire as a [`Pod`].
+pub(crate) fn to_pod_bytes(value: &Value) -> Result<Vec<u8>, String> {
+    PodSerializer::serialize(Cursor::new(Vec::new()), value)
+        .map(|(cursor, _)| cursor.into_inner())
+        .map_err(|e| format!("Failed to serialize param: {e:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use libspa::pod::{Object, Property, Value, ValueArray};
+    use libspa::sys;
+
+    use super::{
+        description_props, mute_props, volume_props, NodeProps,
+    };
+
+    #[test]
+    fn mute_props_round_trip() {
+        for mute in [true, false] {
+            assert_eq!(
+                NodeProps::from_value(&mute_props(mute)),
+                Some(NodeProps {
+                    mute: Some(mute),
+                    ..Default::default()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn volume_props_sets_every_channel() {
+        assert_eq!(
+            NodeProps::from_value(&volume_props(0.5, 2)),
+            Some(NodeProps {
+                mute: None,
+                channel_volumes: Some(vec![0.5, 0.5]),
+            })
+        );
+        let Value::Object(object) = volume_props(0.5, 0) else {
+            panic!("Props must be an object");
+        };
+        assert_eq!(
+            object.properties,
+            vec![Property::new(
+                sys::SPA_PROP_volume,
+                Value::Float(0.5)
+            )]
+        );
+    }
+
+    #[test]
+    fn description_props_are_free_form_params() {
+        let Value::Object(object) = description_props("Stream mix")
+        else {
+            panic!("Props must be an object");
+        };
+        assert_eq!(object.id, sys::SPA_PARAM_Props);
+        assert_eq!(
+            object.properties,
+            vec![Property::new(
+                sys::SPA_PROP_params,
+                Value::Struct(vec![
+                    Value::String("node.description".to_owned()),
+                    Value::String("Stream mix".to_owned()),
+                ])
+            )]
+        );
+    }
+
+    #[test]
+    fn from_value_reads_channel_volumes() {
+        let props = Value::Object(Object {
+            type_: sys::SPA_TYPE_OBJECT_Props,
+            id: sys::SPA_PARAM_Props,
+            properties: vec![
+                Property::new(
+                    sys::SPA_PROP_volume,
+                    Value::Float(1.0),
+                ),
+                Property::new(
+                    sys::SPA_PROP_channelVolumes,
+                    Value::ValueArray(ValueArray::Float(vec![
+                        0.5, 0.25,
+                    ])),
+                ),
+            ],
+        });
+        assert_eq!(
+            NodeProps::from_value(&props),
+            Some(NodeProps {
+                mute: None,
+                channel_volumes: Some(vec![0.5, 0.25]),
+            })
+        );
+    }
+
+    #[test]
+    fn from_value_ignores_other_params() {
+        let route = Value::Object(Object {
+            type_: sys::SPA_TYPE_OBJECT_Props + 1,
+            id: sys::SPA_PARAM_Props,
+            properties: vec![Property::new(
+                sys::SPA_PROP_mute,
+                Value::Bool(true),
+            )],
+        });
+        assert_eq!(NodeProps::from_value(&route), None);
+        assert_eq!(NodeProps::from_value(&Value::Bool(true)), None);
+    }
+}
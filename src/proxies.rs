@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc};
+
+use libspa::utils::dict::DictRef;
+use pipewire::node::Node as NodeProxy;
+use pipewire::registry::{GlobalObject, Registry};
+use pipewire::types::ObjectType;
+
+use crate::event::{report_error, ConnectorEvent};
+use crate::link::BoundLink;
+use crate::metadata::{
+    BoundMetadata, DEFAULT_METADATA_NAME, SETTINGS_METADATA_NAME,
+};
+use crate::meter::BoundMeter;
+use crate::node::BoundNode;
+use crate::objects::PipeWireObjects;
+use crate::sync::RwLock;
+
+/// Objects the backend thread binds to follow their changes or to
+/// control them. They only live on the backend thread.
+#[derive(Default)]
+pub(crate) struct BoundProxies {
+    pub(crate) metadata: Option<BoundMetadata>,
+    /// The `settings` metadata object, not mirrored.
+    pub(crate) settings: Option<BoundMetadata>,
+    links: HashMap<u32, BoundLink>,
+    pub(crate) nodes: HashMap<u32, BoundNode>,
+    /// Level monitors, by monitor id.
+    pub(crate) meters: HashMap<u32, BoundMeter>,
+    /// Nodes created by the manager, destroyed along with it.
+    pub(crate) virtual_nodes: Vec<NodeProxy>,
+}
+
+impl BoundProxies {
+    /// Bind the global if it is one of the objects the backend
+    /// follows.
+    pub(crate) fn bind(
+        &mut self,
+        registry: &Registry,
+        global: &GlobalObject<&DictRef>,
+        objects: &Arc<RwLock<PipeWireObjects>>,
+        sender: &Arc<RwLock<mpsc::Sender<ConnectorEvent>>>,
+    ) {
+        match global.type_ {
+            ObjectType::Metadata
+                if BoundMetadata::is_named(
+                    global,
+                    DEFAULT_METADATA_NAME,
+                ) =>
+            {
+                match BoundMetadata::bind(
+                    registry,
+                    global,
+                    Some(objects.clone()),
+                    sender.clone(),
+                ) {
+                    Ok(bound) => self.metadata = Some(bound),
+                    Err(e) => report_error(
+                        &sender.read().unwrap(),
+                        format!(
+                            "Failed to bind default metadata: {e}"
+                        ),
+                    ),
+                }
+            }
+            ObjectType::Metadata
+                if BoundMetadata::is_named(
+                    global,
+                    SETTINGS_METADATA_NAME,
+                ) =>
+            {
+                match BoundMetadata::bind(
+                    registry,
+                    global,
+                    None,
+                    sender.clone(),
+                ) {
+                    Ok(bound) => self.settings = Some(bound),
+                    Err(e) => report_error(
+                        &sender.read().unwrap(),
+                        format!(
+                            "Failed to bind settings metadata: {e}"
+                        ),
+                    ),
+                }
+            }
+            ObjectType::Link => {
+                match BoundLink::bind(
+                    registry,
+                    global,
+                    objects.clone(),
+                    sender.clone(),
+                ) {
+                    Ok(bound) => {
+                        self.links.insert(global.id, bound);
+                    }
+                    Err(e) => report_error(
+                        &sender.read().unwrap(),
+                        format!(
+                            "Failed to bind link {}: {e}",
+                            global.id
+                        ),
+                    ),
+                }
+            }
+            ObjectType::Node => {
+                match BoundNode::bind(
+                    registry,
+                    global,
+                    objects.clone(),
+                    sender.clone(),
+                ) {
+                    Ok(bound) => {
+                        self.nodes.insert(global.id, bound);
+                    }
+                    Err(e) => report_error(
+                        &sender.read().unwrap(),
+                        format!(
+                            "Failed to bind node {}: {e}",
+                            global.id
+                        ),
+                    ),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Drop the proxy of a removed global, if any.
+    pub(crate) fn remove(&mut self, id: u32) {
+        self.links.remove(&id);
+        self.nodes.remove(&id);
+        for metadata in [&mut self.metadata, &mut self.settings] {
+            if metadata.as_ref().is_some_and(|bound| bound.id == id) {
+                *metadata = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::thread;
+use std::time::Duration;
+
+use libspa::utils::result::SpaResult;
+
+/// How many times, and how fast, an operation failing for transient
+/// reasons (e.g. ports of a new node that were not announced yet) is
+/// attempted again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total amount of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between two attempts.
+    pub max_delay: Duration,
+    /// Factor applied to the delay after every retry. The delay
+    /// jumps to `max_delay` when the product is negative, not a
+    /// number or too large for a [`Duration`].
+    pub multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(20),
+            max_delay: Duration::from_millis(200),
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Delays to wait before each retry, in order.
+    /// There is one less delay than `max_attempts`.
+    pub fn delays(&self) -> impl Iterator<Item = Duration> {
+        let policy = *self;
+        let retries = self.max_attempts.saturating_sub(1) as usize;
+        let first = self.initial_delay.min(self.max_delay);
+        std::iter::successors(Some(first), move |delay| {
+            let next = Duration::try_from_secs_f64(
+                delay.as_secs_f64() * policy.multiplier,
+            );
+            Some(next.map_or(policy.max_delay, |next| {
+                next.min(policy.max_delay)
+            }))
+        })
+        .take(retries)
+    }
+}
+
+impl RetryPolicy {
+    /// Run `operation` until it succeeds, fails for a reason that
+    /// `is_transient` rejects, or runs out of attempts, sleeping
+    /// between attempts. Returns the last error on failure.
+    ///
+    /// The calling thread is blocked while sleeping, which delays the
+    /// PipeWire events when called from the backend thread.
+    pub fn retry<T, E>(
+        &self,
+        is_transient: impl Fn(&E) -> bool,
+        mut operation: impl FnMut() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut delays = self.delays();
+        loop {
+            match operation() {
+                Err(e) if is_transient(&e) => {
+                    let Some(delay) = delays.next() else {
+                        return Err(e);
+                    };
+                    thread::sleep(delay);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Error codes of failures that may go away by trying again.
+const TRANSIENT_ERRNOS: [i32; 4] =
+    [libc::EINTR, libc::EAGAIN, libc::ENOMEM, libc::EBUSY];
+
+/// Whether a PipeWire error may go away by trying again, e.g. while
+/// the server is busy negotiating formats. Errors caused by the
+/// request itself, such as a wrong proxy type, are permanent.
+pub fn is_transient(error: &pipewire::Error) -> bool {
+    match error {
+        pipewire::Error::CreationFailed
+        | pipewire::Error::NoMemory => true,
+        pipewire::Error::WrongProxyType => false,
+        pipewire::Error::SpaError(error) => {
+            TRANSIENT_ERRNOS.iter().any(|errno| {
+                SpaResult::from_c(-errno).into_result().err().as_ref()
+                    == Some(error)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    use libspa::utils::result::SpaResult;
+
+    use super::{is_transient, RetryPolicy};
+
+    fn spa_error(errno: i32) -> pipewire::Error {
+        SpaResult::from_c(-errno).into_result().unwrap_err().into()
+    }
+
+    #[test]
+    fn delays_grow_until_the_maximum() {
+        let delays: Vec<Duration> =
+            RetryPolicy::default().delays().collect();
+        assert_eq!(
+            delays,
+            [20, 40, 80, 160].map(Duration::from_millis)
+        );
+
+        let policy = RetryPolicy {
+            max_attempts: 6,
+            initial_delay: Duration::from_millis(50),
+            max_delay: Duration::from_millis(300),
+            multiplier: 3.0,
+        };
+        let delays: Vec<Duration> = policy.delays().collect();
+        assert_eq!(
+            delays,
+            [50, 150, 300, 300, 300].map(Duration::from_millis)
+        );
+    }
+
+    #[test]
+    fn invalid_multipliers_use_the_maximum_delay() {
+        for multiplier in [-1.0, f64::NAN, f64::INFINITY, f64::MAX] {
+            let policy = RetryPolicy {
+                multiplier,
+                ..Default::default()
+            };
+            let delays: Vec<Duration> = policy.delays().collect();
+            assert_eq!(
+                delays,
+                [20, 200, 200, 200].map(Duration::from_millis)
+            );
+        }
+    }
+
+    #[test]
+    fn no_delays_without_retries() {
+        assert_eq!(RetryPolicy::none().delays().count(), 0);
+        let policy = RetryPolicy {
+            max_attempts: 0,
+            ..Default::default()
+        };
+        assert_eq!(policy.delays().count(), 0);
+    }
+
+    #[test]
+    fn retry_recovers_from_transient_errors() {
+        let attempts = Cell::new(0);
+        let result =
+            RetryPolicy::default().retry(is_transient, || {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() == 1 {
+                    Err(pipewire::Error::CreationFailed)
+                } else {
+                    Ok(attempts.get())
+                }
+            });
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[test]
+    fn retry_gives_up_on_permanent_errors() {
+        let attempts = Cell::new(0);
+        let result: Result<(), _> =
+            RetryPolicy::default().retry(is_transient, || {
+                attempts.set(attempts.get() + 1);
+                Err(spa_error(libc::EPERM))
+            });
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+
+        let attempts = Cell::new(0);
+        let policy = RetryPolicy {
+            initial_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let result: Result<(), _> =
+            policy.retry(is_transient, || {
+                attempts.set(attempts.get() + 1);
+                Err(spa_error(libc::EAGAIN))
+            });
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 5);
+    }
+
+    #[test]
+    fn transient_errors_are_recognized() {
+        assert!(is_transient(&pipewire::Error::NoMemory));
+        assert!(is_transient(&spa_error(libc::EBUSY)));
+        assert!(!is_transient(&pipewire::Error::WrongProxyType));
+        assert!(!is_transient(&spa_error(libc::EACCES)));
+    }
+}
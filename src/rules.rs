@@ -0,0 +1,218 @@
+//! Routing rules linking nodes as soon as they appear, e.g.
+//! "whenever Firefox plays something, link it into my speakers".
+use crate::node::{LinkStrategy, Node};
+use crate::objects::PipeWireObjects;
+
+/// Identifies a rule added to the manager, to remove it later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LinkRuleId(pub(crate) u64);
+
+/// Which nodes a side of a [`LinkRule`] applies to. Unset fields
+/// match every node, and patterns may use `*` as a wildcard, e.g.
+/// `alsa_output.*`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NodeMatch {
+    /// Pattern for the `node.name`.
+    pub name: Option<String>,
+    /// Pattern for the `media.class`, e.g. `Stream/Output/Audio`.
+    pub media_class: Option<String>,
+    /// Pattern for the `media.role`, e.g. `Communication`.
+    pub media_role: Option<String>,
+    /// The node of this id only.
+    pub id: Option<u32>,
+}
+
+impl NodeMatch {
+    pub fn name(pattern: &str) -> Self {
+        Self {
+            name: Some(pattern.to_owned()),
+            ..Self::default()
+        }
+    }
+
+    pub fn media_class(pattern: &str) -> Self {
+        Self {
+            media_class: Some(pattern.to_owned()),
+            ..Self::default()
+        }
+    }
+
+    pub fn media_role(pattern: &str) -> Self {
+        Self {
+            media_role: Some(pattern.to_owned()),
+            ..Self::default()
+        }
+    }
+
+    pub fn id(id: u32) -> Self {
+        Self {
+            id: Some(id),
+            ..Self::default()
+        }
+    }
+
+    pub fn matches(&self, node: &Node) -> bool {
+        let matches = |pattern: &Option<String>,
+                       value: Option<&str>| {
+            pattern.as_deref().is_none_or(|pattern| {
+                value.is_some_and(|value| glob_match(pattern, value))
+            })
+        };
+        self.id.is_none_or(|id| id == node.id)
+            && matches(&self.name, Some(&node.name))
+            && matches(&self.media_class, node.media_class.as_deref())
+            && matches(&self.media_role, node.media_role.as_deref())
+    }
+}
+
+/// Link every node matching `output_match` into every node matching
+/// `input_match`, whenever either of them appears.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkRule {
+    pub output_match: NodeMatch,
+    pub input_match: NodeMatch,
+    pub strategy: LinkStrategy,
+}
+
+/// Whether `value` matches `pattern`, in which `*` stands for any
+/// sequence of characters.
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No wildcard at all
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+impl PipeWireObjects {
+    /// The links the rules ask for now that the `added` nodes
+    /// appeared, as `(output node, input node, strategy)`. Nodes that
+    /// are already linked are left out.
+    pub(crate) fn links_for_rules(
+        &self,
+        added: &[u32],
+    ) -> Vec<(u32, u32, LinkStrategy)> {
+        let mut links = vec![];
+        for (_, rule) in self.link_rules.iter() {
+            for &added_id in added {
+                let Some(added) =
+                    self.find_node_by_exact_id(added_id)
+                else {
+                    continue;
+                };
+                for other in self.nodes.iter() {
+                    if other.id == added_id {
+                        continue;
+                    }
+                    if rule.output_match.matches(added)
+                        && rule.input_match.matches(other)
+                    {
+                        links.push((
+                            added_id,
+                            other.id,
+                            rule.strategy,
+                        ));
+                    }
+                    if rule.input_match.matches(added)
+                        && rule.output_match.matches(other)
+                    {
+                        links.push((
+                            other.id,
+                            added_id,
+                            rule.strategy,
+                        ));
+                    }
+                }
+            }
+        }
+        let mut wanted = vec![];
+        for link in links {
+            if !self.is_linked(link.0, link.1)
+                && !wanted.iter().any(|other: &(u32, u32, _)| {
+                    (other.0, other.1) == (link.0, link.1)
+                })
+            {
+                wanted.push(link);
+            }
+        }
+        wanted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, LinkRule, LinkRuleId, NodeMatch};
+    use crate::link::Link;
+    use crate::node::{LinkStrategy, Node};
+    use crate::test_utils::{stereo_node, with_nodes};
+
+    fn classified(id: u32, name: &str, class: &str) -> Node {
+        let mut node = stereo_node(id, name, id * 10);
+        node.media_class = Some(class.to_owned());
+        node
+    }
+
+    #[test]
+    fn patterns_support_wildcards() {
+        assert!(glob_match("Firefox", "Firefox"));
+        assert!(!glob_match("Firefox", "Firefox Nightly"));
+        assert!(glob_match("alsa_output.*", "alsa_output.usb"));
+        assert!(glob_match(
+            "*.analog-*",
+            "alsa_output.analog-stereo"
+        ));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("alsa_*.monitor", "alsa_output.usb"));
+    }
+
+    #[test]
+    fn rules_link_the_nodes_that_appear() {
+        let mut objects = with_nodes([
+            classified(1, "speakers", "Audio/Sink"),
+            classified(2, "headset", "Audio/Sink"),
+        ]);
+        objects.link_rules.push((
+            LinkRuleId(0),
+            LinkRule {
+                output_match: NodeMatch::name("Firefox"),
+                input_match: NodeMatch {
+                    name: Some("speakers".to_owned()),
+                    media_class: Some("Audio/*".to_owned()),
+                    ..NodeMatch::default()
+                },
+                strategy: LinkStrategy::FrontOnly,
+            },
+        ));
+        objects.insert_node(classified(
+            3,
+            "Firefox",
+            "Stream/Output/Audio",
+        ));
+        objects.insert_node(classified(
+            4,
+            "mpv",
+            "Stream/Output/Audio",
+        ));
+        objects.update_nodes();
+
+        assert_eq!(
+            objects.links_for_rules(&[3, 4]),
+            vec![(3, 1, LinkStrategy::FrontOnly)]
+        );
+        // Nothing more to do once linked
+        objects.links.push(Link::from_parts(50, (3, 30), (1, 11)));
+        assert!(objects.links_for_rules(&[3]).is_empty());
+    }
+}
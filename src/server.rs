@@ -0,0 +1,21 @@
+use pipewire::core::Info;
+
+/// What the PipeWire server told about itself when connecting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub name: String,
+    pub version: String,
+    /// Random number identifying the server instance, it changes
+    /// whenever the daemon restarts.
+    pub cookie: u32,
+}
+
+impl From<&Info> for ServerInfo {
+    fn from(info: &Info) -> Self {
+        Self {
+            name: info.name().to_owned(),
+            version: info.version().to_owned(),
+            cookie: info.cookie(),
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use crate::link::{Link, LinkState};
+use crate::node::NodeInfo;
+use crate::objects::PipeWireObjects;
+use crate::port::{AudioChannel, Port, PortDirection};
+
+/// An owned copy of a [`Port`], with the id of its node.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct PortSnapshot {
+    pub id: u32,
+    pub node_id: u32,
+    pub name: String,
+    pub direction: PortDirection,
+    pub audio_channel: AudioChannel,
+    /// Left empty by snapshots saved before it was kept.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub alias: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub format_dsp: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub physical: bool,
+}
+
+impl PortSnapshot {
+    pub(crate) fn new(node_id: u32, port: &Port) -> Self {
+        Self {
+            id: port.id,
+            node_id,
+            name: port.name.clone(),
+            direction: port.direction.clone(),
+            audio_channel: port.audio_channel.clone(),
+            alias: port.alias.clone(),
+            format_dsp: port.format_dsp.clone(),
+            physical: port.physical,
+        }
+    }
+}
+
+/// An owned copy of a [`Link`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct LinkSnapshot {
+    pub id: u32,
+    pub output_node: u32,
+    pub output_port: u32,
+    pub input_node: u32,
+    pub input_port: u32,
+    pub state: LinkState,
+}
+
+impl LinkSnapshot {
+    /// The id and ports of the link, leaving out its state.
+    fn identity(&self) -> (u32, u32, u32) {
+        (self.id, self.output_port, self.input_port)
+    }
+}
+
+impl From<&Link> for LinkSnapshot {
+    fn from(link: &Link) -> Self {
+        Self {
+            id: link.id,
+            output_node: link.output_node,
+            output_port: link.output_port,
+            input_node: link.input_node,
+            input_port: link.input_port,
+            state: link.state.clone(),
+        }
+    }
+}
+
+/// The whole graph at a single moment, detached from the objects
+/// lock.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct GraphSnapshot {
+    pub nodes: Vec<NodeInfo>,
+    pub ports: Vec<PortSnapshot>,
+    pub links: Vec<LinkSnapshot>,
+}
+
+/// The nodes and links that appeared or disappeared between two
+/// snapshots, see [`GraphSnapshot::diff`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SnapshotDiff {
+    pub added_nodes: Vec<NodeInfo>,
+    pub removed_nodes: Vec<NodeInfo>,
+    pub added_links: Vec<LinkSnapshot>,
+    pub removed_links: Vec<LinkSnapshot>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.added_links.is_empty()
+            && self.removed_links.is_empty()
+    }
+}
+
+impl GraphSnapshot {
+    /// What changed from this snapshot to `newer`, each list in the
+    /// order of its snapshot. Nodes are told apart by id and links
+    /// by id and ports, so a node whose volume changed or a link that
+    /// became active is neither added nor removed.
+    pub fn diff(&self, newer: &Self) -> SnapshotDiff {
+        SnapshotDiff {
+            added_nodes: missing_from(
+                &newer.nodes,
+                &self.nodes,
+                |n| n.id,
+            ),
+            removed_nodes: missing_from(
+                &self.nodes,
+                &newer.nodes,
+                |n| n.id,
+            ),
+            added_links: missing_from(
+                &newer.links,
+                &self.links,
+                LinkSnapshot::identity,
+            ),
+            removed_links: missing_from(
+                &self.links,
+                &newer.links,
+                LinkSnapshot::identity,
+            ),
+        }
+    }
+}
+
+/// The items whose key is not found among the `others`, in linear
+/// time.
+fn missing_from<T: Clone, K: Eq + Hash>(
+    items: &[T],
+    others: &[T],
+    key: impl Fn(&T) -> K,
+) -> Vec<T> {
+    let known: HashSet<K> = others.iter().map(&key).collect();
+    items
+        .iter()
+        .filter(|item| !known.contains(&key(item)))
+        .cloned()
+        .collect()
+}
+
+#[cfg(feature = "serde")]
+impl GraphSnapshot {
+    /// Serialize the snapshot, e.g. to save it as a preset.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// Read back a snapshot saved with [`Self::to_json`].
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+impl PipeWireObjects {
+    /// Copy the nodes, their ports and the links.
+    /// Since the objects are only changed behind their lock, a
+    /// snapshot never mixes objects from different moments.
+    pub fn take_snapshot(&self) -> GraphSnapshot {
+        GraphSnapshot {
+            nodes: self.nodes.iter().map(NodeInfo::from).collect(),
+            ports: self
+                .nodes
+                .iter()
+                .flat_map(|node| {
+                    node.ports()
+                        .iter()
+                        .map(|port| PortSnapshot::new(node.id, port))
+                })
+                .collect(),
+            links: self
+                .links
+                .iter()
+                .map(LinkSnapshot::from)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::link::{Link, LinkState};
+    use crate::node::NodeInfo;
+    use crate::objects::PipeWireObjects;
+    use crate::sync::RwLock;
+    use crate::test_utils::{stereo_node, with_nodes};
+
+    #[test]
+    fn snapshots_never_hold_links_to_missing_nodes() {
+        let objects =
+            Arc::new(RwLock::new(with_nodes([stereo_node(
+                1, "sink", 10,
+            )])));
+        let writer_objects = objects.clone();
+        let writer = thread::spawn(move || {
+            for id in 100..300 {
+                let mut objects = writer_objects.write().unwrap();
+                objects.insert_node(stereo_node(
+                    id,
+                    "source",
+                    id * 10,
+                ));
+                objects.links.push(Link::from_parts(
+                    id + 1000,
+                    (id, id * 10),
+                    (1, 11),
+                ));
+                if id % 2 == 0 {
+                    objects
+                        .links
+                        .retain(|link| link.output_node != id - 1);
+                    objects.nodes.retain(|node| node.id != id - 1);
+                }
+            }
+        });
+
+        while !writer.is_finished() {
+            let snapshot = objects.read().unwrap().take_snapshot();
+            for link in snapshot.links.iter() {
+                for node_id in [link.output_node, link.input_node] {
+                    assert!(
+                        snapshot
+                            .nodes
+                            .iter()
+                            .any(|node| node.id == node_id),
+                        "Link {} references missing node {node_id}",
+                        link.id
+                    );
+                }
+            }
+            assert!(snapshot.ports.iter().all(|port| snapshot
+                .nodes
+                .iter()
+                .any(|node| node.id == port.node_id)));
+        }
+        writer.join().unwrap();
+        let snapshot = objects.read().unwrap().take_snapshot();
+        assert_eq!(snapshot.nodes.len(), snapshot.links.len() + 1);
+        assert_eq!(snapshot.ports.len(), snapshot.nodes.len() * 4);
+    }
+
+    #[test]
+    fn diffs_report_added_and_removed_objects() {
+        let mut objects = PipeWireObjects {
+            links: vec![Link::from_parts(100, (2, 20), (1, 11))],
+            ..with_nodes([
+                stereo_node(1, "sink", 10),
+                stereo_node(2, "source", 20),
+            ])
+        };
+        let before = objects.take_snapshot();
+
+        objects.nodes[0].volume = Some(0.5);
+        objects.links[0].state = LinkState::Active;
+        assert!(before.diff(&objects.take_snapshot()).is_empty());
+
+        objects.nodes.remove(1);
+        objects.insert_node(stereo_node(3, "recorder", 30));
+        objects.links.push(Link::from_parts(101, (1, 10), (3, 31)));
+        let after = objects.take_snapshot();
+        let diff = before.diff(&after);
+
+        let ids = |nodes: &[NodeInfo]| -> Vec<u32> {
+            nodes.iter().map(|node| node.id).collect()
+        };
+        assert_eq!(ids(&diff.added_nodes), vec![3]);
+        assert_eq!(ids(&diff.removed_nodes), vec![2]);
+        assert_eq!(diff.added_links, vec![after.links[1].clone()]);
+        assert!(diff.removed_links.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshots_round_trip_through_json() {
+        use crate::node::NodeState;
+
+        use super::GraphSnapshot;
+
+        let mut source = stereo_node(2, "source", 20);
+        source.state = Some(NodeState::Error("busy".to_owned()));
+        let mut objects = PipeWireObjects {
+            links: vec![Link::from_parts(100, (2, 20), (1, 11))],
+            ..with_nodes([stereo_node(1, "sink", 10), source])
+        };
+        objects.links[0].state = LinkState::Active;
+        let snapshot = objects.take_snapshot();
+
+        let json = snapshot.to_json().unwrap();
+        assert_eq!(
+            GraphSnapshot::from_json(&json).unwrap(),
+            snapshot
+        );
+        assert!(GraphSnapshot::from_json("{").is_err());
+    }
+}
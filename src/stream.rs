@@ -0,0 +1,111 @@
+//! Async access to the events of the manager, behind the `async`
+//! feature.
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use futures::channel::mpsc::{
+    channel, unbounded, Receiver, Sender, UnboundedReceiver,
+    UnboundedSender,
+};
+
+use crate::event::{ConnectorEvent, Event};
+
+/// A bounded stream, along with the events it missed since it last
+/// received one.
+struct BoundedSubscriber {
+    sender: Sender<Event>,
+    /// Sequence number of the first missed event and how many were
+    /// missed.
+    lagged: Option<(u64, u64)>,
+}
+
+impl BoundedSubscriber {
+    /// Send the event, or count it as missed if the stream is full.
+    /// Returns whether the stream is still open.
+    fn send(&mut self, event: Event) -> bool {
+        if let Some((seq, skipped)) = self.lagged {
+            let lagged = Event {
+                seq,
+                kind: ConnectorEvent::Lagged(skipped),
+            };
+            match self.sender.try_send(lagged) {
+                Ok(()) => self.lagged = None,
+                Err(e) if e.is_full() => {
+                    self.lagged = Some((seq, skipped + 1));
+                    return true;
+                }
+                Err(_) => return false,
+            }
+        }
+        let seq = event.seq;
+        match self.sender.try_send(event) {
+            Ok(()) => true,
+            Err(e) if e.is_full() => {
+                self.lagged = Some((seq, 1));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Copies the events sent to the manager to every open stream.
+pub(crate) struct EventStreams {
+    subscribers: Arc<Mutex<Vec<UnboundedSender<Event>>>>,
+    bounded: Arc<Mutex<Vec<BoundedSubscriber>>>,
+}
+
+impl EventStreams {
+    /// Put a forwarding thread in front of the manager, returning
+    /// the sender the backend should use instead.
+    pub(crate) fn forward(
+        manager: mpsc::Sender<Event>,
+    ) -> (mpsc::Sender<Event>, Self) {
+        let (sender, receiver) = mpsc::channel::<Event>();
+        let subscribers: Arc<Mutex<Vec<UnboundedSender<_>>>> =
+            Arc::default();
+        let bounded: Arc<Mutex<Vec<BoundedSubscriber>>> =
+            Arc::default();
+        let forwarded = subscribers.clone();
+        let forwarded_bounded = bounded.clone();
+        thread::spawn(move || {
+            for event in receiver {
+                // Streams that were dropped are forgotten
+                forwarded.lock().unwrap().retain(|subscriber| {
+                    subscriber.unbounded_send(event.clone()).is_ok()
+                });
+                forwarded_bounded.lock().unwrap().retain_mut(
+                    |subscriber| subscriber.send(event.clone()),
+                );
+                let _result = manager.send(event);
+            }
+        });
+        (
+            sender,
+            Self {
+                subscribers,
+                bounded,
+            },
+        )
+    }
+
+    pub(crate) fn subscribe(&self) -> UnboundedReceiver<Event> {
+        let (sender, receiver) = unbounded();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// A stream holding at most `capacity` events, at least one.
+    pub(crate) fn subscribe_bounded(
+        &self,
+        capacity: usize,
+    ) -> Receiver<Event> {
+        // Every sender gets a slot of its own on top of the buffer
+        let (sender, receiver) = channel(capacity.saturating_sub(1));
+        self.bounded.lock().unwrap().push(BoundedSubscriber {
+            sender,
+            lagged: None,
+        });
+        receiver
+    }
+}
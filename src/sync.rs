@@ -0,0 +1,163 @@
+//! The lock guarding the objects shared with the backend thread.
+//!
+//! [`RwLock`] has the API of [`std::sync::RwLock`] and wraps it by
+//! default: a thread panicking while holding it poisons it, and
+//! every later access panics as well, so a single bug on the
+//! backend thread takes the whole manager down with it.
+//!
+//! The `parking_lot` feature makes it wrap `parking_lot::RwLock`
+//! instead, which is never poisoned and is cheaper to lock, at the
+//! price of an extra dependency and of reading objects a panicking
+//! writer may have left half updated. The types and signatures are
+//! the same with either lock, so code locking it (such as
+//! [`crate::manager::PipeWireManager::get_objects`] users) keeps
+//! compiling when another crate turns the feature on.
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+#[cfg(not(feature = "parking_lot"))]
+use std::sync::PoisonError;
+use std::sync::{LockResult, TryLockError, TryLockResult};
+
+#[cfg(feature = "parking_lot")]
+use parking_lot as imp;
+#[cfg(not(feature = "parking_lot"))]
+use std::sync as imp;
+
+/// A reader-writer lock with the API of [`std::sync::RwLock`].
+#[derive(Debug, Default)]
+pub struct RwLock<T: ?Sized>(imp::RwLock<T>);
+
+/// Shared access to the value of a [`RwLock`], released on drop.
+pub struct RwLockReadGuard<'a, T: ?Sized>(
+    imp::RwLockReadGuard<'a, T>,
+);
+
+/// Exclusive access to the value of a [`RwLock`], released on drop.
+pub struct RwLockWriteGuard<'a, T: ?Sized>(
+    imp::RwLockWriteGuard<'a, T>,
+);
+
+impl<T> RwLock<T> {
+    pub fn new(value: T) -> Self {
+        Self(imp::RwLock::new(value))
+    }
+}
+
+#[cfg(not(feature = "parking_lot"))]
+impl<T: ?Sized> RwLock<T> {
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        wrap_guard(self.0.read(), RwLockReadGuard)
+    }
+
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        wrap_guard(self.0.write(), RwLockWriteGuard)
+    }
+
+    pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        self.0.try_read().map(RwLockReadGuard).map_err(|e| match e {
+            TryLockError::Poisoned(e) => TryLockError::Poisoned(
+                PoisonError::new(RwLockReadGuard(e.into_inner())),
+            ),
+            TryLockError::WouldBlock => TryLockError::WouldBlock,
+        })
+    }
+}
+
+/// Wrap the guard of a lock result, poisoned or not.
+#[cfg(not(feature = "parking_lot"))]
+fn wrap_guard<G, W>(
+    result: LockResult<G>,
+    wrap: fn(G) -> W,
+) -> LockResult<W> {
+    result
+        .map(wrap)
+        .map_err(|e| PoisonError::new(wrap(e.into_inner())))
+}
+
+/// Locking never fails, as `parking_lot` locks are not poisoned.
+#[cfg(feature = "parking_lot")]
+impl<T: ?Sized> RwLock<T> {
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        Ok(RwLockReadGuard(self.0.read()))
+    }
+
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        Ok(RwLockWriteGuard(self.0.write()))
+    }
+
+    pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        self.0
+            .try_read()
+            .map(RwLockReadGuard)
+            .ok_or(TryLockError::WouldBlock)
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::RwLock;
+
+    #[cfg(not(feature = "parking_lot"))]
+    #[test]
+    fn std_lock_is_poisoned_by_a_panicking_writer() {
+        let lock = Arc::new(RwLock::new(0));
+        let writer = lock.clone();
+        let _ = thread::spawn(move || {
+            let _guard = writer.write().unwrap();
+            panic!("writer panicked");
+        })
+        .join();
+        assert!(lock.read().is_err());
+    }
+
+    #[cfg(feature = "parking_lot")]
+    #[test]
+    fn parking_lot_lock_survives_a_panicking_writer() {
+        let lock = Arc::new(RwLock::new(0));
+        let writer = lock.clone();
+        let _ = thread::spawn(move || {
+            let mut guard = writer.write().unwrap();
+            *guard = 1;
+            panic!("writer panicked");
+        })
+        .join();
+        assert_eq!(*lock.read().unwrap(), 1);
+    }
+}
@@ -0,0 +1,141 @@
+//! Helpers to build objects from synthetic registry globals, so the
+//! graph logic can be exercised without a running PipeWire daemon.
+use libspa::utils::dict::DictRef;
+use pipewire::permissions::PermissionFlags;
+use pipewire::properties::{properties, Properties};
+use pipewire::registry::GlobalObject;
+use pipewire::types::ObjectType;
+
+use std::sync::Mutex;
+
+use crate::link::Link;
+use crate::node::Node;
+use crate::objects::PipeWireObjects;
+use crate::port::{Port, PortDirection};
+
+/// Held by the tests changing the process-wide logging settings.
+pub static LOGGING: Mutex<()> = Mutex::new(());
+
+pub fn global(
+    id: u32,
+    type_: ObjectType,
+    props: &Properties,
+) -> GlobalObject<&DictRef> {
+    GlobalObject {
+        id,
+        permissions: PermissionFlags::all(),
+        type_,
+        version: 3,
+        props: Some(props.dict()),
+    }
+}
+
+impl Node {
+    /// A node as announced by the registry, without a media class.
+    pub fn from_parts(id: u32, name: &str) -> Self {
+        let props = properties! {
+            "node.name" => name,
+            "object.serial" => id.to_string(),
+        };
+        Node::new(&global(id, ObjectType::Node, &props)).unwrap()
+    }
+}
+
+impl Port {
+    /// A mono float audio port of the node.
+    pub fn from_parts(
+        id: u32,
+        node_id: u32,
+        name: &str,
+        direction: PortDirection,
+        channel: &str,
+    ) -> Self {
+        Self::from_parts_with_format(
+            id,
+            node_id,
+            name,
+            direction,
+            channel,
+            "32 bit float mono audio",
+        )
+    }
+
+    /// Same as [`Port::from_parts`], with the given `format.dsp`.
+    pub fn from_parts_with_format(
+        id: u32,
+        node_id: u32,
+        name: &str,
+        direction: PortDirection,
+        channel: &str,
+        format: &str,
+    ) -> Self {
+        let direction = match direction {
+            PortDirection::In => "in",
+            PortDirection::Out => "out",
+        };
+        let props = properties! {
+            "port.name" => name,
+            "port.direction" => direction,
+            "port.alias" => format!("alias:{name}"),
+            "port.group" => "stream.0",
+            "object.serial" => id.to_string(),
+            "object.path" => format!("test:{node_id}:{name}"),
+            "node.id" => node_id.to_string(),
+            "audio.channel" => channel,
+            "format.dsp" => format,
+        };
+        Port::new(&global(id, ObjectType::Port, &props))
+    }
+}
+
+impl Link {
+    /// `output` and `input` are `(node, port)` pairs.
+    pub fn from_parts(
+        id: u32,
+        output: (u32, u32),
+        input: (u32, u32),
+    ) -> Self {
+        let props = properties! {
+            "link.output.node" => output.0.to_string(),
+            "link.output.port" => output.1.to_string(),
+            "link.input.node" => input.0.to_string(),
+            "link.input.port" => input.1.to_string(),
+        };
+        Link::new(&global(id, ObjectType::Link, &props)).unwrap()
+    }
+}
+
+/// Objects holding the nodes, indexed as if they were announced.
+pub fn with_nodes(
+    nodes: impl IntoIterator<Item = Node>,
+) -> PipeWireObjects {
+    let mut objects = PipeWireObjects::default();
+    for node in nodes {
+        objects.insert_node(node);
+    }
+    objects
+}
+
+/// A node with an output and an input port for both FL and FR,
+/// named after PipeWire's usual `monitor_*`/`playback_*` convention.
+pub fn stereo_node(id: u32, name: &str, first_port: u32) -> Node {
+    let mut node = Node::from_parts(id, name);
+    for (offset, channel) in ["FL", "FR"].iter().enumerate() {
+        let offset = offset as u32;
+        node.add_port(Port::from_parts(
+            first_port + offset * 2,
+            id,
+            &format!("monitor_{channel}"),
+            PortDirection::Out,
+            channel,
+        ));
+        node.add_port(Port::from_parts(
+            first_port + offset * 2 + 1,
+            id,
+            &format!("playback_{channel}"),
+            PortDirection::In,
+            channel,
+        ));
+    }
+    node
+}
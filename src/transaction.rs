@@ -0,0 +1,47 @@
+//! Operations applied together, see
+//! [`crate::manager::PipeWireManager::transaction`].
+use std::cell::RefCell;
+
+use crate::event::PipeWireEvent;
+use crate::node::LinkStrategy;
+
+/// Collects the operations of a transaction, which are only sent to
+/// the backend once the transaction closure returns.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    operations: RefCell<Vec<PipeWireEvent>>,
+}
+
+impl Transaction {
+    /// Link two nodes, pairing their ports as described by the
+    /// default [`LinkStrategy`].
+    pub fn link(&self, out_node: u32, in_node: u32) {
+        self.link_with_strategy(
+            out_node,
+            in_node,
+            LinkStrategy::default(),
+        );
+    }
+
+    pub fn link_with_strategy(
+        &self,
+        out_node: u32,
+        in_node: u32,
+        strategy: LinkStrategy,
+    ) {
+        self.operations.borrow_mut().push(
+            PipeWireEvent::LinkCommand(out_node, in_node, strategy),
+        );
+    }
+
+    /// Remove every link between two nodes.
+    pub fn unlink(&self, out_node: u32, in_node: u32) {
+        self.operations
+            .borrow_mut()
+            .push(PipeWireEvent::UnlinkCommand(out_node, in_node));
+    }
+
+    pub(crate) fn into_operations(self) -> Vec<PipeWireEvent> {
+        self.operations.into_inner()
+    }
+}
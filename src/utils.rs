@@ -1,7 +1,41 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use libspa::utils::dict::DictRef;
+use log::{Level, LevelFilter};
 
 pub const UNKNOWN_STR: &str = "unknown";
 
+/// Target of the logs about the objects the server announces and
+/// removes.
+pub const DISCOVERY_TARGET: &str = "easy_pw::discovery";
+
+static DISCOVERY_LOG_LEVEL: AtomicUsize =
+    AtomicUsize::new(LevelFilter::Trace as usize);
+
+/// Most verbose level logged about discovered objects, on top of the
+/// global `log` level. It is shared by every manager of the process.
+pub fn set_discovery_log_level(level: LevelFilter) {
+    DISCOVERY_LOG_LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+pub fn discovery_log_enabled(level: Level) -> bool {
+    level as usize <= DISCOVERY_LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+/// `log::debug!` for the discovery path, dropped when the discovery
+/// log level is below debug.
+macro_rules! discovery_debug {
+    ($($arg:tt)+) => {
+        if $crate::utils::discovery_log_enabled(log::Level::Debug) {
+            log_debug!(
+                target: $crate::utils::DISCOVERY_TARGET,
+                $($arg)+
+            );
+        }
+    };
+}
+pub(crate) use discovery_debug;
+
 pub fn val(dict: &DictRef, key: &str) -> String {
     let value = dict.get(key);
 
@@ -11,10 +45,104 @@ pub fn val(dict: &DictRef, key: &str) -> String {
     value.unwrap().to_string()
 }
 
-pub fn val_or(dict: &DictRef, key: &str, default: &str) -> String {
-    dict.get(key).unwrap_or(default).to_string()
+/// Make an untrusted label safe to display: control characters
+/// become spaces, and whitespace is collapsed and trimmed.
+pub fn sanitize(label: &str) -> String {
+    label
+        .split(|c: char| c.is_control() || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Switch the calling thread to `SCHED_FIFO` at `priority`, clamped
+/// to the range the system allows. Fails without the needed
+/// privileges (`CAP_SYS_NICE` or an `RLIMIT_RTPRIO` limit, as set up
+/// for the PipeWire RT module).
+pub fn set_realtime_priority(priority: i32) -> std::io::Result<()> {
+    let policy = libc::SCHED_FIFO;
+    // SAFETY: these only read and change the scheduling of the
+    // calling thread.
+    let result = unsafe {
+        let min = libc::sched_get_priority_min(policy);
+        let max = libc::sched_get_priority_max(policy);
+        let param = libc::sched_param {
+            sched_priority: priority.clamp(min, max),
+        };
+        libc::pthread_setschedparam(
+            libc::pthread_self(),
+            policy,
+            &param,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        errno => Err(std::io::Error::from_raw_os_error(errno)),
+    }
 }
 
 pub fn val_opt(dict: &DictRef, key: &str) -> Option<String> {
     dict.get(key).map(|s| s.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::thread::{self, ThreadId};
+
+    use log::{LevelFilter, Log, Metadata, Record};
+
+    use super::{set_discovery_log_level, DISCOVERY_TARGET};
+    use crate::node::Node;
+    use crate::test_utils::LOGGING;
+
+    /// Keeps the targets of the records logged by each thread, as
+    /// the tests run in parallel.
+    struct Capture(Mutex<Vec<(ThreadId, String)>>);
+
+    impl Log for Capture {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push((
+                thread::current().id(),
+                record.target().to_string(),
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+    fn discovery_logs() -> usize {
+        let id = thread::current().id();
+        CAPTURE
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(thread, target)| {
+                *thread == id && target == DISCOVERY_TARGET
+            })
+            .count()
+    }
+
+    #[test]
+    fn discovery_logs_follow_their_own_level() {
+        let _logging = LOGGING.lock().unwrap();
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(LevelFilter::Trace);
+
+        set_discovery_log_level(LevelFilter::Info);
+        drop(Node::from_parts(1, "silenced"));
+        assert_eq!(discovery_logs(), 0);
+
+        set_discovery_log_level(LevelFilter::Trace);
+        drop(Node::from_parts(2, "logged"));
+        // Its creation and its removal.
+        assert_eq!(discovery_logs(), 2);
+    }
+}